@@ -0,0 +1,133 @@
+use chan_core::chan_model::scorer::BspScorer;
+use chan_core::common::cenum::DataField;
+use chan_core::common::time::Time;
+use chan_core::trade::optimize::KlData;
+use chan_core::{Analyzer, ChanConfig};
+use chrono::NaiveDateTime;
+use csv::Reader;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct CsvRecord {
+    pub timestamp: NaiveDateTime,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+/// 目录下的全部csv文件，按文件名排序，保证输出顺序稳定
+pub fn list_csv_files(data_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(data_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("csv") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// 文件名去掉扩展名作为标的代码
+pub fn symbol_of(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+pub fn read_csv_file(path: &Path) -> Result<Vec<CsvRecord>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let mut rdr = Reader::from_reader(file);
+    let mut records = Vec::new();
+    for result in rdr.records() {
+        records.push(parse_csv_record(&result?)?);
+    }
+    records.sort_by_key(|r| r.timestamp);
+    Ok(records)
+}
+
+/// 读入一个csv文件，转成KLineUnit所需的字段，参数优化时所有组合共用
+pub fn read_kl_data(path: &Path) -> Result<Vec<KlData>, Box<dyn Error>> {
+    Ok(read_csv_file(path)?
+        .iter()
+        .map(|record| KlData {
+            time: Time::new(record.timestamp.and_utc().timestamp()),
+            kl_dict: HashMap::from([
+                (DataField::FieldOpen, record.open),
+                (DataField::FieldHigh, record.high),
+                (DataField::FieldLow, record.low),
+                (DataField::FieldClose, record.close),
+                (DataField::FieldVolume, record.volume),
+            ]),
+        })
+        .collect())
+}
+
+/// 用已读入的K线跑完整个分析，scorer不为空时给买卖点打分
+pub fn run_analyzer(
+    symbol: &str,
+    bars: &[KlData],
+    config: ChanConfig,
+    scorer: Option<Arc<dyn BspScorer>>,
+) -> Result<Analyzer, Box<dyn Error>> {
+    let mut analyzer = Analyzer::new(symbol.to_string(), config)?;
+    if let Some(scorer) = scorer {
+        analyzer.set_bsp_scorer(scorer);
+    }
+    for bar in bars {
+        analyzer.add_kl_data(&bar.kl_dict, bar.time, true)?;
+    }
+    if !analyzer.step_calculation {
+        analyzer.cal_seg_and_zs()?;
+    }
+    Ok(analyzer)
+}
+
+/// 逐K线跑分析，每根K线算完后调用on_step，此时只能看到这根K线及之前的结果
+pub fn run_analyzer_step<F: FnMut(&Analyzer)>(
+    path: &Path,
+    config: ChanConfig,
+    scorer: Option<Arc<dyn BspScorer>>,
+    mut on_step: F,
+) -> Result<Analyzer, Box<dyn Error>> {
+    let bars = read_kl_data(path)?;
+    let mut analyzer = Analyzer::new(symbol_of(path), config)?;
+    analyzer.step_calculation = true;
+    if let Some(scorer) = scorer {
+        analyzer.set_bsp_scorer(scorer);
+    }
+    for bar in &bars {
+        analyzer.add_kl_data(&bar.kl_dict, bar.time, true)?;
+        on_step(&analyzer);
+    }
+    Ok(analyzer)
+}
+
+/// 从json文件读取ChanConfig，未指定时使用默认配置
+pub fn load_config(path: Option<&str>) -> Result<ChanConfig, Box<dyn Error>> {
+    let conf = match path {
+        Some(path) => Some(serde_json::from_reader(File::open(path)?)?),
+        None => None,
+    };
+    Ok(ChanConfig::new(conf)?)
+}
+
+fn parse_csv_record(record: &csv::StringRecord) -> Result<CsvRecord, Box<dyn Error>> {
+    let timestamp = NaiveDateTime::parse_from_str(&record[0], "%Y-%m-%d %H:%M:%S")?;
+
+    Ok(CsvRecord {
+        timestamp,
+        open: record[1].parse()?,
+        high: record[2].parse()?,
+        low: record[3].parse()?,
+        close: record[4].parse()?,
+        volume: record[5].parse()?,
+    })
+}
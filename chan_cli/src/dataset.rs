@@ -0,0 +1,300 @@
+use chan_core::chan_model::dataset::{BspCollector, BspDataset, META_COLUMNS};
+use chan_core::chan_model::labeler::BspLabeler;
+use chan_core::chan_model::scorer::{load_scorer, ScorerOption, ScorerType};
+#[cfg(feature = "parquet")]
+use polars::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::data::{list_csv_files, load_config, run_analyzer_step, symbol_of};
+
+/// csv中缺失值的写法
+const CSV_MISSING: &str = "NA";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DatasetFormat {
+    Csv,
+    #[cfg(feature = "parquet")]
+    Parquet,
+    LibSvm,
+}
+
+impl DatasetFormat {
+    fn parse(s: &str) -> Result<Self, Box<dyn Error>> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(Self::Parquet),
+            #[cfg(not(feature = "parquet"))]
+            "parquet" => Err("parquet format requires chan_cli built with --features parquet".into()),
+            "libsvm" => Ok(Self::LibSvm),
+            _ => Err(format!("unknown dataset format: {}", s).into()),
+        }
+    }
+}
+
+pub const USAGE: &str = "chan_cli dataset --data-dir <dir> --output <path> \
+[--format csv|parquet|libsvm] [--config <chan_config.json>] [--columns <file>] [--label <name>] \
+[--model <model.json> --model-type lr|xgboost|lightgbm [--base-score <p>] [--fmap <file>]] [--min-score <p>]";
+
+/// `chan_cli dataset`：对目录下每个标的逐K线跑分析，每个买卖点输出一行特征和标签
+///
+/// 特征取买卖点第一次出现时的值，与实盘能看到的一致；
+/// --columns指定特征列文件（每行一个特征名），不指定时取全部特征的并集；
+/// libsvm格式用--label选择目标列（默认hit_first），没有该标签的买卖点不输出；
+/// 指定--model时给每个买卖点打分，--min-score只导出打分不低于该值的买卖点；
+/// xgboost模型可用--base-score覆盖模型里的base_score，用--fmap（导出libsvm时生成）还原`fN`特征名
+pub fn run(flags: &HashMap<String, String>) -> Result<(), Box<dyn Error>> {
+    let data_dir = flags.get("data-dir").ok_or(USAGE)?;
+    let output = flags.get("output").ok_or(USAGE)?;
+    let format = DatasetFormat::parse(flags.get("format").map_or("csv", String::as_str))?;
+    let config = load_config(flags.get("config").map(String::as_str))?;
+
+    let mut dataset = match flags.get("columns") {
+        Some(path) => BspDataset::with_feature_columns(
+            std::fs::read_to_string(path)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect(),
+        ),
+        None => BspDataset::new(),
+    };
+
+    let scorer = match flags.get("model") {
+        Some(model) => {
+            let scorer_type: ScorerType = flags.get("model-type").map_or("lr", String::as_str).parse()?;
+            let option = ScorerOption {
+                base_score: flags.get("base-score").map(|p| p.parse()).transpose()?,
+                fmap: flags.get("fmap").cloned(),
+            };
+            Some(load_scorer(model, scorer_type, &option)?)
+        }
+        None => None,
+    };
+
+    let labeler = BspLabeler::default();
+    for path in list_csv_files(Path::new(data_dir))? {
+        println!("Processing file: {:?}", path);
+        let symbol = symbol_of(&path);
+        let mut collector = BspCollector::new();
+        // 特征取买卖点第一次出现时的值，标签在analyzer跑完之后再打
+        let _analyzer = run_analyzer_step(&path, config.clone(), scorer.clone(), |analyzer| {
+            collector.collect(&symbol, &analyzer.bs_point_lst, false);
+            collector.collect(&symbol, &analyzer.seg_bs_point_lst, true);
+        })?;
+        collector.finish(&labeler, &mut dataset);
+    }
+    if let Some(min_score) = flags.get("min-score") {
+        dataset.retain_min_score(min_score.parse()?);
+    }
+
+    let output = Path::new(output);
+    match format {
+        DatasetFormat::Csv => write_csv(&dataset, output)?,
+        #[cfg(feature = "parquet")]
+        DatasetFormat::Parquet => write_parquet(&dataset, output)?,
+        DatasetFormat::LibSvm => {
+            let label = flags.get("label").map_or("hit_first", String::as_str);
+            write_libsvm(&dataset, label, output)?
+        }
+    }
+    println!("Saved {} bsp to {}", dataset.len(), output.display());
+    Ok(())
+}
+
+pub fn write_csv(dataset: &BspDataset, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    wtr.write_record(dataset.header())?;
+    let fmt_value = |v: &Option<f64>| v.map_or(CSV_MISSING.to_string(), |v| v.to_string());
+    for row in dataset.rows() {
+        let record = row.record;
+        let mut line = vec![
+            record.symbol.clone(),
+            record.time.clone(),
+            record.bs_type.clone(),
+            record.is_buy.to_string(),
+            record.is_segbsp.to_string(),
+            fmt_value(&record.score),
+        ];
+        line.extend(row.features.iter().map(fmt_value));
+        line.extend(row.labels.iter().map(fmt_value));
+        wtr.write_record(&line)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// 缺失值写为parquet的null
+#[cfg(feature = "parquet")]
+pub fn write_parquet(dataset: &BspDataset, path: &Path) -> Result<(), Box<dyn Error>> {
+    let header = dataset.header();
+    let rows: Vec<_> = dataset.rows().collect();
+    let mut columns = vec![
+        Series::new(META_COLUMNS[0], rows.iter().map(|r| r.record.symbol.as_str()).collect::<Vec<_>>()),
+        Series::new(META_COLUMNS[1], rows.iter().map(|r| r.record.time.as_str()).collect::<Vec<_>>()),
+        Series::new(META_COLUMNS[2], rows.iter().map(|r| r.record.bs_type.as_str()).collect::<Vec<_>>()),
+        Series::new(META_COLUMNS[3], rows.iter().map(|r| r.record.is_buy).collect::<Vec<_>>()),
+        Series::new(META_COLUMNS[4], rows.iter().map(|r| r.record.is_segbsp).collect::<Vec<_>>()),
+        Series::new(META_COLUMNS[5], rows.iter().map(|r| r.record.score).collect::<Vec<_>>()),
+    ];
+    let feature_cnt = dataset.feature_columns().len();
+    for (col_idx, name) in header[META_COLUMNS.len()..].iter().enumerate() {
+        let values: Vec<Option<f64>> = if col_idx < feature_cnt {
+            rows.iter().map(|r| r.features[col_idx]).collect()
+        } else {
+            rows.iter().map(|r| r.labels[col_idx - feature_cnt]).collect()
+        };
+        columns.push(Series::new(name, values));
+    }
+    let mut df = DataFrame::new(columns)?;
+    ParquetWriter::new(File::create(path)?).finish(&mut df)?;
+    Ok(())
+}
+
+/// 每行`<label> <idx>:<value> ...`，缺失的特征不写（稀疏），idx从1开始；
+/// 1、2固定为is_buy、is_segbsp，下标与特征名的对应关系写入同名的`.fmap`文件（XGBoost的fmap格式），
+/// 训练出的模型用`--fmap`加载即可把`fN`还原为特征名
+pub fn write_libsvm(dataset: &BspDataset, label: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let feature_columns = dataset.feature_columns();
+    let label_idx = dataset
+        .label_columns()
+        .iter()
+        .position(|col| col == label)
+        .ok_or_else(|| format!("label {} not found in dataset", label))?;
+
+    // fmap要求下标从0连续，0号占位不出现在数据里
+    let mut fmap_wtr = BufWriter::new(File::create(path.with_extension("fmap"))?);
+    writeln!(fmap_wtr, "0\t_unused\tq")?;
+    for (idx, (name, kind)) in ["is_buy", "is_segbsp"]
+        .into_iter()
+        .map(|name| (name, "i"))
+        .chain(feature_columns.iter().map(|name| (name.as_str(), "q")))
+        .enumerate()
+    {
+        writeln!(fmap_wtr, "{}\t{}\t{}", idx + 1, name, kind)?;
+    }
+    fmap_wtr.flush()?;
+
+    let mut wtr = BufWriter::new(File::create(path)?);
+    for row in dataset.rows() {
+        let Some(y) = row.labels[label_idx] else {
+            continue;
+        };
+        write!(wtr, "{} 1:{} 2:{}", y, row.record.is_buy as u8, row.record.is_segbsp as u8)?;
+        for (idx, value) in row.features.iter().enumerate() {
+            if let Some(value) = value {
+                write!(wtr, " {}:{}", idx + 3, value)?;
+            }
+        }
+        writeln!(wtr)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chan_core::chan_model::dataset::BspRecord;
+    use chan_core::chan_model::features::Features;
+    use chan_core::chan_model::scorer::parse_fmap;
+
+    fn to_features(items: &[(&str, f64)]) -> Features {
+        let mut res = Features::default();
+        res.add_feat(items.iter().map(|(k, v)| (k.to_string(), *v)));
+        res
+    }
+
+    /// 第二行缺少特征b，第三行缺少标签
+    fn dataset() -> BspDataset {
+        let record = |time: &str, is_buy: bool, features: &[(&str, f64)], labels: &[(&str, f64)]| BspRecord {
+            symbol: "sz.000001".to_string(),
+            time: time.to_string(),
+            bs_type: "1,2".to_string(),
+            is_buy,
+            is_segbsp: !is_buy,
+            score: Some(0.5),
+            features: to_features(features),
+            labels: to_features(labels),
+        };
+        let mut dataset = BspDataset::new();
+        dataset.records.push(record("2024/01/02", true, &[("a", 1.5), ("b", -2.0)], &[("hit_first", 1.0)]));
+        dataset.records.push(record("2024/01/03", false, &[("a", 0.25)], &[("hit_first", -1.0)]));
+        dataset.records.push(record("2024/01/04", true, &[("b", 3.0)], &[]));
+        dataset
+    }
+
+    fn tmp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("chan_dataset_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let dataset = dataset();
+        let path = tmp_path("round_trip.csv");
+        write_csv(&dataset, &path).unwrap();
+
+        let mut rdr = csv::Reader::from_path(&path).unwrap();
+        let header: Vec<String> = rdr.headers().unwrap().iter().map(String::from).collect();
+        assert_eq!(header, dataset.header());
+        let rows: Vec<Vec<String>> = rdr
+            .records()
+            .map(|record| record.unwrap().iter().map(String::from).collect())
+            .collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], ["sz.000001", "2024/01/02", "1,2", "true", "false", "0.5", "1.5", "-2", "1"]);
+        assert_eq!(rows[1][6..], ["0.25", CSV_MISSING, "-1"]);
+        assert_eq!(rows[2][6..], [CSV_MISSING, "3", CSV_MISSING]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    #[test]
+    fn test_parquet_needs_feature() {
+        assert!(DatasetFormat::parse("parquet").is_err());
+        assert_eq!(DatasetFormat::parse("csv").unwrap(), DatasetFormat::Csv);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_round_trip() {
+        let dataset = dataset();
+        let path = tmp_path("round_trip.parquet");
+        write_parquet(&dataset, &path).unwrap();
+
+        let df = ParquetReader::new(File::open(&path).unwrap()).finish().unwrap();
+        assert_eq!(df.get_column_names(), dataset.header());
+        assert_eq!(df.height(), 3);
+        let a: Vec<Option<f64>> = df.column("a").unwrap().f64().unwrap().into_iter().collect();
+        assert_eq!(a, vec![Some(1.5), Some(0.25), None]);
+        let label: Vec<Option<f64>> = df.column("label_hit_first").unwrap().f64().unwrap().into_iter().collect();
+        assert_eq!(label, vec![Some(1.0), Some(-1.0), None]);
+        let is_buy: Vec<Option<bool>> = df.column("is_buy").unwrap().bool().unwrap().into_iter().collect();
+        assert_eq!(is_buy, vec![Some(true), Some(false), Some(true)]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_libsvm_round_trip() {
+        let dataset = dataset();
+        let path = tmp_path("round_trip.libsvm");
+        write_libsvm(&dataset, "hit_first", &path).unwrap();
+
+        // 没有标签的第三行不输出，缺失的特征不写
+        let lines: Vec<String> = std::fs::read_to_string(&path).unwrap().lines().map(String::from).collect();
+        assert_eq!(lines, ["1 1:1 2:0 3:1.5 4:-2", "-1 1:0 2:1 3:0.25"]);
+        let fmap = std::fs::read_to_string(path.with_extension("fmap")).unwrap();
+        assert_eq!(fmap, "0\t_unused\tq\n1\tis_buy\ti\n2\tis_segbsp\ti\n3\ta\tq\n4\tb\tq\n");
+        let names = parse_fmap(&fmap).unwrap();
+        assert_eq!((names["f3"].as_str(), names["f4"].as_str()), ("a", "b"));
+
+        assert!(write_libsvm(&dataset, "ret_5", &path).is_err());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(path.with_extension("fmap")).unwrap();
+    }
+}
@@ -1,94 +1,57 @@
-use chan_core::analyzer::analyzer::Analyzer;
-use chan_core::kline::kline_unit::KLineUnit;
-use chrono::NaiveDateTime;
-use csv::Reader;
+mod data;
+mod dataset;
+mod scan;
+mod sweep;
+
+use std::collections::HashMap;
 use std::error::Error;
-use std::fs::File;
 use std::path::Path;
 
-#[derive(Debug)]
-struct CsvRecord {
-    timestamp: NaiveDateTime,
-    open: f64,
-    high: f64,
-    low: f64,
-    close: f64,
-    volume: f64,
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("dataset") => dataset::run(&parse_flags(&args[1..])?),
+        Some("scan") => scan::run(&parse_flags(&args[1..])?),
+        Some("sweep") => sweep::run(&parse_flags(&args[1..])?),
+        _ => analyze_dir(Path::new("/opt/data/raw_data")),
+    }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let data_dir = Path::new("/opt/data/raw_data");
+/// 解析`--key value`形式的参数
+fn parse_flags(args: &[String]) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let mut flags = HashMap::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let key = arg
+            .strip_prefix("--")
+            .ok_or_else(|| format!("unexpected argument: {}", arg))?;
+        let value = iter.next().ok_or_else(|| format!("missing value for --{}", key))?;
+        flags.insert(key.to_string(), value.clone());
+    }
+    Ok(flags)
+}
 
+fn analyze_dir(data_dir: &Path) -> Result<(), Box<dyn Error>> {
     // 遍历目录下的所有csv文件
-    for entry in std::fs::read_dir(data_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if path.extension().and_then(|s| s.to_str()) == Some("csv") {
-            println!("Processing file: {:?}", path);
-            process_csv_file(&path)?;
-        }
+    for path in data::list_csv_files(data_dir)? {
+        println!("Processing file: {:?}", path);
+        process_csv_file(&path)?;
     }
 
     Ok(())
 }
 
 fn process_csv_file(path: &Path) -> Result<(), Box<dyn Error>> {
-    let file = File::open(path)?;
-    let mut rdr = Reader::from_reader(file);
-    let mut kline_units = Vec::new();
-
-    for result in rdr.records() {
-        let record = result?;
-
-        // Parse CSV record
-        let csv_record = parse_csv_record(&record)?;
-
-        // Convert to KLineUnit
-        let klu = KLineUnit::new(
-            csv_record.timestamp.timestamp() as usize,
-            csv_record.open,
-            csv_record.high,
-            csv_record.low,
-            csv_record.close,
-            csv_record.volume,
-        );
-
-        kline_units.push(klu);
-    }
-
-    // Sort by timestamp
-    kline_units.sort_by_key(|k| k.timestamp);
-
-    // Create and run analyzer
-    let mut analyzer = Analyzer::new();
-    analyzer.update(&kline_units)?;
+    let bars = data::read_kl_data(path)?;
+    data::run_analyzer(&data::symbol_of(path), &bars, data::load_config(None)?, None)?;
 
     // Print analysis results
     println!("Analysis completed for {:?}", path);
-    println!("Number of K-line units: {}", kline_units.len());
-    println!(
-        "First timestamp: {}",
-        NaiveDateTime::from_timestamp_opt(kline_units.first().unwrap().timestamp as i64, 0)
-            .unwrap()
-    );
-    println!(
-        "Last timestamp: {}",
-        NaiveDateTime::from_timestamp_opt(kline_units.last().unwrap().timestamp as i64, 0).unwrap()
-    );
+    println!("Number of K-line units: {}", bars.len());
+    if let (Some(first), Some(last)) = (bars.first(), bars.last()) {
+        println!("First timestamp: {}", first.time);
+        println!("Last timestamp: {}", last.time);
+    }
 
     Ok(())
 }
-
-fn parse_csv_record(record: &csv::StringRecord) -> Result<CsvRecord, Box<dyn Error>> {
-    let timestamp = NaiveDateTime::parse_from_str(&record[0], "%Y-%m-%d %H:%M:%S")?;
-
-    Ok(CsvRecord {
-        timestamp,
-        open: record[1].parse()?,
-        high: record[2].parse()?,
-        low: record[3].parse()?,
-        close: record[4].parse()?,
-        volume: record[5].parse()?,
-    })
-}
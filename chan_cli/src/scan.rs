@@ -0,0 +1,428 @@
+use chan_core::buy_sell_point::bs_point::BSPoint;
+use chan_core::buy_sell_point::bs_point_list::BSPointList;
+use chan_core::common::cenum::BspType;
+use chan_core::trade::optimize::par_map;
+use chan_core::traits::line_trait::LineTrait;
+use chan_core::{Analyzer, ChanConfig};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::data::{list_csv_files, load_config, run_analyzer_step, symbol_of};
+
+pub const USAGE: &str = "chan_cli scan --data-dir <dir> [--bars <n>] [--config <chan_config.json>] \
+[--types 1,1p,2,2s,3a,3b] [--dir buy|sell|all] [--level bi|seg|all] \
+[--feature <name>=<v>,<name>>=<v>,...] [--threads <n>]";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Ge,
+    Le,
+    Gt,
+    Lt,
+    Eq,
+}
+
+/// 特征阈值条件，如`bsp1_bi_amp>=0.05`，买卖点没有该特征时不满足
+#[derive(Debug, Clone, PartialEq)]
+struct FeatureCond {
+    name: String,
+    op: CmpOp,
+    value: f64,
+}
+
+impl FeatureCond {
+    fn parse(s: &str) -> Result<Self, Box<dyn Error>> {
+        // 两个字符的比较符要先于单字符匹配
+        let ops = [(">=", CmpOp::Ge), ("<=", CmpOp::Le), (">", CmpOp::Gt), ("<", CmpOp::Lt), ("=", CmpOp::Eq)];
+        for (sym, op) in ops {
+            if let Some((name, value)) = s.split_once(sym) {
+                return Ok(Self {
+                    name: name.trim().to_string(),
+                    op,
+                    value: value.trim().parse()?,
+                });
+            }
+        }
+        Err(format!("invalid feature condition: {}", s).into())
+    }
+
+    fn matches(&self, value: Option<f64>) -> bool {
+        value.is_some_and(|v| match self.op {
+            CmpOp::Ge => v >= self.value,
+            CmpOp::Le => v <= self.value,
+            CmpOp::Gt => v > self.value,
+            CmpOp::Lt => v < self.value,
+            CmpOp::Eq => v == self.value,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct ScanFilter {
+    /// 为空时不限类型，买卖点的任一类型在其中即可
+    types: Vec<BspType>,
+    /// None表示买卖点都要
+    is_buy: Option<bool>,
+    feature_conds: Vec<FeatureCond>,
+}
+
+impl ScanFilter {
+    fn from_flags(flags: &HashMap<String, String>) -> Result<Self, Box<dyn Error>> {
+        let types = match flags.get("types") {
+            Some(types) => types
+                .split(',')
+                .map(|t| t.trim().parse::<BspType>().map_err(|_| format!("unknown bsp type: {}", t)))
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        let is_buy = match flags.get("dir").map_or("all", String::as_str) {
+            "buy" => Some(true),
+            "sell" => Some(false),
+            "all" => None,
+            dir => return Err(format!("unknown direction: {}", dir).into()),
+        };
+        let feature_conds = match flags.get("feature") {
+            Some(conds) => conds.split(',').map(FeatureCond::parse).collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        Ok(Self {
+            types,
+            is_buy,
+            feature_conds,
+        })
+    }
+
+    fn matches<T: LineTrait>(&self, bsp: &BSPoint<T>) -> bool {
+        (self.types.is_empty() || bsp.bs_type.iter().any(|t| self.types.contains(t)))
+            && self.is_buy.is_none_or(|is_buy| bsp.is_buy == is_buy)
+            && self
+                .feature_conds
+                .iter()
+                .all(|cond| cond.matches(bsp.features.get(&cond.name)))
+    }
+}
+
+/// 一个标的在某个级别上最近的买卖点
+#[derive(Debug, Clone)]
+struct ScanHit {
+    symbol: String,
+    level: &'static str,
+    bs_type: String,
+    is_buy: bool,
+    time: String,
+    /// 买卖点首次出现时的K线距最后一根K线的根数，0表示在最后一根K线上才出现
+    bars_ago: usize,
+    price: f64,
+    relate_bsp1: Option<(String, f64)>,
+}
+
+/// 买卖点首次出现时最后一根K线的序号，key为(买卖点所在K线序号, 是否买点)
+///
+/// 买卖点要等笔/线段确认后才出现，比它所在的K线晚，扫描关心的是信号什么时候能看到
+type FirstSeen = HashMap<(usize, bool), usize>;
+
+fn last_klu_idx(analyzer: &Analyzer) -> Option<usize> {
+    analyzer
+        .kline_list
+        .lst
+        .last()
+        .and_then(|klc| klc.lst.last())
+        .map(|klu| klu.index())
+}
+
+fn record_first_seen<T: LineTrait>(first_seen: &mut FirstSeen, bsp_list: &BSPointList<T>, klu_idx: usize) {
+    for bsp in bsp_list.get_lastest_bsp_list() {
+        let bsp = bsp.borrow();
+        first_seen.entry((bsp.klu.index(), bsp.is_buy)).or_insert(klu_idx);
+    }
+}
+
+/// 满足条件的买卖点中最近出现的一个，超过max_bars_ago根K线的不要
+fn latest_hit<T: LineTrait>(
+    symbol: &str,
+    level: &'static str,
+    bsp_list: &BSPointList<T>,
+    first_seen: &FirstSeen,
+    filter: &ScanFilter,
+    last_klu_idx: usize,
+    max_bars_ago: usize,
+) -> Option<ScanHit> {
+    let seen_at = |bsp: &BSPoint<T>| {
+        first_seen
+            .get(&(bsp.klu.index(), bsp.is_buy))
+            .copied()
+            .unwrap_or(last_klu_idx)
+    };
+    let bsp = bsp_list
+        .get_lastest_bsp_list()
+        .into_iter()
+        .filter(|bsp| filter.matches(bsp.borrow()))
+        .max_by_key(|bsp| (seen_at(bsp.borrow()), bsp.borrow().klu.index()))?;
+    let bsp = bsp.borrow();
+    let bars_ago = last_klu_idx.saturating_sub(seen_at(bsp));
+    if bars_ago >= max_bars_ago {
+        return None;
+    }
+    let relate_bsp1 = bsp.relate_bsp1.as_ref().map(|bsp1| {
+        let bsp1 = bsp1.borrow();
+        (bsp1.klu.time.to_string(), bsp1.bi.borrow().get_end_val())
+    });
+    Some(ScanHit {
+        symbol: symbol.to_string(),
+        level,
+        bs_type: bsp.type_to_string(),
+        is_buy: bsp.is_buy,
+        time: bsp.klu.time.to_string(),
+        bars_ago,
+        price: bsp.bi.borrow().get_end_val(),
+        relate_bsp1,
+    })
+}
+
+/// `chan_cli scan`：并行分析目录下每个标的，列出最近--bars根K线内出现买卖点的标的
+///
+/// 每个标的每个级别只看满足过滤条件的最近一个买卖点，结果按出现时间从近到远排序
+pub fn run(flags: &HashMap<String, String>) -> Result<(), Box<dyn Error>> {
+    let data_dir = flags.get("data-dir").ok_or(USAGE)?;
+    let max_bars_ago: usize = flags.get("bars").map_or(Ok(5), |n| n.parse())?;
+    let config = load_config(flags.get("config").map(String::as_str))?;
+    let filter = ScanFilter::from_flags(flags)?;
+    let (scan_bi, scan_seg) = match flags.get("level").map_or("all", String::as_str) {
+        "bi" => (true, false),
+        "seg" => (false, true),
+        "all" => (true, true),
+        level => return Err(format!("unknown level: {}", level).into()),
+    };
+    let threads = match flags.get("threads") {
+        Some(n) => n.parse()?,
+        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    let files = list_csv_files(Path::new(data_dir))?;
+    let results = par_map(&files, threads, |path: &PathBuf| {
+        scan_file(path, config.clone(), &filter, scan_bi, scan_seg, max_bars_ago).map_err(|e| e.to_string())
+    });
+
+    let mut hits = Vec::new();
+    for (path, res) in files.iter().zip(results) {
+        match res {
+            Ok(res) => hits.extend(res),
+            Err(e) => eprintln!("scan {:?} fail: {}", path, e),
+        }
+    }
+    hits.sort_by(|a, b| a.bars_ago.cmp(&b.bars_ago).then_with(|| a.symbol.cmp(&b.symbol)));
+    print_hits(&hits);
+    Ok(())
+}
+
+/// 逐K线分析一个标的，记下每个买卖点首次出现的K线，再取各级别最近出现的买卖点
+fn scan_file(
+    path: &Path,
+    config: ChanConfig,
+    filter: &ScanFilter,
+    scan_bi: bool,
+    scan_seg: bool,
+    max_bars_ago: usize,
+) -> Result<Vec<ScanHit>, Box<dyn Error>> {
+    let symbol = symbol_of(path);
+    let mut bi_first_seen = FirstSeen::new();
+    let mut seg_first_seen = FirstSeen::new();
+    let analyzer = run_analyzer_step(path, config, None, |analyzer| {
+        let Some(klu_idx) = last_klu_idx(analyzer) else {
+            return;
+        };
+        if scan_bi {
+            record_first_seen(&mut bi_first_seen, &analyzer.bs_point_lst, klu_idx);
+        }
+        if scan_seg {
+            record_first_seen(&mut seg_first_seen, &analyzer.seg_bs_point_lst, klu_idx);
+        }
+    })?;
+    let Some(last_klu_idx) = last_klu_idx(&analyzer) else {
+        return Ok(Vec::new());
+    };
+    let mut hits = Vec::new();
+    if scan_bi {
+        hits.extend(latest_hit(
+            &symbol,
+            "bi",
+            &analyzer.bs_point_lst,
+            &bi_first_seen,
+            filter,
+            last_klu_idx,
+            max_bars_ago,
+        ));
+    }
+    if scan_seg {
+        hits.extend(latest_hit(
+            &symbol,
+            "seg",
+            &analyzer.seg_bs_point_lst,
+            &seg_first_seen,
+            filter,
+            last_klu_idx,
+            max_bars_ago,
+        ));
+    }
+    Ok(hits)
+}
+
+fn print_hits(hits: &[ScanHit]) {
+    println!("symbol\tlevel\tbs_type\tdir\ttime\tbars_ago\tprice\trelate_bsp1_time\trelate_bsp1_price");
+    for hit in hits {
+        let (bsp1_time, bsp1_price) = match &hit.relate_bsp1 {
+            Some((time, price)) => (time.clone(), format!("{:.4}", price)),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{:.4}\t{}\t{}",
+            hit.symbol,
+            hit.level,
+            hit.bs_type,
+            if hit.is_buy { "buy" } else { "sell" },
+            hit.time,
+            hit.bars_ago,
+            hit.price,
+            bsp1_time,
+            bsp1_price
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chan_core::common::cenum::DataField;
+    use chan_core::common::sample_data::sine_kl_data;
+    use std::io::Write;
+
+    /// 把chan_core的正弦走势写成日线csv，供逐K线分析
+    fn sine_csv(name: &str, cnt: usize) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("chan_scan_{}_{}.csv", std::process::id(), name));
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(file, "time,open,high,low,close,volume").unwrap();
+        for kl_data in sine_kl_data(cnt) {
+            let field = |field: DataField| kl_data.kl_dict[&field];
+            writeln!(
+                file,
+                "{},{},{},{},{},{}",
+                kl_data.time.to_str(),
+                field(DataField::FieldOpen),
+                field(DataField::FieldHigh),
+                field(DataField::FieldLow),
+                field(DataField::FieldClose),
+                field(DataField::FieldVolume)
+            )
+            .unwrap();
+        }
+        path
+    }
+
+    fn flags(items: &[(&str, &str)]) -> HashMap<String, String> {
+        items.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_feature_cond_parse() {
+        let cond = FeatureCond::parse(" bsp1_bi_amp >= 0.05 ").unwrap();
+        assert_eq!(cond, FeatureCond { name: "bsp1_bi_amp".to_string(), op: CmpOp::Ge, value: 0.05 });
+        assert_eq!(FeatureCond::parse("a<=1").unwrap().op, CmpOp::Le);
+        assert_eq!(FeatureCond::parse("a>1").unwrap().op, CmpOp::Gt);
+        assert_eq!(FeatureCond::parse("a<-1").unwrap(), FeatureCond { name: "a".to_string(), op: CmpOp::Lt, value: -1.0 });
+        assert_eq!(FeatureCond::parse("a=2").unwrap().op, CmpOp::Eq);
+        assert!(FeatureCond::parse("a").is_err());
+        assert!(FeatureCond::parse("a>=x").is_err());
+    }
+
+    #[test]
+    fn test_feature_cond_matches() {
+        let cond = FeatureCond::parse("a>=1").unwrap();
+        assert!(cond.matches(Some(1.0)));
+        assert!(!cond.matches(Some(0.5)));
+        assert!(!cond.matches(None));
+        assert!(FeatureCond::parse("a<1").unwrap().matches(Some(0.5)));
+        assert!(!FeatureCond::parse("a=1").unwrap().matches(Some(1.5)));
+    }
+
+    #[test]
+    fn test_scan_filter_from_flags() {
+        let filter = ScanFilter::from_flags(&flags(&[("types", "1, 2s"), ("dir", "sell"), ("feature", "a>=1,b<2")])).unwrap();
+        assert_eq!(filter.types, vec![BspType::T1, BspType::T2S]);
+        assert_eq!(filter.is_buy, Some(false));
+        assert_eq!(filter.feature_conds.len(), 2);
+
+        let filter = ScanFilter::from_flags(&HashMap::new()).unwrap();
+        assert!(filter.types.is_empty());
+        assert_eq!(filter.is_buy, None);
+        assert!(filter.feature_conds.is_empty());
+
+        assert!(ScanFilter::from_flags(&flags(&[("types", "4")])).is_err());
+        assert!(ScanFilter::from_flags(&flags(&[("dir", "long")])).is_err());
+        assert!(ScanFilter::from_flags(&flags(&[("feature", "a")])).is_err());
+    }
+
+    #[test]
+    fn test_scan_filter_matches() {
+        let path = sine_csv("filter", 200);
+        let analyzer = run_analyzer_step(&path, ChanConfig::new(None).unwrap(), None, |_| {}).unwrap();
+        let bsp_lst = analyzer.bs_point_lst.get_lastest_bsp_list();
+        assert!(!bsp_lst.is_empty());
+        let buy = ScanFilter { is_buy: Some(true), ..Default::default() };
+        let missing_feat = ScanFilter {
+            feature_conds: vec![FeatureCond::parse("no_such_feature>=0").unwrap()],
+            ..Default::default()
+        };
+        for bsp in &bsp_lst {
+            let bsp = bsp.borrow();
+            assert!(ScanFilter::default().matches(bsp));
+            assert_eq!(buy.matches(bsp), bsp.is_buy);
+            assert!(!missing_feat.matches(bsp));
+            let same_type = ScanFilter { types: vec![bsp.bs_type[0]], ..Default::default() };
+            assert!(same_type.matches(bsp));
+            let other_types = [BspType::T1, BspType::T1P, BspType::T2, BspType::T2S, BspType::T3A, BspType::T3B]
+                .into_iter()
+                .filter(|t| !bsp.bs_type.contains(t))
+                .collect();
+            assert!(!ScanFilter { types: other_types, ..Default::default() }.matches(bsp));
+        }
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_bars_ago_from_first_appearance() {
+        let path = sine_csv("bars_ago", 200);
+        let hits = scan_file(&path, ChanConfig::new(None).unwrap(), &ScanFilter::default(), true, false, usize::MAX).unwrap();
+        let hit = hits.first().unwrap();
+        assert_eq!(hit.level, "bi");
+
+        // 逐K线重跑，找到这个买卖点第一次出现时的K线
+        let mut seen_at = None;
+        let analyzer = run_analyzer_step(&path, ChanConfig::new(None).unwrap(), None, |analyzer| {
+            let appeared = analyzer
+                .bs_point_lst
+                .get_lastest_bsp_list()
+                .iter()
+                .any(|bsp| bsp.borrow().klu.time.to_string() == hit.time && bsp.borrow().is_buy == hit.is_buy);
+            if appeared && seen_at.is_none() {
+                seen_at = last_klu_idx(analyzer);
+            }
+        })
+        .unwrap();
+        let last_idx = last_klu_idx(&analyzer).unwrap();
+        assert_eq!(hit.bars_ago, last_idx - seen_at.unwrap());
+
+        // 买卖点不会早于它所在的K线出现
+        let bsp = analyzer
+            .bs_point_lst
+            .get_lastest_bsp_list()
+            .into_iter()
+            .find(|bsp| bsp.borrow().klu.time.to_string() == hit.time && bsp.borrow().is_buy == hit.is_buy)
+            .unwrap();
+        assert!(hit.bars_ago <= last_idx - bsp.borrow().klu.index());
+
+        let recent = scan_file(&path, ChanConfig::new(None).unwrap(), &ScanFilter::default(), true, false, hit.bars_ago).unwrap();
+        assert!(recent.is_empty());
+        std::fs::remove_file(&path).ok();
+    }
+}
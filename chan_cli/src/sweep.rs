@@ -0,0 +1,88 @@
+use chan_core::trade::backtest::BacktestConfig;
+use chan_core::trade::exit_rule::{ExitRuleSet, WithExitRules};
+use chan_core::trade::optimize::{
+    format_results, param_set_to_string, FoldResult, Optimizer, ParamSpace, RankBy, WalkForward,
+};
+use chan_core::trade::strategy::BspStrategy;
+use chan_core::ChanConfig;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+use crate::data::{read_kl_data, symbol_of};
+
+pub const USAGE: &str = "chan_cli sweep --data <csv> --space <param_space.json> \
+[--mode grid|random] [--samples <n>] [--seed <n>] [--backtest <backtest_config.json>] \
+[--rank-by return|win_rate|return_drawdown] [--top <n>] [--threads <n>] \
+[--train <bars> --test <bars> [--step <bars>] [--anchored true]]";
+
+/// `chan_cli sweep`：在一个标的上搜索ChanConfig参数，输出按指标排序的结果
+///
+/// 指定--train和--test时做walk-forward，每个划分输出训练段第一名及其在测试段上的表现；
+/// 策略为默认的BspStrategy，出场规则取各组合配置中的exit_rules
+pub fn run(flags: &HashMap<String, String>) -> Result<(), Box<dyn Error>> {
+    let data = Path::new(flags.get("data").ok_or(USAGE)?);
+    let space: ParamSpace = serde_json::from_reader(File::open(flags.get("space").ok_or(USAGE)?)?)?;
+    let backtest_config: BacktestConfig = match flags.get("backtest") {
+        Some(path) => serde_json::from_reader(File::open(path)?)?,
+        None => BacktestConfig::default(),
+    };
+    let param_sets = match flags.get("mode").map_or("grid", String::as_str) {
+        "grid" => space.grid(),
+        "random" => space.random(
+            flags.get("samples").map_or(Ok(100), |n| n.parse())?,
+            flags.get("seed").map_or(Ok(0), |n| n.parse())?,
+        ),
+        mode => return Err(format!("unknown sweep mode: {}", mode).into()),
+    };
+    let top = flags.get("top").map_or(Ok(20), |n| n.parse())?;
+
+    let bars = read_kl_data(data)?;
+    println!("{} bars loaded, {} param sets to evaluate", bars.len(), param_sets.len());
+    let mut optimizer = Optimizer::new(&symbol_of(data), &bars, space, backtest_config, |conf: &ChanConfig| {
+        WithExitRules::new(BspStrategy::default(), ExitRuleSet::from_config(&conf.exit_rules))
+    });
+    if let Some(rank_by) = flags.get("rank-by") {
+        optimizer.rank_by = rank_by.parse::<RankBy>()?;
+    }
+    if let Some(threads) = flags.get("threads") {
+        optimizer.threads = threads.parse()?;
+    }
+
+    match (flags.get("train"), flags.get("test")) {
+        (Some(train), Some(test)) => {
+            let mut walk_forward = WalkForward::new(train.parse()?, test.parse()?);
+            if let Some(step) = flags.get("step") {
+                walk_forward.step_bars = step.parse()?;
+            }
+            walk_forward.anchored = flags.get("anchored").map_or(Ok(false), |v| v.parse())?;
+            print_folds(&optimizer.walk_forward(&param_sets, &walk_forward)?);
+        }
+        (None, None) => println!("{}", format_results(&optimizer.sweep(&param_sets), top)),
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+fn print_folds(folds: &[FoldResult]) {
+    println!("fold\ttrain\ttest\ttrain_return\ttest_return\ttest_drawdown\ttest_win_rate\ttest_trade_cnt\tparams");
+    for (idx, fold) in folds.iter().enumerate() {
+        let train = format!("{}..{}", fold.fold.train.start, fold.fold.train.end);
+        let test = format!("{}..{}", fold.fold.test.start, fold.fold.test.end);
+        let Some(best) = &fold.best else {
+            println!("{}\t{}\t{}\tno valid param set", idx, train, test);
+            continue;
+        };
+        let train_return = best.metrics.as_ref().map_or(f64::NAN, |m| m.total_return);
+        let params = param_set_to_string(&best.params);
+        match fold.test.as_ref().map(|res| &res.metrics) {
+            Some(Ok(m)) => println!(
+                "{}\t{}\t{}\t{:.4}\t{:.4}\t{:.4}\t{:.4}\t{}\t{}",
+                idx, train, test, train_return, m.total_return, m.max_drawdown, m.win_rate, m.trade_cnt, params
+            ),
+            Some(Err(e)) => println!("{}\t{}\t{}\t{:.4}\tERR: {}\t\t\t\t{}", idx, train, test, train_return, e.msg, params),
+            None => println!("{}\t{}\t{}\t{:.4}\t\t\t\t\t{}", idx, train, test, train_return, params),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! 单指标在1000万根K线上的耗时，以及BOLL与按定义逐窗口计算结果的最大误差
+//!
+//! cargo bench -p chan_core --bench indicators
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use chan_core::math::{
+    boll::{BollMetric, BollModel},
+    rsi::RSI,
+};
+
+const BAR_CNT: usize = 10_000_000;
+
+/// 确定性的随机游走收盘价
+fn gen_prices(cnt: usize) -> Vec<f64> {
+    let mut seed: u64 = 2024;
+    let mut price = 100.0;
+    (0..cnt)
+        .map(|_| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            price = (price + ((seed >> 11) as f64 / (1u64 << 53) as f64 - 0.5) * 0.2).max(1.0);
+            price
+        })
+        .collect()
+}
+
+fn bench<F: FnMut(f64)>(name: &str, prices: &[f64], mut f: F) {
+    let begin = Instant::now();
+    for &price in prices {
+        f(price);
+    }
+    let cost = begin.elapsed();
+    println!(
+        "{:<12} {:>8.1} ms  {:>6.2} ns/bar",
+        name,
+        cost.as_secs_f64() * 1e3,
+        cost.as_nanos() as f64 / prices.len() as f64
+    );
+}
+
+/// 增量BOLL与逐窗口求均值、方差的结果相差不能超过1e-12
+fn check_boll(prices: &[f64], period: usize, k: f64) {
+    let mut boll = BollModel::new(period, k).unwrap();
+    let mut max_err: f64 = 0.0;
+    for (idx, &price) in prices.iter().enumerate() {
+        let metric = boll.add(price);
+        let window = &prices[(idx + 1).saturating_sub(period)..=idx];
+        let n = window.len() as f64;
+        let mid = window.iter().sum::<f64>() / n;
+        let std_dev = (window.iter().map(|&x| (x - mid).powi(2)).sum::<f64>() / n).sqrt();
+        let expected = BollMetric {
+            up: mid + k * std_dev,
+            mid,
+            down: mid - k * std_dev,
+        };
+        max_err = max_err
+            .max((metric.up - expected.up).abs())
+            .max((metric.mid - expected.mid).abs())
+            .max((metric.down - expected.down).abs());
+    }
+    println!("{:<12} max_err = {:e}", format!("boll_{}", period), max_err);
+    assert!(max_err < 1e-12);
+}
+
+fn main() {
+    let prices = gen_prices(BAR_CNT);
+    for period in [20, 120] {
+        let mut boll = BollModel::new(period, 2.0).unwrap();
+        bench(&format!("boll_{}", period), &prices, |price| {
+            black_box(boll.add(price));
+        });
+    }
+    for period in [20, 120] {
+        check_boll(&prices, period, 2.0);
+    }
+    for period in [6, 14] {
+        let mut rsi = RSI::new(period);
+        bench(&format!("rsi_{}", period), &prices, |price| {
+            black_box(rsi.add(price));
+        });
+    }
+}
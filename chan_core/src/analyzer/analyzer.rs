@@ -1,10 +1,15 @@
 use std::collections::HashMap;
+#[cfg(feature = "dataframe")]
 use std::path::Path;
+use std::sync::Arc;
+#[cfg(feature = "dataframe")]
 use polars::prelude::*;
 use crate::common::{
     chan_exception::{ChanException, ErrCode},
+    cenum::DataField,
     chan_config::ChanConfig,
     enums::{SegType, KlineDir},
+    handle::Handle,
     time::Time,
 };
 use crate::bi::{bi::Bi, bi_list::BiList};
@@ -13,24 +18,34 @@ use crate::seg::{
     seg_config::SegConfig,
     seg_list_comm::SegListComm,
 };
+use crate::traits::line_trait::LineTrait;
 use crate::zs::zs_list::ZSList;
-use crate::bs_point::bs_point_list::BSPointList;
+#[cfg(feature = "dataframe")]
+use crate::zs::zs::ZS;
+use crate::buy_sell_point::bs_point_list::BSPointList;
 use crate::kline::{kline_list::KLineList, kline_unit::KLineUnit};
-use crate::metric::metric_model::MetricModel;
+use crate::traits::metric_trait::MetricModel;
+use crate::chan_model::scorer::BspScorer;
 
 /// 分析器，负责处理笔、线段、中枢和买卖点的计算
 #[derive(Debug)]
 pub struct Analyzer {
+    /// 加入过的全部KLU，KLU之间、笔和买卖点上的Handle都指向这里
+    pub klu_lst: Box<Vec<KLineUnit>>,
     pub kline_list: KLineList,
     pub bi_list: BiList,
-    pub seg_list: Box<dyn SegListComm<Item = Bi>>,
-    pub segseg_list: Box<dyn SegListComm<Item = Seg<Bi>>>,
-    pub zs_list: ZSList,
-    pub segzs_list: ZSList,
-    pub bs_point_lst: BSPointList<Bi, BiList>,
-    pub seg_bs_point_lst: BSPointList<Seg<Bi>, Box<dyn SegListComm<Item = Bi>>>,
+    pub seg_list: SegListComm<Bi>,
+    pub segseg_list: SegListComm<Seg<Bi>>,
+    pub zs_list: ZSList<Bi>,
+    pub segzs_list: ZSList<Seg<Bi>>,
+    pub bs_point_lst: BSPointList<Bi>,
+    pub seg_bs_point_lst: BSPointList<Seg<Bi>>,
     pub metric_model_lst: Vec<Box<dyn MetricModel>>,
     pub step_calculation: bool,
+    /// 为false时只算到中枢，不计算自身的买卖点，买卖点由外部用cal_bsp_variant计算
+    pub cal_bsp: bool,
+    /// 每次重算线段、中枢和买卖点加一，共用结构的买卖点参数变体据此判断是否需要重算
+    pub structure_version: usize,
     pub bs_point_history: Vec<HashMap<String, String>>,
     pub seg_bs_point_history: Vec<HashMap<String, String>>,
     config: ChanConfig,
@@ -43,6 +58,7 @@ impl Analyzer {
         let segseg_list = get_seglist_instance(&conf.seg_conf, SegType::Seg)?;
 
         Ok(Self {
+            klu_lst: Box::new(Vec::new()),
             kline_list: KLineList::new(kl_type),
             bi_list: BiList::new(conf.bi_conf.clone()),
             seg_list,
@@ -50,9 +66,11 @@ impl Analyzer {
             zs_list: ZSList::new(conf.zs_conf.clone()),
             segzs_list: ZSList::new(conf.zs_conf.clone()),
             bs_point_lst: BSPointList::new(conf.bs_point_conf.clone()),
-            seg_bs_point_lst: BSPointList::new(conf.seg_bs_point_conf.clone()),
-            metric_model_lst: conf.get_metric_model(),
+            seg_bs_point_lst: BSPointList::new_seg(conf.seg_bs_point_conf.clone()),
+            metric_model_lst: conf.get_metric_model()?,
             step_calculation: conf.trigger_step,
+            cal_bsp: true,
+            structure_version: 0,
             config: conf,
             bs_point_history: Vec::new(),
             seg_bs_point_history: Vec::new(),
@@ -62,63 +80,133 @@ impl Analyzer {
     /// Deep clone the analyzer
     pub fn deep_clone(&self) -> Result<Self, ChanException> {
         Ok(Self {
+            klu_lst: self.klu_lst.clone(),
             kline_list: self.kline_list.deep_clone(),
             bi_list: self.bi_list.clone(),
-            seg_list: self.seg_list.clone_box(),
-            segseg_list: self.segseg_list.clone_box(),
+            seg_list: self.seg_list.clone(),
+            segseg_list: self.segseg_list.clone(),
             zs_list: self.zs_list.clone(),
             segzs_list: self.segzs_list.clone(),
             bs_point_lst: self.bs_point_lst.clone(),
             seg_bs_point_lst: self.seg_bs_point_lst.clone(),
             metric_model_lst: self.metric_model_lst.clone(),
             step_calculation: self.step_calculation,
+            cal_bsp: self.cal_bsp,
+            structure_version: self.structure_version,
             config: self.config.clone(),
             bs_point_history: self.bs_point_history.clone(),
             seg_bs_point_history: self.seg_bs_point_history.clone(),
         })
     }
 
-    /// Calculate segments
-    fn cal_seg(&mut self) -> Result<(), ChanException> {
-        let mut sure_seg_cnt = 0;
-        if self.seg_list.len() == 0 {
-            for bi in self.bi_list.iter_mut() {
-                bi.set_seg_idx(0);
-            }
-            return Ok(());
+    /// 注册自定义指标，只对之后加入的KLU生效
+    pub fn register_metric_model(&mut self, metric_model: Box<dyn MetricModel>) {
+        self.metric_model_lst.push(metric_model);
+    }
+
+    /// 笔和线段买卖点使用同一个打分模型，只对之后生成的买卖点生效
+    pub fn set_bsp_scorer(&mut self, scorer: Arc<dyn BspScorer>) {
+        self.bs_point_lst.set_scorer(scorer.clone());
+        self.seg_bs_point_lst.set_scorer(scorer);
+    }
+
+    /// 在klu_lst上创建下一根KLU，之后须交给add_single_klu
+    pub fn new_klu(
+        &self,
+        kl_dict: &HashMap<DataField, f64>,
+        time: Time,
+        autofix: bool,
+    ) -> Result<KLineUnit, ChanException> {
+        let mut klu = KLineUnit::new(&self.klu_lst, self.klu_lst.len(), kl_dict, autofix)?;
+        klu.time = time;
+        Ok(klu)
+    }
+
+    /// new_klu加add_single_klu
+    pub fn add_kl_data(
+        &mut self,
+        kl_dict: &HashMap<DataField, f64>,
+        time: Time,
+        autofix: bool,
+    ) -> Result<(), ChanException> {
+        let klu = self.new_klu(kl_dict, time, autofix)?;
+        self.add_single_klu(klu)
+    }
+
+    /// Feed a KLineUnit through KLine combining, bi and (in step mode) seg/zs/bsp calculation
+    ///
+    /// klu须由new_klu创建，保证其Handle指向klu_lst中的位置
+    pub fn add_single_klu(&mut self, mut klu: KLineUnit) -> Result<(), ChanException> {
+        if klu.index() != self.klu_lst.len() {
+            return Err(ChanException::new(
+                format!("klu index {} should be {}, create it with Analyzer::new_klu", klu.index(), self.klu_lst.len()),
+                ErrCode::ParaError,
+            ));
         }
+        klu.set_metric(&mut self.metric_model_lst);
+        self.klu_lst.push(klu.clone());
+        let klc_cnt = self.kline_list.len();
+        self.kline_list.add_single_klu(klu)?;
 
-        let mut begin_seg = self.seg_list.last().unwrap();
-        for seg in self.seg_list.iter().rev() {
-            if seg.is_sure() {
-                sure_seg_cnt += 1;
-            } else {
-                sure_seg_cnt = 0;
-            }
-            begin_seg = seg;
-            if sure_seg_cnt > 2 {
-                break;
+        let len = self.kline_list.len();
+        if len > klc_cnt {
+            // 不需要合并K线，生成了新的合并K线
+            if len >= 2
+                && self.bi_list.update_bi(&self.kline_list[len - 2], &self.kline_list[len - 1], self.step_calculation)?
+                && self.step_calculation
+            {
+                self.cal_seg_and_zs()?;
             }
+        } else if self.step_calculation && self.bi_list.try_add_virtual_bi(&self.kline_list[len - 1], true)? {
+            // 这里的必要性参见issue#175
+            self.cal_seg_and_zs()?;
         }
+        Ok(())
+    }
 
-        let mut cur_seg = self.seg_list.last().unwrap();
-        for bi in self.bi_list.iter_mut().rev() {
-            if bi.seg_idx().is_some() && bi.idx() < begin_seg.start_bi().idx() {
-                break;
-            }
-            if bi.idx() > cur_seg.end_bi().idx() {
-                bi.set_seg_idx(cur_seg.idx() + 1);
-                continue;
+    /// Calculate seg, zs and bsp on both levels
+    ///
+    /// 笔级别：笔->线段->笔中枢->笔买卖点；线段级别：线段->线段的线段->线段中枢->线段买卖点
+    pub fn cal_seg_and_zs(&mut self) -> Result<(), ChanException> {
+        if !self.step_calculation {
+            if let Some(last_klc) = self.kline_list.last() {
+                self.bi_list.try_add_virtual_bi(last_klc, false)?;
             }
-            if bi.idx() < cur_seg.start_bi().idx() {
-                cur_seg = cur_seg.pre().expect("cur_seg.pre should exist");
-            }
-            bi.set_seg_idx(cur_seg.idx());
         }
+        self.seg_list.update(&self.bi_list.lst)?;
+        cal_seg(&self.bi_list.lst, &self.seg_list);
+        self.zs_list.cal_bi_zs(&self.bi_list.lst, &self.seg_list);
+        // 计算seg的zs_lst，以及中枢的bi_in, bi_out
+        self.update_zs_in_seg(false)?;
+
+        self.segseg_list.update(&self.seg_list.lst)?;
+        cal_seg(&self.seg_list.lst, &self.segseg_list);
+        self.segzs_list.cal_bi_zs(&self.seg_list.lst, &self.segseg_list);
+        // 计算segseg的zs_lst，以及中枢的bi_in, bi_out
+        self.update_zs_in_seg(true)?;
 
+        // 计算买卖点，线段买卖点使用seg_bs_point_conf
+        if self.cal_bsp {
+            self.seg_bs_point_lst.cal(&self.seg_list.lst, &self.segseg_list)?;
+            self.bs_point_lst.cal(&self.bi_list.lst, &self.seg_list)?;
+            self.record_current_bs_points();
+        }
+        self.structure_version += 1;
         Ok(())
     }
 
+    /// 用当前的笔、线段和中枢计算另一组买卖点，不改动自身的买卖点
+    ///
+    /// 参数优化时只有买卖点参数不同的配置共用同一个Analyzer，每个配置各自持有买卖点列表
+    pub fn cal_bsp_variant(
+        &self,
+        bs_point_lst: &mut BSPointList<Bi>,
+        seg_bs_point_lst: &mut BSPointList<Seg<Bi>>,
+    ) -> Result<(), ChanException> {
+        seg_bs_point_lst.cal(&self.seg_list.lst, &self.segseg_list)?;
+        bs_point_lst.cal(&self.bi_list.lst, &self.seg_list)
+    }
+
     /// Update ZhongShu in segments
     fn update_zs_in_seg(&mut self, is_seg_level: bool) -> Result<(), ChanException> {
         let (seg_list, zs_list) = if is_seg_level {
@@ -181,6 +269,7 @@ impl Analyzer {
             }
             if let Some(bi) = &latest_bsp.bi {
                 record.insert("bi_idx".to_string(), bi.idx().to_string());
+                record.insert("bi_type".to_string(), bi.bi_type().to_string());
                 record.insert("bi_begin_time".to_string(), bi.get_begin_klu().time.to_string());
                 record.insert("bi_end_time".to_string(), bi.get_end_klu().time.to_string());
             }
@@ -206,6 +295,7 @@ impl Analyzer {
     }
 
     /// Convert analysis results to DataFrames
+    #[cfg(feature = "dataframe")]
     pub fn to_dataframes(&self) -> Result<HashMap<String, DataFrame>, ChanException> {
         let mut dataframes = HashMap::new();
 
@@ -230,7 +320,8 @@ impl Analyzer {
                 "end_time" => self.bi_list.iter().map(|b| b.get_end_klu().time.to_string()).collect::<Vec<_>>(),
                 "high" => self.bi_list.iter().map(|b| b.high()).collect::<Vec<_>>(),
                 "low" => self.bi_list.iter().map(|b| b.low()).collect::<Vec<_>>(),
-                "direction" => self.bi_list.iter().map(|b| b.direction().to_string()).collect::<Vec<_>>()
+                "direction" => self.bi_list.iter().map(|b| b.direction().to_string()).collect::<Vec<_>>(),
+                "bi_type" => self.bi_list.iter().map(|b| b.bi_type().to_string()).collect::<Vec<_>>()
             )?;
             dataframes.insert("bis".to_string(), bi_data);
         }
@@ -248,6 +339,27 @@ impl Analyzer {
             dataframes.insert("segments".to_string(), seg_data);
         }
 
+        // 线段的线段数据
+        if !self.segseg_list.is_empty() {
+            let segseg_data = df!(
+                "idx" => self.segseg_list.iter().map(|s| s.idx()).collect::<Vec<_>>(),
+                "begin_time" => self.segseg_list.iter().map(|s| s.start_bi().get_begin_klu().time.to_string()).collect::<Vec<_>>(),
+                "end_time" => self.segseg_list.iter().map(|s| s.end_bi().get_end_klu().time.to_string()).collect::<Vec<_>>(),
+                "high" => self.segseg_list.iter().map(|s| s.high()).collect::<Vec<_>>(),
+                "low" => self.segseg_list.iter().map(|s| s.low()).collect::<Vec<_>>(),
+                "direction" => self.segseg_list.iter().map(|s| s.direction().to_string()).collect::<Vec<_>>()
+            )?;
+            dataframes.insert("segsegs".to_string(), segseg_data);
+        }
+
+        // 中枢数据
+        if !self.zs_list.is_empty() {
+            dataframes.insert("zs".to_string(), zs_dataframe(&self.zs_list)?);
+        }
+        if !self.segzs_list.is_empty() {
+            dataframes.insert("seg_zs".to_string(), zs_dataframe(&self.segzs_list)?);
+        }
+
         // 买卖点历史
         if !self.bs_point_history.is_empty() {
             let bs_history = DataFrame::new(vec![
@@ -256,16 +368,32 @@ impl Analyzer {
                 Series::new("is_buy", self.bs_point_history.iter().map(|h| h.get("is_buy").unwrap()).collect::<Vec<_>>()),
                 Series::new("relate_bsp1", self.bs_point_history.iter().map(|h| h.get("relate_bsp1").unwrap_or(&"".to_string())).collect::<Vec<_>>()),
                 Series::new("bi_idx", self.bs_point_history.iter().map(|h| h.get("bi_idx").unwrap_or(&"".to_string())).collect::<Vec<_>>()),
+                Series::new("bi_type", self.bs_point_history.iter().map(|h| h.get("bi_type").unwrap_or(&"".to_string())).collect::<Vec<_>>()),
                 Series::new("bi_begin_time", self.bs_point_history.iter().map(|h| h.get("bi_begin_time").unwrap_or(&"".to_string())).collect::<Vec<_>>()),
                 Series::new("bi_end_time", self.bs_point_history.iter().map(|h| h.get("bi_end_time").unwrap_or(&"".to_string())).collect::<Vec<_>>())
             ])?;
             dataframes.insert("bs_point_history".to_string(), bs_history);
         }
 
+        // 线段买卖点历史
+        if !self.seg_bs_point_history.is_empty() {
+            let seg_bs_history = DataFrame::new(vec![
+                Series::new("begin_time", self.seg_bs_point_history.iter().map(|h| h.get("begin_time").unwrap()).collect::<Vec<_>>()),
+                Series::new("bsp_type", self.seg_bs_point_history.iter().map(|h| h.get("bsp_type").unwrap()).collect::<Vec<_>>()),
+                Series::new("is_buy", self.seg_bs_point_history.iter().map(|h| h.get("is_buy").unwrap()).collect::<Vec<_>>()),
+                Series::new("relate_bsp1", self.seg_bs_point_history.iter().map(|h| h.get("relate_bsp1").unwrap_or(&"".to_string())).collect::<Vec<_>>()),
+                Series::new("seg_idx", self.seg_bs_point_history.iter().map(|h| h.get("seg_idx").unwrap_or(&"".to_string())).collect::<Vec<_>>()),
+                Series::new("bi_begin_time", self.seg_bs_point_history.iter().map(|h| h.get("bi_begin_time").unwrap_or(&"".to_string())).collect::<Vec<_>>()),
+                Series::new("bi_end_time", self.seg_bs_point_history.iter().map(|h| h.get("bi_end_time").unwrap_or(&"".to_string())).collect::<Vec<_>>())
+            ])?;
+            dataframes.insert("seg_bs_point_history".to_string(), seg_bs_history);
+        }
+
         Ok(dataframes)
     }
 
     /// Save analysis results to CSV files
+    #[cfg(feature = "dataframe")]
     pub fn to_csv(&self, directory: &str) -> Result<(), ChanException> {
         std::fs::create_dir_all(directory)?;
         
@@ -281,26 +409,232 @@ impl Analyzer {
     }
 }
 
+/// 根据线段划分更新每一笔（或每一线段）所属的线段序号，线段之后的部分记为最后一个线段idx+1
+///
+/// 只回溯到倒数第三个确定线段，之前的部分已经不会再变
+fn cal_seg<T: LineTrait>(bi_list: &[Handle<T>], seg_list: &SegListComm<T>) {
+    let last_seg = match seg_list.last() {
+        Some(seg) => seg.clone(),
+        None => {
+            for bi in bi_list {
+                bi.as_mut().set_seg_idx(0);
+            }
+            return;
+        }
+    };
+
+    let mut sure_seg_cnt = 0;
+    let mut begin_seg = last_seg.clone();
+    for seg in seg_list.iter().rev() {
+        if seg.borrow().is_sure {
+            sure_seg_cnt += 1;
+        } else {
+            sure_seg_cnt = 0;
+        }
+        begin_seg = seg.clone();
+        if sure_seg_cnt > 2 {
+            break;
+        }
+    }
+    let begin_bi_idx = begin_seg.borrow().start_bi.borrow().idx();
+
+    let mut cur_seg = last_seg;
+    for bi in bi_list.iter().rev() {
+        let bi_idx = bi.borrow().idx();
+        if bi.borrow().seg_idx().is_some() && bi_idx < begin_bi_idx {
+            break;
+        }
+        if bi_idx > cur_seg.borrow().end_bi.borrow().idx() {
+            let seg_idx = cur_seg.borrow().index() + 1;
+            bi.as_mut().set_seg_idx(seg_idx);
+            continue;
+        }
+        if bi_idx < cur_seg.borrow().start_bi.borrow().idx() {
+            let pre_seg = cur_seg.borrow().pre.clone().expect("cur_seg.pre should exist");
+            cur_seg = pre_seg;
+        }
+        let seg_idx = cur_seg.borrow().index();
+        bi.as_mut().set_seg_idx(seg_idx);
+    }
+}
+
+/// 中枢列表导出为DataFrame，笔中枢和线段中枢共用
+#[cfg(feature = "dataframe")]
+fn zs_dataframe<T: LineTrait>(zs_list: &ZSList<T>) -> Result<DataFrame, ChanException> {
+    let begin_bi_idx = |zs: &ZS<T>| zs.begin_bi.as_ref().map(|bi| bi.borrow().idx() as u32);
+    let end_bi_idx = |zs: &ZS<T>| zs.end_bi.as_ref().map(|bi| bi.borrow().idx() as u32);
+    // 合并前的各个中枢，按"起始笔-结束笔"用逗号连接，未合并时为空
+    let sub_zs = |zs: &ZS<T>| {
+        zs.sub_zs_lst
+            .iter()
+            .map(|sub_zs| format!("{}-{}", begin_bi_idx(sub_zs).unwrap_or_default(), end_bi_idx(sub_zs).unwrap_or_default()))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    Ok(df!(
+        "begin_bi_idx" => zs_list.iter().map(begin_bi_idx).collect::<Vec<_>>(),
+        "end_bi_idx" => zs_list.iter().map(end_bi_idx).collect::<Vec<_>>(),
+        "begin_time" => zs_list.iter().map(|zs| zs.begin.as_ref().map(|klu| klu.time.to_string())).collect::<Vec<_>>(),
+        "end_time" => zs_list.iter().map(|zs| zs.end.as_ref().map(|klu| klu.time.to_string())).collect::<Vec<_>>(),
+        "low" => zs_list.iter().map(|zs| zs.low).collect::<Vec<_>>(),
+        "high" => zs_list.iter().map(|zs| zs.high).collect::<Vec<_>>(),
+        "peak_low" => zs_list.iter().map(|zs| zs.peak_low).collect::<Vec<_>>(),
+        "peak_high" => zs_list.iter().map(|zs| zs.peak_high).collect::<Vec<_>>(),
+        "is_sure" => zs_list.iter().map(|zs| zs.is_sure).collect::<Vec<_>>(),
+        "sub_zs" => zs_list.iter().map(sub_zs).collect::<Vec<_>>()
+    )?)
+}
+
 /// Get segment list instance based on configuration
-fn get_seglist_instance(seg_config: &SegConfig, lv: SegType) -> Result<Box<dyn SegListComm>, ChanException> {
+///
+/// 笔的线段和线段的线段共用SegListComm，只支持seg_algo=chan
+fn get_seglist_instance<T: Clone + std::fmt::Debug>(seg_config: &SegConfig, lv: SegType) -> Result<SegListComm<T>, ChanException> {
     match seg_config.seg_algo.as_str() {
-        "chan" => {
-            use crate::seg::seg_list_chan::SegListChan;
-            Ok(Box::new(SegListChan::new(seg_config.clone(), lv)))
-        },
-        "1+1" => {
-            println!("Please avoid using seg_algo=1+1 as it is deprecated and no longer maintained.");
-            use crate::seg::seg_list_dyh::SegListDYH;
-            Ok(Box::new(SegListDYH::new(seg_config.clone(), lv)))
-        },
-        "break" => {
-            println!("Please avoid using seg_algo=break as it is deprecated and no longer maintained.");
-            use crate::seg::seg_list_def::SegListDef;
-            Ok(Box::new(SegListDef::new(seg_config.clone(), lv)))
-        },
+        "chan" => Ok(SegListComm::new(Some(seg_config.clone()), Some(lv))),
         _ => Err(ChanException::new(
             format!("unsupport seg algorithm:{}", seg_config.seg_algo),
             ErrCode::ParaError,
         )),
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::test_util::sine_kl_data;
+
+    fn run_sine(cnt: usize) -> Analyzer {
+        let mut analyzer = Analyzer::new("day".to_string(), ChanConfig::new(None).unwrap()).unwrap();
+        for kl_data in sine_kl_data(cnt) {
+            analyzer.add_kl_data(&kl_data.kl_dict, kl_data.time, false).unwrap();
+        }
+        analyzer.cal_seg_and_zs().unwrap();
+        analyzer
+    }
+
+    /// 买卖点的(所在笔或线段序号, 是否买点, 类型)
+    fn bsp_keys<T: LineTrait>(bsp_list: &BSPointList<T>) -> Vec<(usize, bool, String)> {
+        bsp_list
+            .iter()
+            .map(|bsp| {
+                let bsp = bsp.borrow();
+                (bsp.bi.borrow().idx(), bsp.is_buy, bsp.type_to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cal_seg_and_zs_builds_both_levels() {
+        let analyzer = run_sine(1200);
+        let seg_cnt = analyzer.seg_list.len();
+        assert!(seg_cnt >= 3);
+        // 每一笔都有所属线段，最后一个线段之后的笔记为seg_cnt
+        for bi in &analyzer.bi_list.lst {
+            assert!(bi.borrow().seg_idx().is_some_and(|idx| idx <= seg_cnt));
+        }
+        for pair in analyzer.seg_list.lst.windows(2) {
+            assert_eq!(pair[0].borrow().end_bi.borrow().idx() + 1, pair[1].borrow().start_bi.borrow().idx());
+        }
+        // 线段的线段、线段中枢都由线段组成
+        for segseg in analyzer.segseg_list.iter() {
+            assert!(segseg.borrow().end_bi.borrow().idx() < seg_cnt);
+        }
+        for zs in analyzer.segzs_list.iter() {
+            assert!(zs.begin_bi.as_ref().unwrap().borrow().idx() > 0);
+            assert!(zs.end_bi.as_ref().unwrap().borrow().idx() < seg_cnt);
+        }
+        for seg in analyzer.seg_list.iter() {
+            let seg = seg.borrow();
+            for zs in &seg.zs_lst {
+                let begin_idx = zs.begin_bi.as_ref().unwrap().borrow().idx();
+                assert!(seg.start_bi.borrow().idx() <= begin_idx && begin_idx <= seg.end_bi.borrow().idx());
+            }
+        }
+    }
+
+    #[test]
+    fn test_seg_bsp_computed_on_seg_level() {
+        let analyzer = run_sine(1200);
+        assert!(analyzer.bs_point_lst.iter().all(|bsp| !bsp.borrow().is_segbsp));
+        for bsp in analyzer.seg_bs_point_lst.iter() {
+            let bsp = bsp.borrow();
+            assert!(bsp.is_segbsp);
+            // 线段买卖点在线段的末端
+            let seg = analyzer.seg_list[bsp.bi.borrow().idx()].borrow();
+            assert_eq!(seg.get_end_klu().index(), bsp.klu.index());
+            assert_eq!(seg.is_down(), bsp.is_buy);
+        }
+
+        // 与直接用线段和线段的线段计算的结果一致
+        let conf = ChanConfig::new(None).unwrap();
+        let mut seg_bs_point_lst = BSPointList::new_seg(conf.seg_bs_point_conf.clone());
+        seg_bs_point_lst.cal(&analyzer.seg_list.lst, &analyzer.segseg_list).unwrap();
+        assert_eq!(bsp_keys(&analyzer.seg_bs_point_lst), bsp_keys(&seg_bs_point_lst));
+        let mut bs_point_lst = BSPointList::new(conf.bs_point_conf.clone());
+        bs_point_lst.cal(&analyzer.bi_list.lst, &analyzer.seg_list).unwrap();
+        assert_eq!(bsp_keys(&analyzer.bs_point_lst), bsp_keys(&bs_point_lst));
+    }
+
+    #[test]
+    fn test_cal_bsp_off_keeps_structure() {
+        let mut analyzer = Analyzer::new("day".to_string(), ChanConfig::new(None).unwrap()).unwrap();
+        analyzer.cal_bsp = false;
+        for kl_data in sine_kl_data(1200) {
+            analyzer.add_kl_data(&kl_data.kl_dict, kl_data.time, false).unwrap();
+        }
+        analyzer.cal_seg_and_zs().unwrap();
+        assert_eq!(analyzer.seg_list.len(), run_sine(1200).seg_list.len());
+        assert!(analyzer.bs_point_lst.is_empty());
+        assert!(analyzer.seg_bs_point_lst.is_empty());
+    }
+
+    #[cfg(feature = "dataframe")]
+    #[test]
+    fn test_to_dataframes() {
+        let analyzer = run_sine(80);
+        let dataframes = analyzer.to_dataframes().unwrap();
+        assert_eq!(dataframes["klines"].height(), analyzer.kline_list.len());
+        assert!(!analyzer.bi_list.is_empty());
+        assert_eq!(dataframes["bis"].height(), analyzer.bi_list.len());
+        assert_eq!(dataframes.contains_key("segments"), !analyzer.seg_list.is_empty());
+        assert_eq!(dataframes.contains_key("zs"), !analyzer.zs_list.is_empty());
+        if let Some(zs_df) = dataframes.get("zs") {
+            assert_eq!(zs_df.height(), analyzer.zs_list.len());
+            assert_eq!(zs_df.width(), 10);
+            let sub_zs: Vec<String> = zs_df
+                .column("sub_zs")
+                .unwrap()
+                .utf8()
+                .unwrap()
+                .into_iter()
+                .map(|v| v.unwrap_or_default().to_string())
+                .collect();
+            for (zs, sub_zs) in analyzer.zs_list.iter().zip(sub_zs) {
+                assert_eq!(sub_zs.is_empty(), zs.sub_zs_lst.is_empty());
+                assert_eq!(sub_zs.split(',').filter(|s| !s.is_empty()).count(), zs.sub_zs_lst.len());
+            }
+        }
+    }
+
+    #[cfg(feature = "dataframe")]
+    #[test]
+    fn test_empty_analyzer_exports_klines_only() {
+        let analyzer = run_sine(0);
+        let dataframes = analyzer.to_dataframes().unwrap();
+        assert_eq!(dataframes.len(), 1);
+        assert_eq!(dataframes["klines"].height(), 0);
+    }
+
+    #[cfg(feature = "dataframe")]
+    #[test]
+    fn test_to_csv() {
+        let analyzer = run_sine(80);
+        let dir = std::env::temp_dir().join(format!("chan_export_{}", std::process::id()));
+        analyzer.to_csv(dir.to_str().unwrap()).unwrap();
+        for (name, df) in analyzer.to_dataframes().unwrap() {
+            let content = std::fs::read_to_string(dir.join(format!("{}.csv", name))).unwrap();
+            // 表头加每行一条记录
+            assert_eq!(content.lines().count(), df.height() + 1);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
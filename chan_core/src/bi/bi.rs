@@ -1,11 +1,12 @@
 use crate::common::{
-    enums::{BiDir, BiType, DataField, FxType, MacdAlgo},
+    enums::{BiDir, BiType, FxType},
     chan_exception::{ChanException, ErrCode},
-    handle::Handle,
+    handle::{AsHandle, Handle},
 };
 use crate::kline::{kline::KLine, kline_unit::KLineUnit};
 use crate::seg::seg::Seg;
 use crate::bs_point::bs_point::BSPoint;
+use crate::traits::line_trait::LineTrait;
 use crate::impl_handle;
 
 /// 笔结构，表示一段方向明确的走势
@@ -95,6 +96,11 @@ impl Bi {
         self.seg_idx = Some(idx);
     }
 
+    /// Set the reason why this bi was accepted
+    pub fn set_bi_type(&mut self, bi_type: BiType) {
+        self.bi_type = bi_type;
+    }
+
     /// Check if the Bi is valid
     fn check(&self) -> Result<(), ChanException> {
         if self.is_down() {
@@ -219,70 +225,65 @@ impl Bi {
         self.cached_klu_cnt = Some(cnt);
         Ok(cnt)
     }
+}
 
-    /// Calculate MACD metric based on algorithm
-    pub fn cal_macd_metric(&self, macd_algo: MacdAlgo, is_reverse: bool) -> Result<f64, ChanException> {
-        match macd_algo {
-            MacdAlgo::Area => self.cal_macd_half(is_reverse),
-            MacdAlgo::Peak => self.cal_macd_peak(),
-            MacdAlgo::FullArea => self.cal_macd_area(),
-            MacdAlgo::Diff => self.cal_macd_diff(),
-            MacdAlgo::Slope => self.cal_macd_slope(),
-            MacdAlgo::Amp => self.cal_macd_amp(),
-            MacdAlgo::Amount => self.cal_macd_trade_metric(DataField::FieldTurnover, false),
-            MacdAlgo::Volume => self.cal_macd_trade_metric(DataField::FieldVolume, false),
-            MacdAlgo::VolumeAvg => self.cal_macd_trade_metric(DataField::FieldVolume, true),
-            MacdAlgo::AmountAvg => self.cal_macd_trade_metric(DataField::FieldTurnover, true),
-            MacdAlgo::TurnrateAvg => self.cal_macd_trade_metric(DataField::FieldTurnrate, true),
-            MacdAlgo::Rsi => self.cal_rsi(),
-            _ => Err(ChanException::new(
-                format!("unsupport macd_algo={:?}, should be one of area/full_area/peak/diff/slope/amp", macd_algo),
-                ErrCode::ParaError,
-            )),
-        }
+impl_handle!(Bi);
+
+impl LineTrait for Bi {
+    fn get_begin_klu(&self) -> Handle<KLineUnit> {
+        self.begin_klc
+            .get_peak_klu(!self.is_up())
+            .expect("begin klc of bi must have a peak klu")
+            .as_handle()
     }
 
-    /// Calculate RSI
-    fn cal_rsi(&self) -> Result<f64, ChanException> {
-        let mut rsi_lst = Vec::new();
-        for klc in self.klc_lst() {
-            for klu in klc.lst.iter() {
-                rsi_lst.push(klu.rsi);
-            }
-        }
-        Ok(if self.is_down() {
-            10000.0 / (rsi_lst.iter().min_by(|a, b| a.partial_cmp(b).unwrap()).unwrap() + 1e-7)
-        } else {
-            *rsi_lst.iter().max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap()
-        })
+    fn get_end_klu(&self) -> Handle<KLineUnit> {
+        self.end_klc
+            .get_peak_klu(self.is_up())
+            .expect("end klc of bi must have a peak klu")
+            .as_handle()
     }
 
-    /// Calculate MACD area
-    fn cal_macd_area(&self) -> Result<f64, ChanException> {
-        let mut s = 1e-7;
-        for klc in self.klc_lst() {
-            for klu in klc.lst.iter() {
-                s += klu.macd.macd.abs();
-            }
-        }
-        Ok(s)
+    fn _low(&self) -> f64 {
+        if self.is_up() { self.begin_klc.low() } else { self.end_klc.low() }
     }
 
-    /// Calculate MACD peak
-    fn cal_macd_peak(&self) -> Result<f64, ChanException> {
-        let mut peak = 1e-7;
-        for klc in self.klc_lst() {
-            for klu in klc.lst.iter() {
-                if klu.macd.macd.abs() > peak {
-                    peak = klu.macd.macd.abs();
-                }
-            }
-        }
-        Ok(peak)
+    fn _high(&self) -> f64 {
+        if self.is_up() { self.end_klc.high() } else { self.begin_klc.high() }
     }
-}
 
-impl_handle!(Bi);
+    fn idx(&self) -> usize { self.idx }
+
+    fn seg_idx(&self) -> Option<usize> { self.seg_idx }
+
+    fn set_seg_idx(&mut self, idx: usize) { Bi::set_seg_idx(self, idx) }
+
+    fn get_begin_val(&self) -> f64 {
+        if self.is_up() { self.begin_klc.low() } else { self.begin_klc.high() }
+    }
+
+    fn get_end_val(&self) -> f64 {
+        if self.is_up() { self.end_klc.high() } else { self.end_klc.low() }
+    }
+
+    fn is_down(&self) -> bool { self.dir == BiDir::Down }
+
+    fn is_up(&self) -> bool { self.dir == BiDir::Up }
+
+    fn is_sure(&self) -> bool { self.is_sure }
+
+    fn parent_seg_dir(&self) -> Option<BiDir> { self.parent_seg.as_ref().map(|seg| seg.dir) }
+
+    /// 笔的指标覆盖首尾合并K线内的全部KLU
+    fn klu_iter(&self) -> Box<dyn Iterator<Item = Handle<KLineUnit>> + '_> {
+        let end_klc_idx = self.end_klc.idx;
+        Box::new(
+            std::iter::successors(Some(self.begin_klc.as_handle()), |klc| klc.next())
+                .take_while(move |klc| klc.idx <= end_klc_idx)
+                .flat_map(|klc| (0..klc.lst.len()).map(move |i| klc[i].as_handle())),
+        )
+    }
+}
 
 impl std::fmt::Display for Bi {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -1,6 +1,6 @@
 use std::ops::{Index, IndexMut};
 use crate::common::{
-    enums::{FxType, KlineDir},
+    enums::{BiType, FxType, KlineDir},
     chan_exception::{ChanException, ErrCode},
     handle::Handle,
 };
@@ -89,7 +89,7 @@ impl BiList {
         }
         
         let tmp_last_bi = self.bi_list.pop().unwrap();
-        if !self.try_update_end(klc, for_virtual)? {
+        if !self.update_end(klc, for_virtual, true)? {
             self.bi_list.push(tmp_last_bi);
             Ok(false)
         } else {
@@ -132,8 +132,10 @@ impl BiList {
         if !self.bi_list.is_empty() && !self.bi_list.last().unwrap().is_sure() {
             let sure_end_list: Vec<_> = self.bi_list.last().unwrap().sure_end().to_vec();
             if !sure_end_list.is_empty() {
+                let bi_type = self.cal_bi_type(&sure_end_list[0], self.bi_list.last().unwrap().begin_klc())?;
                 let last_bi = self.bi_list.last_mut().unwrap();
                 last_bi.restore_from_virtual_end(&sure_end_list[0])?;
+                last_bi.set_bi_type(bi_type);
                 self.last_end = Some(last_bi.end_klc().clone());
                 
                 for sure_end in &sure_end_list[1..] {
@@ -200,7 +202,9 @@ impl BiList {
 
     /// Add new Bi to the list
     pub fn add_new_bi(&mut self, pre_klc: KLine, cur_klc: KLine, is_sure: bool) -> Result<(), ChanException> {
-        let new_bi = Bi::new(pre_klc, cur_klc, self.bi_list.len(), is_sure, &Box::new(self.bi_list.clone()))?;
+        let bi_type = self.cal_bi_type(&cur_klc, &pre_klc)?;
+        let mut new_bi = Bi::new(pre_klc, cur_klc, self.bi_list.len(), is_sure, &Box::new(self.bi_list.clone()))?;
+        new_bi.set_bi_type(bi_type);
         
         if !self.bi_list.is_empty() {
             let last_bi = self.bi_list.last_mut().unwrap();
@@ -268,6 +272,37 @@ impl BiList {
         Ok(span)
     }
 
+    /// Collect the span information used to classify a Bi from `last_end` to `klc`
+    pub fn cal_bi_span(&self, klc: &KLine, last_end: &KLine) -> Result<BiSpan, ChanException> {
+        let mut klu_cnt = 0;
+        let mut has_gap = false;
+        let mut tmp_klc = Some(last_end.clone());
+        while let Some(current_klc) = tmp_klc {
+            if current_klc.idx() >= klc.idx() {
+                break;
+            }
+            if current_klc.idx() > last_end.idx() {
+                klu_cnt += current_klc.lst.len();
+            }
+            has_gap |= current_klc.has_gap_with_next();
+            tmp_klc = current_klc.next();
+        }
+
+        Ok(BiSpan {
+            end_is_peak: end_is_peak(last_end, klc)?,
+            klc_span: klc.idx() - last_end.idx(),
+            gap_span: self.get_klc_span(klc, last_end)?,
+            klu_cnt,
+            has_gap,
+            pushed: false,
+        })
+    }
+
+    /// Classify why a Bi from `last_end` to `klc` is accepted
+    pub fn cal_bi_type(&self, klc: &KLine, last_end: &KLine) -> Result<BiType, ChanException> {
+        Ok(self.cal_bi_span(klc, last_end)?.bi_type())
+    }
+
     /// Check if can make Bi
     pub fn can_make_bi(&self, klc: &KLine, last_end: &KLine, for_virtual: bool) -> Result<bool, ChanException> {
         let satisfy_span = if self.config.bi_algo == "fx" {
@@ -293,10 +328,18 @@ impl BiList {
 
     /// Try to update end
     pub fn try_update_end(&mut self, klc: &KLine, for_virtual: bool) -> Result<bool, ChanException> {
+        self.update_end(klc, for_virtual, false)
+    }
+
+    /// 延伸最后一笔的尾部到klc，pushed表示是否越过了被推掉的一笔（推笔）
+    fn update_end(&mut self, klc: &KLine, for_virtual: bool, pushed: bool) -> Result<bool, ChanException> {
         if self.bi_list.is_empty() {
             return Ok(false);
         }
         
+        let mut bi_span = self.cal_bi_span(klc, self.bi_list.last().unwrap().begin_klc())?;
+        bi_span.pushed = pushed;
+        let bi_type = bi_span.bi_type();
         let last_bi = self.bi_list.last_mut().unwrap();
         let check_top = if for_virtual {
             klc.dir() == KlineDir::Up
@@ -317,6 +360,7 @@ impl BiList {
             } else {
                 last_bi.update_new_end(klc)?;
             }
+            last_bi.set_bi_type(bi_type);
             self.last_end = Some(klc.clone());
             Ok(true)
         } else {
@@ -330,6 +374,53 @@ impl BiList {
     }
 }
 
+/// 成笔时首尾之间的跨度信息，用于判断笔的类型
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BiSpan {
+    /// 尾部是否为笔内的极值
+    pub end_is_peak: bool,
+    /// 合并后K线的跨度
+    pub klc_span: usize,
+    /// 按gap_as_kl把跳空计为K线后的跨度
+    pub gap_span: usize,
+    /// 首尾之间（不含首尾）原始K线的根数
+    pub klu_cnt: usize,
+    /// 首尾之间是否有跳空
+    pub has_gap: bool,
+    /// 是否由推掉后一笔延伸而来
+    pub pushed: bool,
+}
+
+impl BiSpan {
+    /// 首尾之间原始K线至少这么多根但合并后跨度不足时为大横笔
+    pub const DAHENG_KLU_CNT: usize = 4;
+
+    /// 判断成笔的原因，优先级依次为：
+    /// 尾部不是峰值为SubValue，推掉后一笔延伸而来为Tuibi，
+    /// 合并K线跨度满足严格笔为Strict，靠跳空补足跨度为TiaokongThred，
+    /// 合并后跨度不足但原始K线足够多为Daheng，满足非严格笔跨度为Unstrict，
+    /// 跨度都不满足（fx算法不检查跨度）但有跳空为TiaokongValue，其余为Unstrict
+    pub fn bi_type(&self) -> BiType {
+        if !self.end_is_peak {
+            BiType::SubValue
+        } else if self.pushed {
+            BiType::Tuibi
+        } else if self.klc_span >= 4 {
+            BiType::Strict
+        } else if self.gap_span >= 4 {
+            BiType::TiaokongThred
+        } else if self.klu_cnt >= Self::DAHENG_KLU_CNT {
+            BiType::Daheng
+        } else if self.klc_span >= 3 && self.klu_cnt >= 3 {
+            BiType::Unstrict
+        } else if self.has_gap {
+            BiType::TiaokongValue
+        } else {
+            BiType::Unstrict
+        }
+    }
+}
+
 /// Check if end is peak
 pub fn end_is_peak(last_end: &KLine, cur_end: &KLine) -> Result<bool, ChanException> {
     match last_end.fx() {
@@ -389,4 +480,44 @@ impl std::fmt::Display for BiList {
         }
         Ok(())
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(klc_span: usize, gap_span: usize, klu_cnt: usize, has_gap: bool) -> BiSpan {
+        BiSpan {
+            end_is_peak: true,
+            klc_span,
+            gap_span,
+            klu_cnt,
+            has_gap,
+            pushed: false,
+        }
+    }
+
+    #[test]
+    fn test_bi_type_by_span() {
+        assert_eq!(span(4, 4, 3, false).bi_type(), BiType::Strict);
+        assert_eq!(span(3, 4, 2, true).bi_type(), BiType::TiaokongThred);
+        assert_eq!(span(3, 3, 5, false).bi_type(), BiType::Daheng);
+        assert_eq!(span(3, 3, 3, false).bi_type(), BiType::Unstrict);
+        assert_eq!(span(2, 2, 1, true).bi_type(), BiType::TiaokongValue);
+        assert_eq!(span(2, 2, 1, false).bi_type(), BiType::Unstrict);
+    }
+
+    #[test]
+    fn test_bi_type_sub_value_and_tuibi() {
+        let sub_value = BiSpan { end_is_peak: false, pushed: true, ..span(4, 4, 3, false) };
+        assert_eq!(sub_value.bi_type(), BiType::SubValue);
+        let tuibi = BiSpan { pushed: true, ..span(4, 4, 3, false) };
+        assert_eq!(tuibi.bi_type(), BiType::Tuibi);
+    }
+
+    #[test]
+    fn test_strict_span_wins_over_gap_and_daheng() {
+        assert_eq!(span(5, 6, 8, true).bi_type(), BiType::Strict);
+        assert_eq!(span(3, 4, 6, true).bi_type(), BiType::TiaokongThred);
+    }
+}
@@ -1,21 +1,10 @@
 use std::collections::HashMap;
-use strum_macros::{Display, EnumString};
 use crate::chan_model::features::Features;
+use crate::common::cenum::BspType;
 use crate::common::handle::Handle;
 use crate::kline::kline_unit::KLineUnit;
 use crate::traits::line_trait::LineTrait;
 
-#[derive(Debug, Clone, Display, EnumString, PartialEq)]
-pub enum BspType {
-    #[strum(serialize = "BS1")]
-    BS1,
-    #[strum(serialize = "BS2")]
-    BS2,
-    #[strum(serialize = "BS3")]
-    BS3,
-    // ... 其他买卖点类型
-}
-
 pub struct BSPoint<T: LineTrait> {
     /// The bi/seg this point belongs to
     pub bi: Handle<T>,
@@ -35,6 +24,12 @@ pub struct BSPoint<T: LineTrait> {
     /// Features of this point
     pub features: Features,
     
+    /// Outcome labels computed from the bars after this point, kept apart from features
+    pub labels: Features,
+    
+    /// Probability given by the registered BspScorer
+    pub score: Option<f64>,
+    
     /// Whether this is a segment buy/sell point
     pub is_segbsp: bool,
 }
@@ -57,6 +52,8 @@ impl<T: LineTrait> BSPoint<T> {
             bs_type: vec![bs_type],
             relate_bsp1,
             features,
+            labels: Features::default(),
+            score: None,
             is_segbsp: false,
         };
         
@@ -98,22 +95,14 @@ impl<T: LineTrait> BSPoint<T> {
         }
     }
 
-    pub fn add_feat<K>(&mut self, inp1: K, inp2: Option<f64>)
-    where
-        K: Into<Features> + std::fmt::Debug,
-    {
-        self.features.add_feat(inp1, inp2);
+    pub fn add_feat<I: IntoIterator<Item = (String, f64)>>(&mut self, feats: I) {
+        self.features.add_feat(feats);
     }
 
     pub fn init_common_feature(&mut self) {
         // Initialize features that apply to all buy/sell points
-        let mut common_features = HashMap::new();
-        common_features.insert(
-            "bsp_bi_amp".to_string(),
-            self.bi.borrow().amp(),
-        );
-        
-        self.add_feat(common_features, None);
+        let amp = self.bi.borrow().amp();
+        self.features.add("bsp_bi_amp", amp);
     }
 }
 
@@ -126,6 +115,8 @@ impl<T: LineTrait> Clone for BSPoint<T> {
             bs_type: self.bs_type.clone(),
             relate_bsp1: self.relate_bsp1.clone(),
             features: self.features.clone(),
+            labels: self.labels.clone(),
+            score: self.score,
             is_segbsp: self.is_segbsp,
         }
     }
@@ -1,35 +1,7 @@
 use std::collections::HashMap;
-use strum_macros::{Display, EnumString};
-use crate::common::enums::BspType;
-use crate::common::utils::parse_inf;
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
-pub enum MacdAlgo {
-    #[strum(serialize = "area")]
-    Area,
-    #[strum(serialize = "peak")]
-    Peak,
-    #[strum(serialize = "full_area")]
-    FullArea,
-    #[strum(serialize = "diff")]
-    Diff,
-    #[strum(serialize = "slope")]
-    Slope,
-    #[strum(serialize = "amp")]
-    Amp,
-    #[strum(serialize = "amount")]
-    Amount,
-    #[strum(serialize = "volumn")]
-    Volumn,
-    #[strum(serialize = "amount_avg")]
-    AmountAvg,
-    #[strum(serialize = "volumn_avg")]
-    VolumnAvg,
-    #[strum(serialize = "turnrate_avg")]
-    TurnrateAvg,
-    #[strum(serialize = "rsi")]
-    Rsi,
-}
+use std::str::FromStr;
+use crate::common::cenum::{BspType, MacdAlgo};
+use crate::common::chan_exception::{ChanException, ErrCode};
 
 #[derive(Debug, Clone)]
 pub struct BSPointConfig {
@@ -38,10 +10,26 @@ pub struct BSPointConfig {
 }
 
 impl BSPointConfig {
-    pub fn new(args: HashMap<String, String>) -> Self {
-        let b_conf = PointConfig::new(&args);
-        let s_conf = PointConfig::new(&args);
-        Self { b_conf, s_conf }
+    pub fn new(args: HashMap<String, String>) -> Result<Self, ChanException> {
+        let b_conf = PointConfig::new(&args)?;
+        let s_conf = PointConfig::new(&args)?;
+        Ok(Self { b_conf, s_conf })
+    }
+
+    /// 从json配置生成，null视为未设置，字符串取其内容
+    pub fn from_args(args: &HashMap<String, serde_json::Value>) -> Result<Self, ChanException> {
+        let args = args
+            .iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| {
+                let v = match v {
+                    serde_json::Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                };
+                (k.clone(), v)
+            })
+            .collect();
+        Self::new(args)
     }
 
     pub fn get_bs_config(&self, is_buy: bool) -> &PointConfig {
@@ -49,6 +37,12 @@ impl BSPointConfig {
     }
 }
 
+impl Default for BSPointConfig {
+    fn default() -> Self {
+        Self::new(HashMap::new()).expect("default bsp config is valid")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PointConfig {
     pub divergence_rate: f64,
@@ -67,58 +61,48 @@ pub struct PointConfig {
     pub strict_bsp3: bool,
 }
 
+/// 解析单个参数，失败时返回ParaError
+fn parse_para<T: FromStr>(key: &str, value: &str) -> Result<T, ChanException> {
+    value.parse().map_err(|_| {
+        ChanException::new(format!("invalid value for {}: {}", key, value), ErrCode::ParaError)
+    })
+}
+
 impl PointConfig {
-    pub fn new(args: &HashMap<String, String>) -> Self {
+    pub fn new(args: &HashMap<String, String>) -> Result<Self, ChanException> {
         let mut config = Self {
-            divergence_rate: args.get("divergence_rate").and_then(|v| v.parse().ok()).unwrap_or(0.0),
-            min_zs_cnt: args.get("min_zs_cnt").and_then(|v| v.parse().ok()).unwrap_or(0),
-            bsp1_only_multibi_zs: args.get("bsp1_only_multibi_zs").and_then(|v| v.parse().ok()).unwrap_or(false),
-            max_bs2_rate: args.get("max_bs2_rate").and_then(|v| v.parse().ok()).unwrap_or(1.0),
+            divergence_rate: 0.0,
+            min_zs_cnt: 0,
+            bsp1_only_multibi_zs: false,
+            max_bs2_rate: 1.0,
             macd_algo: MacdAlgo::Area,
-            bs1_peak: args.get("bs1_peak").and_then(|v| v.parse().ok()).unwrap_or(false),
+            bs1_peak: false,
             tmp_target_types: vec![],
             target_types: vec![],
-            bsp2_follow_1: args.get("bsp2_follow_1").and_then(|v| v.parse().ok()).unwrap_or(false),
-            bsp3_follow_1: args.get("bsp3_follow_1").and_then(|v| v.parse().ok()).unwrap_or(false),
-            bsp3_peak: args.get("bsp3_peak").and_then(|v| v.parse().ok()).unwrap_or(false),
-            bsp2s_follow_2: args.get("bsp2s_follow_2").and_then(|v| v.parse().ok()).unwrap_or(false),
-            max_bsp2s_lv: args.get("max_bsp2s_lv").and_then(|v| v.parse().ok()),
-            strict_bsp3: args.get("strict_bsp3").and_then(|v| v.parse().ok()).unwrap_or(false),
+            bsp2_follow_1: false,
+            bsp3_follow_1: false,
+            bsp3_peak: false,
+            bsp2s_follow_2: false,
+            max_bsp2s_lv: None,
+            strict_bsp3: false,
         };
-
-        if let Some(macd_algo) = args.get("macd_algo") {
-            config.set_macd_algo(macd_algo);
-        }
-
-        if let Some(bs_type) = args.get("bs_type") {
-            config.tmp_target_types = bs_type.split(',').map(|s| s.trim().to_string()).collect();
-            config.parse_target_type();
+        for (key, value) in args {
+            config.set(key, value)?;
         }
-
-        assert!(config.max_bs2_rate <= 1.0);
-        config
+        config.parse_target_type()?;
+        Ok(config)
     }
 
-    pub fn parse_target_type(&mut self) {
-        let valid_types = ["1", "2", "3a", "2s", "1p", "3b"];
-        for target_t in &self.tmp_target_types {
-            assert!(valid_types.contains(&target_t.as_str()));
-        }
-
-        self.target_types = self.tmp_target_types.iter()
-            .map(|t| match t.as_str() {
-                "1" => BspType::BS1,
-                "2" => BspType::BS2,
-                "3a" => BspType::BS3,
-                "2s" => BspType::BS2Strict,
-                "1p" => BspType::BS1Peak,
-                "3b" => BspType::BS3Peak,
-                _ => unreachable!(),
-            })
-            .collect();
+    pub fn parse_target_type(&mut self) -> Result<(), ChanException> {
+        self.target_types = self
+            .tmp_target_types
+            .iter()
+            .map(|t| parse_para("bs_type", t))
+            .collect::<Result<_, _>>()?;
+        Ok(())
     }
 
-    pub fn set_macd_algo(&mut self, algo: &str) {
+    pub fn set_macd_algo(&mut self, algo: &str) -> Result<(), ChanException> {
         self.macd_algo = match algo {
             "area" => MacdAlgo::Area,
             "peak" => MacdAlgo::Peak,
@@ -132,26 +116,123 @@ impl PointConfig {
             "volumn_avg" => MacdAlgo::VolumnAvg,
             "turnrate_avg" => MacdAlgo::TurnrateAvg,
             "rsi" => MacdAlgo::Rsi,
-            _ => panic!("Unknown MACD algorithm: {}", algo),
+            "atr_amp" => MacdAlgo::AtrAmp,
+            "obv" => MacdAlgo::Obv,
+            _ => {
+                return Err(ChanException::new(
+                    format!("unknown macd_algo: {}", algo),
+                    ErrCode::ParaError,
+                ))
+            }
         };
+        Ok(())
     }
 
-    pub fn set(&mut self, key: &str, value: &str) {
-        let value = parse_inf(value);
+    /// 设置单个参数，字符串值可以带引号，max_bsp2s_lv为null时不限制；
+    /// 设置bs_type后需调用parse_target_type
+    pub fn set(&mut self, key: &str, value: impl ToString) -> Result<(), ChanException> {
+        let value = value.to_string();
+        let value = value.trim_matches('"');
         match key {
-            "macd_algo" => self.set_macd_algo(value),
-            "divergence_rate" => self.divergence_rate = value.parse().unwrap(),
-            "min_zs_cnt" => self.min_zs_cnt = value.parse().unwrap(),
-            "bsp1_only_multibi_zs" => self.bsp1_only_multibi_zs = value.parse().unwrap(),
-            "max_bs2_rate" => self.max_bs2_rate = value.parse().unwrap(),
-            "bs1_peak" => self.bs1_peak = value.parse().unwrap(),
-            "bsp2_follow_1" => self.bsp2_follow_1 = value.parse().unwrap(),
-            "bsp3_follow_1" => self.bsp3_follow_1 = value.parse().unwrap(),
-            "bsp3_peak" => self.bsp3_peak = value.parse().unwrap(),
-            "bsp2s_follow_2" => self.bsp2s_follow_2 = value.parse().unwrap(),
-            "max_bsp2s_lv" => self.max_bsp2s_lv = Some(value.parse().unwrap()),
-            "strict_bsp3" => self.strict_bsp3 = value.parse().unwrap(),
-            _ => panic!("Unknown config key: {}", key),
+            "macd_algo" => self.set_macd_algo(value)?,
+            "divergence_rate" => self.divergence_rate = parse_para(key, value)?,
+            "min_zs_cnt" => self.min_zs_cnt = parse_para(key, value)?,
+            "bsp1_only_multibi_zs" => self.bsp1_only_multibi_zs = parse_para(key, value)?,
+            "max_bs2_rate" => {
+                let max_bs2_rate: f64 = parse_para(key, value)?;
+                if max_bs2_rate > 1.0 {
+                    return Err(ChanException::new(
+                        format!("max_bs2_rate must not exceed 1, got {}", max_bs2_rate),
+                        ErrCode::ParaError,
+                    ));
+                }
+                self.max_bs2_rate = max_bs2_rate;
+            }
+            "bs1_peak" => self.bs1_peak = parse_para(key, value)?,
+            "bs_type" => self.tmp_target_types = value.split(',').map(|s| s.trim().to_string()).collect(),
+            "bsp2_follow_1" => self.bsp2_follow_1 = parse_para(key, value)?,
+            "bsp3_follow_1" => self.bsp3_follow_1 = parse_para(key, value)?,
+            "bsp3_peak" => self.bsp3_peak = parse_para(key, value)?,
+            "bsp2s_follow_2" => self.bsp2s_follow_2 = parse_para(key, value)?,
+            "max_bsp2s_lv" => {
+                self.max_bsp2s_lv = if value == "null" { None } else { Some(parse_para(key, value)?) }
+            }
+            "strict_bsp3" => self.strict_bsp3 = parse_para(key, value)?,
+            _ => {
+                return Err(ChanException::new(
+                    format!("unknown bsp para: {}", key),
+                    ErrCode::ParaError,
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(items: &[(&str, &str)]) -> HashMap<String, String> {
+        items.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_new_parses_args() {
+        let conf = BSPointConfig::new(args(&[
+            ("bs_type", "1,2s, 3a"),
+            ("macd_algo", "atr_amp"),
+            ("divergence_rate", "inf"),
+            ("max_bsp2s_lv", "2"),
+        ]))
+        .unwrap();
+        assert_eq!(conf.b_conf.target_types, vec![BspType::T1, BspType::T2S, BspType::T3A]);
+        assert_eq!(conf.s_conf.macd_algo, MacdAlgo::AtrAmp);
+        assert_eq!(conf.b_conf.divergence_rate, f64::INFINITY);
+        assert_eq!(conf.b_conf.max_bsp2s_lv, Some(2));
+    }
+
+    #[test]
+    fn test_bad_values_are_para_error() {
+        for items in [
+            [("bs_type", "1,4")],
+            [("macd_algo", "macd")],
+            [("divergence_rate", "high")],
+            [("max_bs2_rate", "1.5")],
+            [("bsp2_follow_1", "yes")],
+            [("no_such_para", "1")],
+        ] {
+            let err = BSPointConfig::new(args(&items)).unwrap_err();
+            assert_eq!(err.errcode, ErrCode::ParaError, "{:?}", items);
         }
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_from_args_json_values() {
+        let json_args: HashMap<String, serde_json::Value> = serde_json::from_value(serde_json::json!({
+            "macd_algo": "peak",
+            "min_zs_cnt": 2,
+            "bs1_peak": true,
+            "max_bsp2s_lv": null,
+        }))
+        .unwrap();
+        let conf = BSPointConfig::from_args(&json_args).unwrap();
+        assert_eq!(conf.b_conf.macd_algo, MacdAlgo::Peak);
+        assert_eq!(conf.b_conf.min_zs_cnt, 2);
+        assert!(conf.b_conf.bs1_peak);
+        assert_eq!(conf.b_conf.max_bsp2s_lv, None);
+    }
+
+    #[test]
+    fn test_set_quoted_and_null() {
+        let mut conf = PointConfig::new(&HashMap::new()).unwrap();
+        conf.set("macd_algo", "\"slope\"").unwrap();
+        conf.set("bsp1_only_multibi_zs", false).unwrap();
+        conf.set("max_bsp2s_lv", 3).unwrap();
+        conf.set("max_bsp2s_lv", "null").unwrap();
+        assert_eq!(conf.macd_algo, MacdAlgo::Slope);
+        assert!(!conf.bsp1_only_multibi_zs);
+        assert_eq!(conf.max_bsp2s_lv, None);
+        assert_eq!(conf.set("min_zs_cnt", "two").unwrap_err().errcode, ErrCode::ParaError);
+    }
+}
@@ -1,7 +1,9 @@
 use std::collections::HashMap;
-use crate::bi::bi::Bi;
-use crate::bi::bi_list::BiList;
-use crate::common::enums::BspType;
+use std::sync::Arc;
+use crate::chan_model::feature_extractor::{default_feature_extractors, FeatureContext, FeatureExtractor};
+use crate::chan_model::scorer::BspScorer;
+use crate::common::chan_exception::ChanException;
+use crate::common::cenum::BspType;
 use crate::common::handle::Handle;
 use crate::common::utils::has_overlap;
 use crate::seg::seg::Seg;
@@ -17,6 +19,12 @@ pub struct BSPointList<T: LineTrait> {
     pub bsp1_lst: Vec<Handle<BSPoint<T>>>,
     pub config: BSPointConfig,
     pub last_sure_pos: isize,
+    /// 买卖点生成时依次调用的特征提取器
+    pub feature_extractors: Vec<Arc<dyn FeatureExtractor<T>>>,
+    /// 特征计算完后给买卖点打分，结果写入`BSPoint.score`
+    pub scorer: Option<Arc<dyn BspScorer>>,
+    /// 是否为线段级别的买卖点列表，决定生成的买卖点的is_segbsp
+    pub is_seg: bool,
 }
 
 impl<T: LineTrait> BSPointList<T> {
@@ -27,9 +35,29 @@ impl<T: LineTrait> BSPointList<T> {
             bsp1_lst: Vec::new(),
             config: bs_point_config,
             last_sure_pos: -1,
+            feature_extractors: default_feature_extractors(),
+            scorer: None,
+            is_seg: false,
         }
     }
 
+    /// 线段级别的买卖点列表，生成的买卖点is_segbsp为true
+    pub fn new_seg(bs_point_config: BSPointConfig) -> Self {
+        Self {
+            is_seg: true,
+            ..Self::new(bs_point_config)
+        }
+    }
+
+    /// 注册自定义特征提取器，在默认提取器之后调用
+    pub fn register_feature_extractor(&mut self, extractor: Arc<dyn FeatureExtractor<T>>) {
+        self.feature_extractors.push(extractor);
+    }
+
+    pub fn set_scorer(&mut self, scorer: Arc<dyn BspScorer>) {
+        self.scorer = Some(scorer);
+    }
+
     pub fn len(&self) -> usize {
         self.lst.len()
     }
@@ -46,18 +74,51 @@ impl<T: LineTrait> BSPointList<T> {
         self.lst.get(index)
     }
 
-    pub fn cal(&mut self, bi_list: &BiList<T>, seg_list: &SegListComm<T>) {
+    pub fn cal(&mut self, bi_list: &[Handle<T>], seg_list: &SegListComm<T>) -> Result<(), ChanException> {
         self.lst.retain(|bsp| bsp.borrow().klu.borrow().idx as isize <= self.last_sure_pos);
         self.bsp_dict = self.lst.iter()
             .map(|bsp| (bsp.borrow().bi.borrow().get_end_klu().borrow().idx, bsp.clone()))
             .collect();
         self.bsp1_lst.retain(|bsp| bsp.borrow().klu.borrow().idx as isize <= self.last_sure_pos);
 
-        self.cal_seg_bs1point(seg_list, bi_list);
+        self.cal_seg_bs1point(seg_list, bi_list)?;
         self.cal_seg_bs2point(seg_list, bi_list);
         self.cal_seg_bs3point(seg_list, bi_list);
+        self.extract_features(bi_list, seg_list);
+        self.score_bsps()?;
 
         self.update_last_pos(seg_list);
+        Ok(())
+    }
+
+    /// 对本轮新生成的买卖点（last_sure_pos之后）计算特征
+    fn extract_features(&self, bi_list: &[Handle<T>], seg_list: &SegListComm<T>) {
+        for bsp in self.lst.iter().filter(|bsp| bsp.borrow().klu.borrow().idx as isize > self.last_sure_pos) {
+            let feats: Vec<(String, f64)> = {
+                let ctx = FeatureContext {
+                    bsp: bsp.borrow(),
+                    bi_list,
+                    seg_list,
+                };
+                self.feature_extractors
+                    .iter()
+                    .flat_map(|extractor| extractor.extract(&ctx))
+                    .collect()
+            };
+            bsp.borrow_mut().add_feat(feats);
+        }
+    }
+
+    /// 与特征一样只对本轮新生成的买卖点打分
+    fn score_bsps(&self) -> Result<(), ChanException> {
+        let Some(scorer) = &self.scorer else {
+            return Ok(());
+        };
+        for bsp in self.lst.iter().filter(|bsp| bsp.borrow().klu.borrow().idx as isize > self.last_sure_pos) {
+            let score = scorer.score(&bsp.borrow().features)?;
+            bsp.borrow_mut().score = Some(score);
+        }
+        Ok(())
     }
 
     pub fn update_last_pos(&mut self, seg_list: &SegListComm<T>) {
@@ -79,7 +140,7 @@ impl<T: LineTrait> BSPointList<T> {
         bs_type: BspType,
         bi: Handle<T>,
         relate_bsp1: Option<Handle<BSPoint<T>>>,
-        is_target_bsp: bool,
+        mut is_target_bsp: bool,
         feature_dict: Option<HashMap<String, f64>>,
     ) {
         let is_buy = bi.borrow().is_down();
@@ -89,7 +150,7 @@ impl<T: LineTrait> BSPointList<T> {
             assert_eq!(exist_bsp.borrow().is_buy, is_buy);
             exist_bsp.borrow_mut().add_another_bsp_prop(bs_type, relate_bsp1);
             if let Some(features) = feature_dict {
-                exist_bsp.borrow_mut().add_feat(features, None);
+                exist_bsp.borrow_mut().add_feat(features);
             }
             return;
         }
@@ -98,327 +159,408 @@ impl<T: LineTrait> BSPointList<T> {
             is_target_bsp = false;
         }
 
-        if is_target_bsp || matches!(bs_type, BspType::BS1 | BspType::BS1Peak) {
-            let bsp = Handle::new(BSPoint::new(
-                bi.clone(),
-                is_buy,
-                bs_type,
-                relate_bsp1,
-                feature_dict,
-            ));
+        if is_target_bsp || matches!(bs_type, BspType::T1 | BspType::T1P) {
+            let mut bsp = BSPoint::new(bi.clone(), is_buy, bs_type, relate_bsp1, feature_dict);
+            bsp.is_segbsp = self.is_seg;
+            let bsp = Handle::new(bsp);
 
             if is_target_bsp {
                 self.lst.push(bsp.clone());
                 self.bsp_dict.insert(end_klu_idx, bsp.clone());
             }
-            if matches!(bs_type, BspType::BS1 | BspType::BS1Peak) {
+            if matches!(bs_type, BspType::T1 | BspType::T1P) {
                 self.bsp1_lst.push(bsp);
             }
         }
     }
 
-    pub fn cal_seg_bs1point(&mut self, seg_list: &SegListComm<T>, bi_list: &BiList<T>) {
+    pub fn cal_seg_bs1point(&mut self, seg_list: &SegListComm<T>, bi_list: &[Handle<T>]) -> Result<(), ChanException> {
         for seg in seg_list.iter() {
             if !self.seg_need_cal(seg) {
                 continue;
             }
-            self.cal_single_bs1point(seg, bi_list);
+            self.cal_single_bs1point(seg, bi_list)?;
         }
+        Ok(())
     }
 
-    pub fn cal_single_bs1point(&mut self, seg: &Handle<Seg<T>>, bi_list: &BiList<T>) {
+    pub fn cal_single_bs1point(&mut self, seg: &Handle<Seg<T>>, bi_list: &[Handle<T>]) -> Result<(), ChanException> {
         let seg_ref = seg.borrow();
-        let bsp_conf = self.config.get_bs_config(seg_ref.is_down());
-        
+        let bsp_conf = self.config.get_bs_config(seg_ref.is_down()).clone();
+
         let zs_cnt = if bsp_conf.bsp1_only_multibi_zs {
             seg_ref.get_multi_bi_zs_cnt()
         } else {
             seg_ref.zs_lst.len()
         };
-        
         let is_target_bsp = bsp_conf.min_zs_cnt <= 0 || zs_cnt >= bsp_conf.min_zs_cnt as usize;
 
-        if !seg_ref.zs_lst.is_empty() {
-            let last_zs = &seg_ref.zs_lst.last().unwrap();
-            let valid_last_zs = !last_zs.is_one_bi_zs() && 
-                ((last_zs.bi_out.is_some() && last_zs.bi_out.as_ref().unwrap().borrow().idx >= seg_ref.end_bi.borrow().idx) ||
-                last_zs.bi_lst.last().unwrap().borrow().idx >= seg_ref.end_bi.borrow().idx) &&
-                seg_ref.end_bi.borrow().idx - last_zs.get_bi_in().borrow().idx > 2;
+        let end_bi_idx = seg_ref.end_bi.borrow().idx();
+        let valid_last_zs = seg_ref.zs_lst.last().map_or(false, |last_zs| {
+            !last_zs.is_one_bi_zs()
+                && (last_zs.bi_out.as_ref().map_or(false, |bi_out| bi_out.borrow().idx() >= end_bi_idx)
+                    || last_zs.bi_lst.last().unwrap().borrow().idx() >= end_bi_idx)
+                && end_bi_idx - last_zs.get_bi_in().borrow().idx() > 2
+        });
 
-            if valid_last_zs {
-                self.treat_bsp1(seg, bsp_conf, is_target_bsp);
-            } else {
-                self.treat_pz_bsp1(seg, bsp_conf, bi_list, is_target_bsp);
-            }
+        if valid_last_zs {
+            self.treat_bsp1(seg, &bsp_conf, is_target_bsp)
+        } else {
+            self.treat_pz_bsp1(seg, &bsp_conf, bi_list, is_target_bsp)
         }
     }
 
-    fn treat_bsp1(&mut self, seg: &Handle<Seg<T>>, bsp_conf: &PointConfig, mut is_target_bsp: bool) {
+    /// 趋势背驰一类买卖点：最后一个中枢的出中枢笔与进中枢笔比较
+    fn treat_bsp1(&mut self, seg: &Handle<Seg<T>>, bsp_conf: &PointConfig, mut is_target_bsp: bool) -> Result<(), ChanException> {
         let seg_ref = seg.borrow();
         let last_zs = seg_ref.zs_lst.last().unwrap();
-        let (break_peak, _) = last_zs.out_bi_is_peak(seg_ref.end_bi.borrow().idx);
-        
+        let (break_peak, _) = last_zs.out_bi_is_peak(seg_ref.end_bi.borrow().idx());
         if bsp_conf.bs1_peak && !break_peak {
             is_target_bsp = false;
         }
-        
-        let (is_diver, divergence_rate) = last_zs.is_divergence(bsp_conf, Some(&seg_ref.end_bi));
+
+        let (is_diver, divergence_rate) = last_zs.is_divergence(bsp_conf, Some(&seg_ref.end_bi))?;
         if !is_diver {
             is_target_bsp = false;
         }
 
         let mut feature_dict = HashMap::new();
-        feature_dict.insert("bsp1_zs_height".to_string(), (last_zs.high.unwrap() - last_zs.low.unwrap()) / last_zs.low.unwrap());
-        feature_dict.insert("bsp1_bi_amp".to_string(), seg_ref.end_bi.borrow().amp());
         if let Some(rate) = divergence_rate {
-            feature_dict.insert("bsp1_divergence_rate".to_string(), rate);
+            feature_dict.insert("divergence_rate".to_string(), rate);
         }
+        feature_dict.insert("zs_cnt".to_string(), seg_ref.zs_lst.len() as f64);
 
         self.add_bs(
-            if bsp_conf.bs1_peak { BspType::BS1Peak } else { BspType::BS1 },
+            BspType::T1,
             seg_ref.end_bi.clone(),
             None,
             is_target_bsp,
             Some(feature_dict),
         );
+        Ok(())
     }
 
+    /// 盘整背驰一类买卖点：线段最后一笔与同向前一笔比较
     fn treat_pz_bsp1(
         &mut self,
         seg: &Handle<Seg<T>>,
         bsp_conf: &PointConfig,
-        bi_list: &BiList<T>,
-        is_target_bsp: bool,
-    ) {
+        bi_list: &[Handle<T>],
+        mut is_target_bsp: bool,
+    ) -> Result<(), ChanException> {
         let seg_ref = seg.borrow();
-        if seg_ref.bi_list.len() < 3 {
-            return;
+        let last_bi = seg_ref.end_bi.borrow();
+        if last_bi.idx() < 2 {
+            return Ok(());
+        }
+        let pre_bi = bi_list[last_bi.idx() - 2].borrow();
+        if last_bi.seg_idx() != pre_bi.seg_idx() {
+            return Ok(());
+        }
+        if last_bi.is_down() != seg_ref.is_down() {
+            return Ok(());
+        }
+        if last_bi.is_down() && last_bi._low() > pre_bi._low() {
+            // 创新低
+            return Ok(());
+        }
+        if last_bi.is_up() && last_bi._high() < pre_bi._high() {
+            // 创新高
+            return Ok(());
+        }
+
+        let in_metric = pre_bi.cal_macd_metric(bsp_conf.macd_algo, false)?;
+        let out_metric = last_bi.cal_macd_metric(bsp_conf.macd_algo, true)?;
+        let is_diver = out_metric <= bsp_conf.divergence_rate * in_metric;
+        let divergence_rate = out_metric / (in_metric + 1e-7);
+        if !is_diver {
+            is_target_bsp = false;
         }
 
         let mut feature_dict = HashMap::new();
-        feature_dict.insert("bsp1_bi_amp".to_string(), seg_ref.end_bi.borrow().amp());
+        feature_dict.insert("divergence_rate".to_string(), divergence_rate);
+        feature_dict.insert("bsp1_bi_amp".to_string(), last_bi.amp());
 
         self.add_bs(
-            if bsp_conf.bs1_peak { BspType::BS1Peak } else { BspType::BS1 },
+            BspType::T1P,
             seg_ref.end_bi.clone(),
             None,
             is_target_bsp,
             Some(feature_dict),
         );
+        Ok(())
     }
 
-    pub fn cal_seg_bs2point(&mut self, seg_list: &SegListComm<T>, bi_list: &BiList<T>) {
-        let mut bsp1_bi_idx_dict = HashMap::new();
-        for bsp in &self.bsp1_lst {
-            bsp1_bi_idx_dict.insert(bsp.borrow().bi.borrow().idx, bsp.clone());
-        }
+    pub fn cal_seg_bs2point(&mut self, seg_list: &SegListComm<T>, bi_list: &[Handle<T>]) {
+        let bsp1_bi_idx_dict: HashMap<usize, Handle<BSPoint<T>>> = self.bsp1_lst.iter()
+            .map(|bsp| (bsp.borrow().bi.borrow().idx(), bsp.clone()))
+            .collect();
 
-        for (i, seg) in seg_list.iter().enumerate() {
-            if !self.seg_need_cal(seg) {
+        for seg in seg_list.iter() {
+            let config = self.config.get_bs_config(seg.borrow().is_down());
+            if !config.target_types.contains(&BspType::T2) && !config.target_types.contains(&BspType::T2S) {
                 continue;
             }
-            self.cal_single_bs2point(seg, bi_list, &bsp1_bi_idx_dict);
-            if self.config.b_conf.bsp2s_follow_2 || self.config.s_conf.bsp2s_follow_2 {
-                self.cal_single_bs2s_point(seg, bi_list, &bsp1_bi_idx_dict);
-            }
+            self.cal_single_bs2point(seg, &bsp1_bi_idx_dict, seg_list, bi_list);
         }
     }
 
+    /// 二类买卖点：一类买卖点之后第一次回调不破（回撤不超过max_bs2_rate）
     fn cal_single_bs2point(
         &mut self,
         seg: &Handle<Seg<T>>,
-        bi_list: &BiList<T>,
         bsp1_bi_idx_dict: &HashMap<usize, Handle<BSPoint<T>>>,
+        seg_list: &SegListComm<T>,
+        bi_list: &[Handle<T>],
     ) {
-        let seg_ref = seg.borrow();
-        let bsp_conf = self.config.get_bs_config(seg_ref.is_down());
-        
-        if !bsp_conf.bsp2_follow_1 {
+        if !self.seg_need_cal(seg) {
             return;
         }
+        let seg_ref = seg.borrow();
 
-        let mut last_bsp1_bi_idx = None;
-        for zs in &seg_ref.zs_lst {
-            if let Some(bi_out) = &zs.bi_out {
-                let bi_out_idx = bi_out.borrow().idx;
-                if bsp1_bi_idx_dict.contains_key(&bi_out_idx) {
-                    last_bsp1_bi_idx = Some(bi_out_idx);
-                }
+        let (bsp_conf, bsp1_bi_idx, real_bsp1, break_bi, bsp2_bi) = if seg_list.len() > 1 {
+            let bsp1_bi_idx = seg_ref.end_bi.borrow().idx();
+            if bsp1_bi_idx + 2 >= bi_list.len() {
+                return;
+            }
+            (
+                self.config.get_bs_config(seg_ref.is_down()).clone(),
+                Some(bsp1_bi_idx),
+                bsp1_bi_idx_dict.get(&bsp1_bi_idx).cloned(),
+                bi_list[bsp1_bi_idx + 1].clone(),
+                bi_list[bsp1_bi_idx + 2].clone(),
+            )
+        } else {
+            if bi_list.len() <= 1 {
+                return;
             }
+            (
+                self.config.get_bs_config(seg_ref.is_up()).clone(),
+                None,
+                None,
+                bi_list[0].clone(),
+                bi_list[1].clone(),
+            )
+        };
+
+        // check bsp2_follow_1
+        if bsp_conf.bsp2_follow_1
+            && !bsp1_bi_idx.map_or(false, |idx| self.bsp_dict.values().any(|bsp| bsp.borrow().bi.borrow().idx() == idx))
+        {
+            return;
         }
 
-        if let Some(last_idx) = last_bsp1_bi_idx {
-            let real_bsp1 = bsp1_bi_idx_dict.get(&last_idx).unwrap();
+        let retrace_rate = bsp2_bi.borrow().amp() / break_bi.borrow().amp();
+        if retrace_rate <= bsp_conf.max_bs2_rate {
             let mut feature_dict = HashMap::new();
-            feature_dict.insert("bsp2_bi_amp".to_string(), seg_ref.end_bi.borrow().amp());
-            
+            feature_dict.insert("bsp2_retrace_rate".to_string(), retrace_rate);
+            feature_dict.insert("bsp2_break_bi_amp".to_string(), break_bi.borrow().amp());
+            feature_dict.insert("bsp2_bi_amp".to_string(), bsp2_bi.borrow().amp());
+
             self.add_bs(
-                BspType::BS2,
-                seg_ref.end_bi.clone(),
-                Some(real_bsp1.clone()),
+                BspType::T2,
+                bsp2_bi.clone(),
+                real_bsp1.clone(),
                 true,
                 Some(feature_dict),
             );
+        } else if bsp_conf.bsp2s_follow_2 {
+            return;
         }
+
+        if !self.config.get_bs_config(seg_ref.is_down()).target_types.contains(&BspType::T2S) {
+            return;
+        }
+        self.cal_single_bs2s_point(seg_list, bi_list, &bsp2_bi, &break_bi, real_bsp1, &bsp_conf);
     }
 
+    /// 类二买卖点：二类之后每隔一笔，与前面的回调笔保持重叠且不破突破笔
     fn cal_single_bs2s_point(
         &mut self,
-        seg: &Handle<Seg<T>>,
-        bi_list: &BiList<T>,
-        bsp1_bi_idx_dict: &HashMap<usize, Handle<BSPoint<T>>>,
+        seg_list: &SegListComm<T>,
+        bi_list: &[Handle<T>],
+        bsp2_bi: &Handle<T>,
+        break_bi: &Handle<T>,
+        real_bsp1: Option<Handle<BSPoint<T>>>,
+        bsp_conf: &PointConfig,
     ) {
-        let seg_ref = seg.borrow();
-        let bsp_conf = self.config.get_bs_config(seg_ref.is_down());
-        
-        if !bsp_conf.bsp2s_follow_2 {
-            return;
-        }
+        let bsp2_bi_ref = bsp2_bi.borrow();
+        let break_bi_ref = break_bi.borrow();
+        let bsp2_seg_idx = bi_seg_idx(&*bsp2_bi_ref, seg_list);
+
+        let mut bias = 2;
+        let (mut low, mut high) = (f64::NEG_INFINITY, f64::INFINITY);
+        while bsp2_bi_ref.idx() + bias < bi_list.len() {
+            let bsp2s_bi = &bi_list[bsp2_bi_ref.idx() + bias];
+            let bsp2s_bi_ref = bsp2s_bi.borrow();
+            let bsp2s_seg_idx = bi_seg_idx(&*bsp2s_bi_ref, seg_list);
+            let lv = bias / 2;
+
+            if bsp_conf.max_bsp2s_lv.map_or(false, |max_lv| lv as i32 > max_lv) {
+                break;
+            }
+            if bsp2s_seg_idx != bsp2_seg_idx
+                && (bsp2s_seg_idx < seg_list.len() - 1
+                    || bsp2s_seg_idx - bsp2_seg_idx >= 2
+                    || seg_list[bsp2_seg_idx].borrow().is_sure)
+            {
+                break;
+            }
 
-        let mut last_bsp2_bi = None;
-        let mut last_bsp2_real_bsp1 = None;
-        
-        for bi in &seg_ref.bi_list {
-            let bi_idx = bi.borrow().idx;
-            if let Some(bsp) = self.bsp_dict.get(&bi.borrow().get_end_klu().borrow().idx) {
-                if bsp.borrow().bs_type.contains(&BspType::BS2) {
-                    last_bsp2_bi = Some(bi.clone());
-                    last_bsp2_real_bsp1 = bsp.borrow().relate_bsp1.clone();
+            if bias == 2 {
+                if !has_overlap(bsp2_bi_ref._low(), bsp2_bi_ref._high(), bsp2s_bi_ref._low(), bsp2s_bi_ref._high(), false) {
+                    break;
                 }
+                low = bsp2_bi_ref._low().max(bsp2s_bi_ref._low());
+                high = bsp2_bi_ref._high().min(bsp2s_bi_ref._high());
+            } else if !has_overlap(low, high, bsp2s_bi_ref._low(), bsp2s_bi_ref._high(), false) {
+                break;
             }
-        }
 
-        if let (Some(bsp2_bi), Some(real_bsp1)) = (last_bsp2_bi, last_bsp2_real_bsp1) {
-            if bsp2s_break_bsp1(&seg_ref.end_bi, &bsp2_bi) {
-                let mut feature_dict = HashMap::new();
-                feature_dict.insert("bsp2s_bi_amp".to_string(), seg_ref.end_bi.borrow().amp());
-                
-                self.add_bs(
-                    BspType::BS2Strict,
-                    seg_ref.end_bi.clone(),
-                    Some(real_bsp1),
-                    true,
-                    Some(feature_dict),
-                );
+            if bsp2s_break_bsp1(bsp2s_bi, break_bi) {
+                break;
+            }
+            let retrace_rate = (bsp2s_bi_ref.get_end_val() - break_bi_ref.get_end_val()).abs() / break_bi_ref.amp();
+            if retrace_rate > bsp_conf.max_bs2_rate {
+                break;
             }
+
+            let mut feature_dict = HashMap::new();
+            feature_dict.insert("bsp2s_retrace_rate".to_string(), retrace_rate);
+            feature_dict.insert("bsp2s_break_bi_amp".to_string(), break_bi_ref.amp());
+            feature_dict.insert("bsp2s_bi_amp".to_string(), bsp2s_bi_ref.amp());
+            feature_dict.insert("bsp2s_lv".to_string(), lv as f64);
+
+            self.add_bs(
+                BspType::T2S,
+                bsp2s_bi.clone(),
+                real_bsp1.clone(),
+                true,
+                Some(feature_dict),
+            );
+            bias += 2;
         }
     }
 
-    pub fn cal_seg_bs3point(&mut self, seg_list: &SegListComm<T>, bi_list: &BiList<T>) {
-        let mut bsp1_bi_idx_dict = HashMap::new();
-        for bsp in &self.bsp1_lst {
-            bsp1_bi_idx_dict.insert(bsp.borrow().bi.borrow().idx, bsp.clone());
-        }
+    pub fn cal_seg_bs3point(&mut self, seg_list: &SegListComm<T>, bi_list: &[Handle<T>]) {
+        let bsp1_bi_idx_dict: HashMap<usize, Handle<BSPoint<T>>> = self.bsp1_lst.iter()
+            .map(|bsp| (bsp.borrow().bi.borrow().idx(), bsp.clone()))
+            .collect();
 
-        for (i, seg) in seg_list.iter().enumerate() {
+        for seg in seg_list.iter() {
             if !self.seg_need_cal(seg) {
                 continue;
             }
+            let config = self.config.get_bs_config(seg.borrow().is_down());
+            if !config.target_types.contains(&BspType::T3A) && !config.target_types.contains(&BspType::T3B) {
+                continue;
+            }
 
             let seg_ref = seg.borrow();
-            let bsp1_bi = if let Some(last_zs) = seg_ref.zs_lst.last() {
-                last_zs.bi_out.clone()
+            let (bsp_conf, next_seg, next_seg_idx, bsp1_bi, real_bsp1) = if seg_list.len() > 1 {
+                let bsp1_bi = seg_ref.end_bi.clone();
+                let real_bsp1 = bsp1_bi_idx_dict.get(&bsp1_bi.borrow().idx()).cloned();
+                // next可能为None, 所以并不一定可以保证next_seg_idx == next_seg.idx
+                (
+                    self.config.get_bs_config(seg_ref.is_down()).clone(),
+                    seg_ref.next.clone(),
+                    seg_ref.index() + 1,
+                    Some(bsp1_bi),
+                    real_bsp1,
+                )
             } else {
-                None
+                (
+                    self.config.get_bs_config(seg_ref.is_up()).clone(),
+                    Some(seg.clone()),
+                    seg_ref.index(),
+                    None,
+                    None,
+                )
             };
+            let bsp1_bi_idx = bsp1_bi.as_ref().map(|bi| bi.borrow().idx());
 
-            let (bsp_conf, next_seg, next_seg_idx, real_bsp1, bsp1_bi_idx) = if let Some(bsp1_bi) = &bsp1_bi {
-                let bsp_conf = self.config.get_bs_config(seg_ref.is_down());
-                let real_bsp1 = bsp1_bi_idx_dict.get(&bsp1_bi.borrow().idx).cloned();
-                let next_seg_idx = seg_ref.idx + 1;
-                (bsp_conf, seg_ref.next.clone(), next_seg_idx, real_bsp1, bsp1_bi.borrow().idx)
-            } else {
-                let bsp_conf = self.config.get_bs_config(seg_ref.is_up());
-                (bsp_conf, Some(seg.clone()), seg_ref.idx, None, -1)
-            };
-
-            if bsp_conf.bsp3_follow_1 && !self.bsp_dict.values().any(|bsp| bsp.borrow().bi.borrow().idx == bsp1_bi_idx) {
+            if bsp_conf.bsp3_follow_1
+                && !bsp1_bi_idx.map_or(false, |idx| self.bsp_dict.values().any(|bsp| bsp.borrow().bi.borrow().idx() == idx))
+            {
                 continue;
             }
-
             if let Some(next_seg) = &next_seg {
-                self.treat_bsp3_after(
-                    seg_list,
-                    next_seg,
-                    bsp_conf,
-                    bi_list,
-                    real_bsp1.clone(),
-                    bsp1_bi_idx,
-                    next_seg_idx,
-                );
+                self.treat_bsp3_after(seg_list, next_seg, &bsp_conf, bi_list, real_bsp1.clone(), bsp1_bi_idx, next_seg_idx);
             }
-
-            self.treat_bsp3_before(
-                seg_list,
-                seg,
-                next_seg.as_ref(),
-                bsp1_bi,
-                bsp_conf,
-                bi_list,
-                real_bsp1,
-                next_seg_idx,
-            );
+            self.treat_bsp3_before(seg_list, seg, next_seg.as_ref(), bsp1_bi, &bsp_conf, bi_list, real_bsp1, next_seg_idx);
         }
     }
 
+    /// 3a：中枢在1类买卖点后面，离开中枢后的第一次回抽不回到中枢
+    #[allow(clippy::too_many_arguments)]
     fn treat_bsp3_after(
         &mut self,
         seg_list: &SegListComm<T>,
         next_seg: &Handle<Seg<T>>,
         bsp_conf: &PointConfig,
-        bi_list: &BiList<T>,
+        bi_list: &[Handle<T>],
         real_bsp1: Option<Handle<BSPoint<T>>>,
-        bsp1_bi_idx: isize,
+        bsp1_bi_idx: Option<usize>,
         next_seg_idx: usize,
     ) {
         let next_seg_ref = next_seg.borrow();
-        if next_seg_ref.bi_list.len() < 3 {
+        let first_zs = match next_seg_ref.get_first_multi_bi_zs() {
+            Some(zs) => zs,
+            None => return,
+        };
+        if bsp_conf.strict_bsp3 && Some(first_zs.get_bi_in().borrow().idx()) != bsp1_bi_idx.map(|idx| idx + 1) {
             return;
         }
-
-        let first_zs = if let Some(zs) = next_seg_ref.zs_lst.first() {
-            if zs.is_one_bi_zs() {
-                return;
-            }
-            zs
-        } else {
-            return;
+        let bsp3_bi = match &first_zs.bi_out {
+            Some(bi_out) if bi_out.borrow().idx() + 1 < bi_list.len() => &bi_list[bi_out.borrow().idx() + 1],
+            _ => return,
         };
+        let bsp3_bi_ref = bsp3_bi.borrow();
 
-        let end_bi_idx = cal_bsp3_bi_end_idx(Some(next_seg));
-        for bsp3_bi in bi_list.iter().skip(bsp1_bi_idx as usize + 2).step_by(2) {
-            let bsp3_bi_ref = bsp3_bi.borrow();
-            if bsp3_bi_ref.idx as f64 > end_bi_idx {
-                break;
-            }
-            if bsp3_bi_ref.seg_idx.unwrap() != next_seg_idx && next_seg_idx < seg_list.len() - 2 {
-                return;
-            }
-            if bsp3_back2zs(bsp3_bi, first_zs) {
-                return;
+        // 线段之后的笔seg_idx为最后一个线段idx+1，即不属于任何线段
+        match bsp3_bi_ref.seg_idx().filter(|idx| *idx < seg_list.len()) {
+            None => {
+                if next_seg_ref.index() != seg_list.len() - 1 {
+                    return;
+                }
             }
-            let bsp3_peak_zs = bsp3_break_zspeak(bsp3_bi, first_zs);
-            if bsp_conf.bsp3_peak && !bsp3_peak_zs {
-                return;
+            Some(parent_seg_idx) => {
+                if parent_seg_idx != next_seg_ref.index() && seg_list[parent_seg_idx].borrow().bi_list.len() >= 3 {
+                    return;
+                }
             }
+        }
+        if bsp3_bi_ref.is_up() == next_seg_ref.is_up() {
+            return;
+        }
+        if bsp3_bi_ref.seg_idx() != Some(next_seg_idx) && next_seg_idx + 2 < seg_list.len() {
+            return;
+        }
+        if bsp3_back2zs(bsp3_bi, &first_zs) {
+            return;
+        }
+        if bsp_conf.bsp3_peak && !bsp3_break_zspeak(bsp3_bi, &first_zs) {
+            return;
+        }
 
-            let mut feature_dict = HashMap::new();
-            feature_dict.insert(
-                "bsp3_zs_height".to_string(),
-                (first_zs.high.unwrap() - first_zs.low.unwrap()) / first_zs.low.unwrap(),
-            );
-            feature_dict.insert("bsp3_bi_amp".to_string(), bsp3_bi_ref.amp());
+        let mut feature_dict = HashMap::new();
+        feature_dict.insert(
+            "bsp3_zs_height".to_string(),
+            (first_zs.high.unwrap() - first_zs.low.unwrap()) / first_zs.low.unwrap(),
+        );
+        feature_dict.insert("bsp3_bi_amp".to_string(), bsp3_bi_ref.amp());
 
-            self.add_bs(
-                BspType::BS3,
-                bsp3_bi.clone(),
-                real_bsp1.clone(),
-                true,
-                Some(feature_dict),
-            );
-        }
+        self.add_bs(
+            BspType::T3A,
+            bsp3_bi.clone(),
+            real_bsp1,
+            true,
+            Some(feature_dict),
+        );
     }
 
+    /// 3b：中枢在1类买卖点前面，1类之后第一个不回到该中枢的同向笔
+    #[allow(clippy::too_many_arguments)]
     fn treat_bsp3_before(
         &mut self,
         seg_list: &SegListComm<T>,
@@ -426,36 +568,33 @@ impl<T: LineTrait> BSPointList<T> {
         next_seg: Option<&Handle<Seg<T>>>,
         bsp1_bi: Option<Handle<T>>,
         bsp_conf: &PointConfig,
-        bi_list: &BiList<T>,
+        bi_list: &[Handle<T>],
         real_bsp1: Option<Handle<BSPoint<T>>>,
         next_seg_idx: usize,
     ) {
-        let seg_ref = seg.borrow();
-        let cmp_zs = seg_ref.get_final_multi_bi_zs();
-        if cmp_zs.is_none() || bsp1_bi.is_none() {
+        let cmp_zs = match seg.borrow().get_final_multi_bi_zs() {
+            Some(zs) => zs,
+            None => return,
+        };
+        let bsp1_bi = match bsp1_bi {
+            Some(bi) => bi,
+            None => return,
+        };
+        let bsp1_bi_idx = bsp1_bi.borrow().idx();
+        if bsp_conf.strict_bsp3 && cmp_zs.bi_out.as_ref().map(|bi| bi.borrow().idx()) != Some(bsp1_bi_idx) {
             return;
         }
-        let cmp_zs = cmp_zs.unwrap();
-        let bsp1_bi = bsp1_bi.unwrap();
-
-        if bsp_conf.strict_bsp3 {
-            if cmp_zs.bi_out.is_none() || cmp_zs.bi_out.as_ref().unwrap().borrow().idx != bsp1_bi.borrow().idx {
-                return;
-            }
-        }
 
         let end_bi_idx = cal_bsp3_bi_end_idx(next_seg);
-        for bsp3_bi in bi_list.iter().skip(bsp1_bi.borrow().idx as usize + 2).step_by(2) {
+        for bsp3_bi in bi_list.iter().skip(bsp1_bi_idx + 2).step_by(2) {
             let bsp3_bi_ref = bsp3_bi.borrow();
-            if bsp3_bi_ref.idx as f64 > end_bi_idx {
+            if bsp3_bi_ref.idx() as f64 > end_bi_idx {
                 break;
             }
-            
-            assert!(bsp3_bi_ref.seg_idx.is_some());
-            if bsp3_bi_ref.seg_idx.unwrap() != next_seg_idx && bsp3_bi_ref.seg_idx.unwrap() < seg_list.len() - 1 {
+            let bsp3_seg_idx = bi_seg_idx(&*bsp3_bi_ref, seg_list);
+            if bsp3_seg_idx != next_seg_idx && bsp3_seg_idx + 1 < seg_list.len() {
                 break;
             }
-            
             if bsp3_back2zs(bsp3_bi, &cmp_zs) {
                 continue;
             }
@@ -468,9 +607,9 @@ impl<T: LineTrait> BSPointList<T> {
             feature_dict.insert("bsp3_bi_amp".to_string(), bsp3_bi_ref.amp());
 
             self.add_bs(
-                BspType::BS3Peak,
+                BspType::T3B,
                 bsp3_bi.clone(),
-                real_bsp1.clone(),
+                real_bsp1,
                 true,
                 Some(feature_dict),
             );
@@ -483,14 +622,18 @@ impl<T: LineTrait> BSPointList<T> {
             return vec![];
         }
         let mut result = self.lst.clone();
-        result.sort_by(|a, b| {
-            b.borrow().bi.borrow().idx.cmp(&a.borrow().bi.borrow().idx)
-        });
+        result.sort_by_key(|bsp| std::cmp::Reverse(bsp.borrow().bi.borrow().idx()));
         result
     }
 }
 
 // Helper functions
+
+/// 笔所属线段的序号，最后一个线段之后还未成段的笔视为seg_list.len()
+pub fn bi_seg_idx<T: LineTrait>(bi: &T, seg_list: &SegListComm<T>) -> usize {
+    bi.seg_idx().unwrap_or(seg_list.len())
+}
+
 pub fn bsp2s_break_bsp1<T: LineTrait>(bsp2s_bi: &Handle<T>, bsp2_break_bi: &Handle<T>) -> bool {
     let bsp2s = bsp2s_bi.borrow();
     let break_bi = bsp2_break_bi.borrow();
@@ -518,13 +661,13 @@ pub fn cal_bsp3_bi_end_idx<T: LineTrait>(seg: Option<&Handle<Seg<T>>>) -> f64 {
             if seg.get_multi_bi_zs_cnt() == 0 && seg.next.is_none() {
                 f64::INFINITY
             } else {
-                let mut end_bi_idx = seg.end_bi.borrow().idx as f64 - 1.0;
+                let mut end_bi_idx = seg.end_bi.borrow().idx() as f64 - 1.0;
                 for zs in &seg.zs_lst {
                     if zs.is_one_bi_zs() {
                         continue;
                     }
                     if let Some(bi_out) = &zs.bi_out {
-                        end_bi_idx = bi_out.borrow().idx as f64;
+                        end_bi_idx = bi_out.borrow().idx() as f64;
                         break;
                     }
                 }
@@ -532,4 +675,318 @@ pub fn cal_bsp3_bi_end_idx<T: LineTrait>(seg: Option<&Handle<Seg<T>>>) -> f64 {
             }
         }
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::cenum::MacdAlgo;
+    use crate::common::chan_exception::ErrCode;
+    use crate::common::test_util::{build_chan_fixture, ChanFixture, MockBi};
+
+    /// 背驰比较使用只依赖端点价格的amp，不需要计算MACD
+    fn bsp_config(bs_type: &str) -> BSPointConfig {
+        bsp_config_with(bs_type, &[])
+    }
+
+    /// 在bsp_config的基础上追加其它配置项
+    fn bsp_config_with(bs_type: &str, extra: &[(&str, &str)]) -> BSPointConfig {
+        let mut args = HashMap::from([
+            ("bs_type".to_string(), bs_type.to_string()),
+            ("macd_algo".to_string(), "amp".to_string()),
+            ("divergence_rate".to_string(), "0.9".to_string()),
+        ]);
+        args.extend(extra.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        BSPointConfig::new(args).unwrap()
+    }
+
+    /// 返回按笔序号排序的(笔序号, 是否买点, 类型, 关联一类买卖点的笔序号)
+    fn cal_bsp(fixture: &ChanFixture, bsp_list: &mut BSPointList<MockBi>) -> Vec<(usize, bool, Vec<BspType>, Option<usize>)> {
+        bsp_list.feature_extractors.clear();
+        bsp_list.cal(&fixture.bi_lst, &fixture.seg_list).unwrap();
+        let mut res: Vec<_> = bsp_list
+            .iter()
+            .map(|bsp| {
+                let bsp = bsp.borrow();
+                let relate_bsp1 = bsp.relate_bsp1.as_ref().map(|bsp1| bsp1.borrow().bi.borrow().idx());
+                (bsp.bi.borrow().idx(), bsp.is_buy, bsp.bs_type.clone(), relate_bsp1)
+            })
+            .collect();
+        res.sort_by_key(|(idx, ..)| *idx);
+        res
+    }
+
+    /// 下跌线段0（中枢为笔1~5）以背驰的笔6结束，上涨线段1（中枢为笔8~10）未确定，笔12不属于任何线段
+    fn trend_fixture() -> ChanFixture {
+        build_chan_fixture(
+            &[100.0, 60.0, 80.0, 65.0, 78.0, 66.0, 79.0, 55.0, 75.0, 62.0, 72.0, 64.0, 90.0, 76.0],
+            &[(0, 6, true), (7, 11, false)],
+            &[(1, 5), (8, 10)],
+        )
+    }
+
+    /// 下跌线段0的中枢（笔1~3）在笔4之前就结束了，笔6与笔4比较盘整背驰；笔8回调不进入中枢
+    fn pz_fixture() -> ChanFixture {
+        build_chan_fixture(
+            &[100.0, 70.0, 82.0, 72.0, 80.0, 60.0, 68.0, 55.0, 90.0, 82.0, 88.0],
+            &[(0, 6, true), (7, 9, false)],
+            &[(1, 3)],
+        )
+    }
+
+    #[test]
+    fn test_trend_bs1() {
+        let fixture = trend_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config("1,1p"));
+        assert_eq!(cal_bsp(&fixture, &mut bsp_list), vec![(6, true, vec![BspType::T1], None)]);
+        // 线段1的出中枢笔没有背驰，只记入bsp1_lst
+        assert_eq!(bsp_list.bsp1_lst.len(), 2);
+    }
+
+    #[test]
+    fn test_trend_bs1_atr_amp() {
+        let mut fixture = trend_fixture();
+        let conf = bsp_config_with("1,1p", &[("macd_algo", "atr_amp")]);
+        assert_eq!(conf.b_conf.macd_algo, MacdAlgo::AtrAmp);
+
+        // 没有计算ATR时无法比较背驰
+        let mut bsp_list = BSPointList::new(conf.clone());
+        let err = bsp_list.cal(&fixture.bi_lst, &fixture.seg_list).unwrap_err();
+        assert_eq!(err.errcode, ErrCode::ParaError);
+
+        // ATR处处相同时与amp的结论一致：24 / 1 < 0.9 * 40 / 1
+        for klu in fixture.klus.iter_mut() {
+            klu.atr = Some(1.0);
+        }
+        let mut bsp_list = BSPointList::new(conf.clone());
+        assert_eq!(cal_bsp(&fixture, &mut bsp_list), vec![(6, true, vec![BspType::T1], None)]);
+
+        // 出中枢笔起点的波动率更低，按ATR归一化后力度更大：24 / 0.5 > 0.9 * 40 / 1，不再背驰
+        fixture.klus[6].atr = Some(0.5);
+        let mut bsp_list = BSPointList::new(conf);
+        assert!(cal_bsp(&fixture, &mut bsp_list).is_empty());
+    }
+
+    #[test]
+    fn test_target_types_filter_keeps_bsp1() {
+        let fixture = trend_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config("1p"));
+        assert!(cal_bsp(&fixture, &mut bsp_list).is_empty());
+        // 不是目标类型的一类买卖点仍要记入bsp1_lst，供二类、三类买卖点关联
+        assert_eq!(bsp_list.bsp1_lst.len(), 2);
+    }
+
+    #[test]
+    fn test_pz_bs1p() {
+        let fixture = pz_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config("1,1p"));
+        assert_eq!(cal_bsp(&fixture, &mut bsp_list), vec![(6, true, vec![BspType::T1P], None)]);
+    }
+
+    #[test]
+    fn test_is_segbsp_follows_list_level() {
+        let fixture = pz_fixture();
+        let mut bi_bsp_list = BSPointList::new(bsp_config("1p"));
+        cal_bsp(&fixture, &mut bi_bsp_list);
+        assert!(bi_bsp_list.iter().all(|bsp| !bsp.borrow().is_segbsp));
+
+        let mut seg_bsp_list = BSPointList::new_seg(bsp_config("1p"));
+        cal_bsp(&fixture, &mut seg_bsp_list);
+        assert_eq!(seg_bsp_list.len(), 1);
+        assert!(seg_bsp_list.iter().all(|bsp| bsp.borrow().is_segbsp));
+    }
+
+    /// 同trend_fixture的线段0，线段1没有中枢，笔8之后有两级类二买点：笔10和笔12都与笔8重叠且不破笔7的低点
+    fn bs2s_fixture() -> ChanFixture {
+        build_chan_fixture(
+            &[100.0, 60.0, 80.0, 65.0, 78.0, 66.0, 79.0, 55.0, 75.0, 62.0, 72.0, 64.0, 70.0, 66.0, 74.0],
+            &[(0, 6, true), (7, 13, false)],
+            &[(1, 5)],
+        )
+    }
+
+    #[test]
+    fn test_trend_bs2_bs2s() {
+        let fixture = trend_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config("1,2,2s"));
+        assert_eq!(
+            cal_bsp(&fixture, &mut bsp_list),
+            vec![
+                (6, true, vec![BspType::T1], None),
+                (8, true, vec![BspType::T2], Some(6)),
+                (10, true, vec![BspType::T2S], Some(6)),
+            ]
+        );
+        // 笔8回撤笔7的13/20，笔10的终点离笔7的终点11/20
+        let bsp2 = bsp_list.iter().find(|bsp| bsp.borrow().bs_type == vec![BspType::T2]).unwrap();
+        assert!((bsp2.borrow().features.get("bsp2_retrace_rate").unwrap() - 0.65).abs() < 1e-9);
+        let bsp2s = bsp_list.iter().find(|bsp| bsp.borrow().bs_type == vec![BspType::T2S]).unwrap();
+        assert!((bsp2s.borrow().features.get("bsp2s_retrace_rate").unwrap() - 0.55).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_lastest_bsp_list_newest_first() {
+        let fixture = trend_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config("1,2,2s"));
+        cal_bsp(&fixture, &mut bsp_list);
+        let bi_idx: Vec<usize> = bsp_list
+            .get_lastest_bsp_list()
+            .iter()
+            .map(|bsp| bsp.borrow().bi.borrow().idx())
+            .collect();
+        assert_eq!(bi_idx, vec![10, 8, 6]);
+    }
+
+    #[test]
+    fn test_max_bs2_rate() {
+        let fixture = trend_fixture();
+        // 笔8回撤超限不是二类，笔10仍是类二
+        let mut bsp_list = BSPointList::new(bsp_config_with("2,2s", &[("max_bs2_rate", "0.6")]));
+        assert_eq!(cal_bsp(&fixture, &mut bsp_list), vec![(10, true, vec![BspType::T2S], Some(6))]);
+
+        let mut bsp_list = BSPointList::new(bsp_config_with("2,2s", &[("max_bs2_rate", "0.5")]));
+        assert!(cal_bsp(&fixture, &mut bsp_list).is_empty());
+
+        // 类二要求先有二类
+        let mut bsp_list = BSPointList::new(bsp_config_with(
+            "2,2s",
+            &[("max_bs2_rate", "0.6"), ("bsp2s_follow_2", "true")],
+        ));
+        assert!(cal_bsp(&fixture, &mut bsp_list).is_empty());
+    }
+
+    #[test]
+    fn test_bsp2_follow_1() {
+        let fixture = trend_fixture();
+        // 一类不是目标类型时不在bsp_dict中，二类和类二都不算
+        let mut bsp_list = BSPointList::new(bsp_config_with("2,2s", &[("bsp2_follow_1", "true")]));
+        assert!(cal_bsp(&fixture, &mut bsp_list).is_empty());
+
+        let mut bsp_list = BSPointList::new(bsp_config_with("1,2,2s", &[("bsp2_follow_1", "true")]));
+        assert_eq!(
+            cal_bsp(&fixture, &mut bsp_list),
+            vec![
+                (6, true, vec![BspType::T1], None),
+                (8, true, vec![BspType::T2], Some(6)),
+                (10, true, vec![BspType::T2S], Some(6)),
+            ]
+        );
+
+        // 一类背驰不成立时同样不算
+        let mut bsp_list = BSPointList::new(bsp_config_with(
+            "1,2,2s",
+            &[("bsp2_follow_1", "true"), ("divergence_rate", "0.1")],
+        ));
+        assert!(cal_bsp(&fixture, &mut bsp_list).is_empty());
+    }
+
+    #[test]
+    fn test_max_bsp2s_lv() {
+        let fixture = bs2s_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config("2,2s"));
+        assert_eq!(
+            cal_bsp(&fixture, &mut bsp_list),
+            vec![
+                (8, true, vec![BspType::T2], Some(6)),
+                (10, true, vec![BspType::T2S], Some(6)),
+                (12, true, vec![BspType::T2S], Some(6)),
+            ]
+        );
+        let lvs: Vec<f64> = bsp_list
+            .iter()
+            .filter_map(|bsp| bsp.borrow().features.get("bsp2s_lv"))
+            .collect();
+        assert_eq!(lvs, vec![1.0, 2.0]);
+
+        let mut bsp_list = BSPointList::new(bsp_config_with("2,2s", &[("max_bsp2s_lv", "1")]));
+        assert_eq!(
+            cal_bsp(&fixture, &mut bsp_list),
+            vec![
+                (8, true, vec![BspType::T2], Some(6)),
+                (10, true, vec![BspType::T2S], Some(6)),
+            ]
+        );
+
+        let mut bsp_list = BSPointList::new(bsp_config_with("2,2s", &[("max_bsp2s_lv", "0")]));
+        assert_eq!(cal_bsp(&fixture, &mut bsp_list), vec![(8, true, vec![BspType::T2], Some(6))]);
+    }
+
+    /// 同trend_fixture，但笔12的高点74没有超过线段1中枢的最高点75
+    fn bsp3_peak_fixture() -> ChanFixture {
+        build_chan_fixture(
+            &[100.0, 60.0, 80.0, 65.0, 78.0, 66.0, 79.0, 55.0, 75.0, 62.0, 72.0, 64.0, 74.0, 73.0],
+            &[(0, 6, true), (7, 11, false)],
+            &[(1, 5), (8, 10)],
+        )
+    }
+
+    #[test]
+    fn test_trend_bs3a() {
+        let fixture = trend_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config("3a,3b"));
+        // 笔8、10回到线段0的中枢，不是3b
+        assert_eq!(cal_bsp(&fixture, &mut bsp_list), vec![(12, true, vec![BspType::T3A], Some(6))]);
+    }
+
+    #[test]
+    fn test_pz_bs3b() {
+        let fixture = pz_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config("3a,3b"));
+        assert_eq!(cal_bsp(&fixture, &mut bsp_list), vec![(8, true, vec![BspType::T3B], Some(6))]);
+    }
+
+    #[test]
+    fn test_bsp3_follow_1() {
+        let fixture = trend_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config_with("3a,3b", &[("bsp3_follow_1", "true")]));
+        assert!(cal_bsp(&fixture, &mut bsp_list).is_empty());
+
+        let mut bsp_list = BSPointList::new(bsp_config_with("1,3a,3b", &[("bsp3_follow_1", "true")]));
+        assert_eq!(
+            cal_bsp(&fixture, &mut bsp_list),
+            vec![
+                (6, true, vec![BspType::T1], None),
+                (12, true, vec![BspType::T3A], Some(6)),
+            ]
+        );
+
+        let fixture = pz_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config_with("3a,3b", &[("bsp3_follow_1", "true")]));
+        assert!(cal_bsp(&fixture, &mut bsp_list).is_empty());
+
+        let mut bsp_list = BSPointList::new(bsp_config_with("1p,3a,3b", &[("bsp3_follow_1", "true")]));
+        assert_eq!(
+            cal_bsp(&fixture, &mut bsp_list),
+            vec![
+                (6, true, vec![BspType::T1P], None),
+                (8, true, vec![BspType::T3B], Some(6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bsp3_peak() {
+        // 笔12的高点90超过中枢最高点75
+        let fixture = trend_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config_with("3a", &[("bsp3_peak", "true")]));
+        assert_eq!(cal_bsp(&fixture, &mut bsp_list), vec![(12, true, vec![BspType::T3A], Some(6))]);
+
+        let fixture = bsp3_peak_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config("3a"));
+        assert_eq!(cal_bsp(&fixture, &mut bsp_list), vec![(12, true, vec![BspType::T3A], Some(6))]);
+        let mut bsp_list = BSPointList::new(bsp_config_with("3a", &[("bsp3_peak", "true")]));
+        assert!(cal_bsp(&fixture, &mut bsp_list).is_empty());
+    }
+
+    #[test]
+    fn test_strict_bsp3() {
+        // 线段1的中枢紧接一类买点后的笔7开始，3a仍成立
+        let fixture = trend_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config_with("3a", &[("strict_bsp3", "true")]));
+        assert_eq!(cal_bsp(&fixture, &mut bsp_list), vec![(12, true, vec![BspType::T3A], Some(6))]);
+
+        // 线段0的中枢在笔4离开，不是由一类买点的笔6离开，3b不成立
+        let fixture = pz_fixture();
+        let mut bsp_list = BSPointList::new(bsp_config_with("3b", &[("strict_bsp3", "true")]));
+        assert!(cal_bsp(&fixture, &mut bsp_list).is_empty());
+    }
+}
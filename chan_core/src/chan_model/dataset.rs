@@ -0,0 +1,266 @@
+use std::collections::{BTreeSet, HashSet};
+
+use crate::buy_sell_point::bs_point::BSPoint;
+use crate::buy_sell_point::bs_point_list::BSPointList;
+use crate::common::handle::Handle;
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::line_trait::LineTrait;
+
+use super::features::Features;
+use super::labeler::BspLabeler;
+
+/// 每行固定在特征之前的列
+pub const META_COLUMNS: [&str; 6] = ["symbol", "time", "bs_type", "is_buy", "is_segbsp", "score"];
+
+/// 标签列名的前缀，避免与特征重名
+pub const LABEL_PREFIX: &str = "label_";
+
+/// 一个买卖点对应的一行训练数据
+#[derive(Debug, Clone)]
+pub struct BspRecord {
+    pub symbol: String,
+    pub time: String,
+    pub bs_type: String,
+    pub is_buy: bool,
+    pub is_segbsp: bool,
+    /// 未设置打分模型时为None
+    pub score: Option<f64>,
+    pub features: Features,
+    pub labels: Features,
+}
+
+impl BspRecord {
+    pub fn from_bsp<T: LineTrait>(symbol: &str, bsp: &BSPoint<T>, is_segbsp: bool) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            time: bsp.klu.time.to_string(),
+            bs_type: bsp.type_to_string(),
+            is_buy: bsp.is_buy,
+            is_segbsp: bsp.is_segbsp || is_segbsp,
+            score: bsp.score,
+            features: bsp.features.clone(),
+            labels: bsp.labels.clone(),
+        }
+    }
+}
+
+/// 多个标的的买卖点数据集
+///
+/// 列顺序固定：META_COLUMNS、特征列、标签列。特征列默认取全部买卖点特征名的并集并排序，
+/// 也可以用`with_feature_columns`指定，保证多次导出的列完全一致；
+/// 某个买卖点缺少的特征或标签输出为None，由写出格式决定缺失值的表示
+#[derive(Debug, Clone, Default)]
+pub struct BspDataset {
+    pub records: Vec<BspRecord>,
+    feature_columns: Option<Vec<String>>,
+}
+
+impl BspDataset {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 固定特征列，不在列表中的特征不导出
+    pub fn with_feature_columns(feature_columns: Vec<String>) -> Self {
+        Self {
+            records: Vec::new(),
+            feature_columns: Some(feature_columns),
+        }
+    }
+
+    pub fn add_bsp_list<T: LineTrait>(&mut self, symbol: &str, bsp_list: &BSPointList<T>, is_segbsp: bool) {
+        self.records
+            .extend(bsp_list.iter().map(|bsp| BspRecord::from_bsp(symbol, bsp.borrow(), is_segbsp)));
+    }
+
+    /// 只保留打分不低于min_score的买卖点，没有打分的也会被去掉
+    pub fn retain_min_score(&mut self, min_score: f64) {
+        self.records.retain(|record| record.score.is_some_and(|score| score >= min_score));
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn feature_columns(&self) -> Vec<String> {
+        match &self.feature_columns {
+            Some(columns) => columns.clone(),
+            None => key_union(self.records.iter().map(|record| &record.features)),
+        }
+    }
+
+    /// 标签名，不带LABEL_PREFIX
+    pub fn label_columns(&self) -> Vec<String> {
+        key_union(self.records.iter().map(|record| &record.labels))
+    }
+
+    /// 完整表头
+    pub fn header(&self) -> Vec<String> {
+        META_COLUMNS
+            .iter()
+            .map(|col| col.to_string())
+            .chain(self.feature_columns())
+            .chain(self.label_columns().into_iter().map(|col| format!("{}{}", LABEL_PREFIX, col)))
+            .collect()
+    }
+
+    /// 按列顺序展开每一行的特征和标签
+    pub fn rows(&self) -> impl Iterator<Item = DatasetRow<'_>> {
+        let feature_columns = self.feature_columns();
+        let label_columns = self.label_columns();
+        self.records.iter().map(move |record| DatasetRow {
+            record,
+            features: feature_columns.iter().map(|col| record.features.get(col)).collect(),
+            labels: label_columns.iter().map(|col| record.labels.get(col)).collect(),
+        })
+    }
+}
+
+/// 逐K线计算时收集买卖点，数据集中的特征不会用到买卖点出现之后的K线
+///
+/// 每个买卖点只在第一次出现时按当时的特征记录一行，之后被修正或消失都不影响已记录的行；
+/// 标签需要之后的K线，在`finish`时统一打
+#[derive(Default)]
+pub struct BspCollector {
+    /// 已记录的买卖点，(时间, 是否买点, 是否线段买卖点)
+    seen: HashSet<(String, bool, bool)>,
+    /// 记录以及打标签用的KLU和所在笔的终点价格
+    pending: Vec<(BspRecord, Handle<KLineUnit>, f64)>,
+}
+
+impl BspCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 每根K线加入Analyzer之后调用
+    pub fn collect<T: LineTrait>(&mut self, symbol: &str, bsp_list: &BSPointList<T>, is_segbsp: bool) {
+        for bsp in bsp_list.iter() {
+            let bsp = bsp.borrow();
+            let key = (bsp.klu.time.to_string(), bsp.is_buy, bsp.is_segbsp || is_segbsp);
+            if self.seen.insert(key) {
+                let bsp_peak = bsp.bi.borrow().get_end_val();
+                self.pending.push((BspRecord::from_bsp(symbol, bsp, is_segbsp), bsp.klu, bsp_peak));
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// 全部K线加入之后调用，打标签并加入数据集；KLU归Analyzer所有，此时Analyzer须仍然存在
+    pub fn finish(self, labeler: &BspLabeler, dataset: &mut BspDataset) {
+        for (mut record, klu, bsp_peak) in self.pending {
+            record.labels = labeler.label_klu(record.is_buy, &klu, bsp_peak);
+            dataset.records.push(record);
+        }
+    }
+}
+
+/// 展开后的一行，features和labels与`feature_columns`、`label_columns`一一对应
+pub struct DatasetRow<'a> {
+    pub record: &'a BspRecord,
+    pub features: Vec<Option<f64>>,
+    pub labels: Vec<Option<f64>>,
+}
+
+fn key_union<'a>(iter: impl Iterator<Item = &'a Features>) -> Vec<String> {
+    iter.flat_map(|features| features.items().map(|(k, _)| k.clone()))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::analyzer::Analyzer;
+    use crate::common::test_util::sine_kl_data;
+    use crate::config::chan_config::ChanConfig;
+
+    fn record(features: &[(&str, f64)], labels: &[(&str, f64)]) -> BspRecord {
+        let to_features = |items: &[(&str, f64)]| {
+            let mut res = Features::default();
+            res.add_feat(items.iter().map(|(k, v)| (k.to_string(), *v)));
+            res
+        };
+        BspRecord {
+            symbol: "sz.000001".to_string(),
+            time: "2024-01-02 00:00:00".to_string(),
+            bs_type: "1".to_string(),
+            is_buy: true,
+            is_segbsp: false,
+            score: None,
+            features: to_features(features),
+            labels: to_features(labels),
+        }
+    }
+
+    #[test]
+    fn test_stable_columns_with_missing() {
+        let mut dataset = BspDataset::new();
+        dataset.records.push(record(&[("b", 2.0), ("a", 1.0)], &[("ret_5", 0.1)]));
+        dataset.records.push(record(&[("c", 3.0)], &[]));
+
+        assert_eq!(dataset.feature_columns(), vec!["a", "b", "c"]);
+        assert_eq!(
+            dataset.header()[META_COLUMNS.len()..],
+            ["a", "b", "c", "label_ret_5"].map(String::from)
+        );
+        let rows: Vec<_> = dataset.rows().collect();
+        assert_eq!(rows[0].features, vec![Some(1.0), Some(2.0), None]);
+        assert_eq!(rows[1].features, vec![None, None, Some(3.0)]);
+        assert_eq!(rows[1].labels, vec![None]);
+    }
+
+    #[test]
+    fn test_pinned_columns() {
+        let mut dataset = BspDataset::with_feature_columns(vec!["z".to_string(), "a".to_string()]);
+        dataset.records.push(record(&[("a", 1.0), ("b", 2.0)], &[]));
+        let rows: Vec<_> = dataset.rows().collect();
+        assert_eq!(rows[0].features, vec![None, Some(1.0)]);
+    }
+
+    #[test]
+    fn test_retain_min_score() {
+        let mut dataset = BspDataset::new();
+        for score in [None, Some(0.3), Some(0.8)] {
+            dataset.records.push(BspRecord { score, ..record(&[], &[]) });
+        }
+        dataset.retain_min_score(0.5);
+        assert_eq!(dataset.len(), 1);
+        assert_eq!(dataset.records[0].score, Some(0.8));
+    }
+
+    #[test]
+    fn test_collect_step_by_step() {
+        let mut analyzer = Analyzer::new("sine".to_string(), ChanConfig::new(None).unwrap()).unwrap();
+        analyzer.step_calculation = true;
+        let mut collector = BspCollector::new();
+        for kl_data in sine_kl_data(400) {
+            analyzer.add_kl_data(&kl_data.kl_dict, kl_data.time, false).unwrap();
+            collector.collect("sine", &analyzer.bs_point_lst, false);
+            collector.collect("sine", &analyzer.seg_bs_point_lst, true);
+        }
+        assert!(!collector.is_empty());
+
+        let mut dataset = BspDataset::new();
+        collector.finish(&BspLabeler::default(), &mut dataset);
+        // 每个买卖点只记录一次
+        let keys: HashSet<_> = dataset.records.iter().map(|r| (r.time.clone(), r.is_buy, r.is_segbsp)).collect();
+        assert_eq!(keys.len(), dataset.len());
+        assert!(dataset.records.iter().all(|r| !r.features.is_empty()));
+        // 买卖点之后K线足够时有完整的标签
+        assert!(!dataset.label_columns().is_empty());
+        assert!(dataset.records.iter().any(|r| r.labels.contains("ret_20") && r.labels.contains("mfe")));
+    }
+}
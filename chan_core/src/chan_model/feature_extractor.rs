@@ -0,0 +1,335 @@
+use std::sync::Arc;
+
+use crate::buy_sell_point::bs_point::BSPoint;
+use crate::common::cenum::MacdAlgo;
+use crate::common::handle::Handle;
+use crate::seg::seg::Seg;
+use crate::seg::seg_list_comm::SegListComm;
+use crate::traits::line_trait::LineTrait;
+
+/// 计算特征时可以访问的数据，只包含买卖点生成时已有的笔和线段
+pub struct FeatureContext<'a, T: LineTrait> {
+    pub bsp: &'a BSPoint<T>,
+    pub bi_list: &'a [Handle<T>],
+    pub seg_list: &'a SegListComm<T>,
+}
+
+impl<T: LineTrait> FeatureContext<'_, T> {
+    /// 买卖点所在的线段，线段之后的笔返回None
+    pub fn parent_seg(&self) -> Option<Handle<Seg<T>>> {
+        self.bsp
+            .bi
+            .borrow()
+            .seg_idx()
+            .filter(|idx| *idx < self.seg_list.lst.len())
+            .map(|idx| self.seg_list.lst[idx].clone())
+    }
+
+    /// 与买卖点所在笔同向的前一笔
+    pub fn pre_same_dir_bi(&self) -> Option<&Handle<T>> {
+        let idx = self.bsp.bi.borrow().idx();
+        idx.checked_sub(2).and_then(|pre_idx| self.bi_list.get(pre_idx))
+    }
+}
+
+/// 买卖点特征提取器，在买卖点生成时调用，返回的特征写入`BSPoint.features`
+pub trait FeatureExtractor<T: LineTrait> {
+    fn name(&self) -> &str;
+
+    fn extract(&self, ctx: &FeatureContext<T>) -> Vec<(String, f64)>;
+}
+
+/// 默认启用的特征提取器
+pub fn default_feature_extractors<T: LineTrait>() -> Vec<Arc<dyn FeatureExtractor<T>>> {
+    vec![
+        Arc::new(BiShapeExtractor),
+        Arc::new(MacdRatioExtractor),
+        Arc::new(ZsExtractor),
+        Arc::new(IndicatorSnapshotExtractor),
+        Arc::new(RelateBsp1Extractor),
+    ]
+}
+
+/// 买卖点所在笔的形态：K线数、振幅比例、每根K线的涨跌幅
+pub struct BiShapeExtractor;
+
+impl<T: LineTrait> FeatureExtractor<T> for BiShapeExtractor {
+    fn name(&self) -> &str {
+        "bi_shape"
+    }
+
+    fn extract(&self, ctx: &FeatureContext<T>) -> Vec<(String, f64)> {
+        let bi = ctx.bsp.bi.borrow();
+        let klu_cnt = (bi.get_end_klu().index() - bi.get_begin_klu().index() + 1) as f64;
+        let amp_rate = bi.amp() / bi.get_begin_val();
+        vec![
+            ("bsp_bi_klu_cnt".to_string(), klu_cnt),
+            ("bsp_bi_amp_rate".to_string(), amp_rate),
+            ("bsp_bi_klu_amp_rate".to_string(), amp_rate / klu_cnt),
+        ]
+    }
+}
+
+/// 买卖点所在笔与同向前一笔的MACD面积、峰值和振幅之比
+pub struct MacdRatioExtractor;
+
+impl<T: LineTrait> FeatureExtractor<T> for MacdRatioExtractor {
+    fn name(&self) -> &str {
+        "macd_ratio"
+    }
+
+    fn extract(&self, ctx: &FeatureContext<T>) -> Vec<(String, f64)> {
+        let pre_bi = match ctx.pre_same_dir_bi() {
+            Some(pre_bi) => pre_bi.borrow(),
+            None => return Vec::new(),
+        };
+        let bi = ctx.bsp.bi.borrow();
+        let mut res = Vec::new();
+        if let (Ok(area), Ok(pre_area)) = (bi.cal_macd_area(), pre_bi.cal_macd_area()) {
+            res.push(("bsp_macd_area_ratio".to_string(), area / pre_area));
+        }
+        if let (Ok(peak), Ok(pre_peak)) = (bi.cal_macd_peak(), pre_bi.cal_macd_peak()) {
+            res.push(("bsp_macd_peak_ratio".to_string(), peak / pre_peak));
+        }
+        res.extend([
+            ("bsp_amp_ratio".to_string(), bi.amp() / (pre_bi.amp() + 1e-7)),
+            (
+                "bsp_slope_ratio".to_string(),
+                bi.cal_macd_metric(MacdAlgo::Slope, false).unwrap_or(0.0)
+                    / (pre_bi.cal_macd_metric(MacdAlgo::Slope, false).unwrap_or(0.0) + 1e-7),
+            ),
+        ]);
+        res
+    }
+}
+
+/// 买卖点前最近一个中枢的高度、宽度、买卖点到中枢的距离，以及所在线段中已结束的中枢个数
+pub struct ZsExtractor;
+
+impl<T: LineTrait> FeatureExtractor<T> for ZsExtractor {
+    fn name(&self) -> &str {
+        "zs"
+    }
+
+    fn extract(&self, ctx: &FeatureContext<T>) -> Vec<(String, f64)> {
+        let seg = match ctx.parent_seg() {
+            Some(seg) => seg,
+            None => return Vec::new(),
+        };
+        let bi = ctx.bsp.bi.borrow();
+        let seg_ref = seg.borrow();
+        let zs = match seg_ref.zs_lst.iter().rev().find(|zs| {
+            zs.begin_bi.as_ref().is_some_and(|begin_bi| begin_bi.borrow().idx() < bi.idx())
+        }) {
+            Some(zs) => zs,
+            None => return Vec::new(),
+        };
+        let (Some(low), Some(high), Some(mid)) = (zs.low, zs.high, zs.mid) else {
+            return Vec::new();
+        };
+        // 只统计买卖点出现时已经结束的中枢
+        let bsp_klu_idx = ctx.bsp.klu.index();
+        let seg_zs_cnt = seg_ref
+            .zs_lst
+            .iter()
+            .filter(|zs| zs.end.as_ref().is_some_and(|end| end.index() <= bsp_klu_idx))
+            .count();
+        let mut res = vec![
+            ("zs_height_rate".to_string(), (high - low) / mid),
+            ("zs_peak_height_rate".to_string(), (zs.peak_high - zs.peak_low) / mid),
+            ("bsp_zs_dis_rate".to_string(), (bi.get_end_val() - mid) / mid),
+            ("seg_zs_cnt".to_string(), seg_zs_cnt as f64),
+        ];
+        if let (Some(begin_bi), Some(end_bi)) = (&zs.begin_bi, &zs.end_bi) {
+            res.push((
+                "zs_bi_cnt".to_string(),
+                (end_bi.borrow().idx() - begin_bi.borrow().idx() + 1) as f64,
+            ));
+        }
+        if let (Some(begin), Some(end)) = (&zs.begin, &zs.end) {
+            res.push(("zs_klu_cnt".to_string(), (end.index() - begin.index() + 1) as f64));
+        }
+        res
+    }
+}
+
+/// 买卖点那根K线上已计算的指标
+pub struct IndicatorSnapshotExtractor;
+
+impl<T: LineTrait> FeatureExtractor<T> for IndicatorSnapshotExtractor {
+    fn name(&self) -> &str {
+        "indicator_snapshot"
+    }
+
+    fn extract(&self, ctx: &FeatureContext<T>) -> Vec<(String, f64)> {
+        let klu = &ctx.bsp.klu;
+        let mut res = Vec::new();
+        if let Some(macd) = &klu.macd {
+            res.push(("klu_macd".to_string(), macd.macd));
+            res.push(("klu_macd_dif".to_string(), macd.dif));
+            res.push(("klu_macd_dea".to_string(), macd.dea));
+        }
+        if let Some(rsi) = klu.rsi {
+            res.push(("klu_rsi".to_string(), rsi));
+        }
+        if let Some(kdj) = &klu.kdj {
+            res.push(("klu_kdj_k".to_string(), kdj.k));
+            res.push(("klu_kdj_d".to_string(), kdj.d));
+            res.push(("klu_kdj_j".to_string(), kdj.j));
+        }
+        if let Some(boll) = &klu.boll {
+            // 收盘价在布林带中的位置，0为下轨，1为上轨
+            res.push(("klu_boll_pos".to_string(), (klu.close - boll.down) / (boll.up - boll.down + 1e-7)));
+        }
+        if let Some(atr) = klu.atr {
+            res.push(("klu_atr_rate".to_string(), atr / klu.close));
+        }
+        if let Some(vwap) = klu.vwap {
+            res.push(("klu_vwap_dev".to_string(), (klu.close - vwap) / vwap));
+        }
+        res
+    }
+}
+
+/// 与关联的一类买卖点之间的距离
+pub struct RelateBsp1Extractor;
+
+impl<T: LineTrait> FeatureExtractor<T> for RelateBsp1Extractor {
+    fn name(&self) -> &str {
+        "relate_bsp1"
+    }
+
+    fn extract(&self, ctx: &FeatureContext<T>) -> Vec<(String, f64)> {
+        let bsp1 = match &ctx.bsp.relate_bsp1 {
+            Some(bsp1) => bsp1.borrow(),
+            None => return Vec::new(),
+        };
+        let bi = ctx.bsp.bi.borrow();
+        let bsp1_bi = bsp1.bi.borrow();
+        vec![
+            (
+                "relate_bsp1_klu_dis".to_string(),
+                (ctx.bsp.klu.index() - bsp1.klu.index()) as f64,
+            ),
+            ("relate_bsp1_bi_dis".to_string(), (bi.idx() - bsp1_bi.idx()) as f64),
+            (
+                "relate_bsp1_price_rate".to_string(),
+                bi.get_end_val() / bsp1_bi.get_end_val() - 1.0,
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::common::cenum::BspType;
+    use crate::common::test_util::{build_chan_fixture, ChanFixture, MockBi};
+
+    const VALS: [f64; 14] = [100.0, 60.0, 80.0, 65.0, 78.0, 66.0, 79.0, 55.0, 75.0, 62.0, 72.0, 64.0, 90.0, 76.0];
+
+    /// 下跌线段0的中枢为笔1~5（区间66~78，峰值60~80），笔6向下到55
+    fn fixture() -> ChanFixture {
+        build_chan_fixture(&VALS, &[(0, 6, true), (7, 11, false)], &[(1, 5), (8, 10)])
+    }
+
+    fn extract<E: FeatureExtractor<MockBi>>(extractor: &E, fixture: &ChanFixture, bsp: &BSPoint<MockBi>) -> HashMap<String, f64> {
+        let ctx = FeatureContext {
+            bsp,
+            bi_list: &fixture.bi_lst,
+            seg_list: &fixture.seg_list,
+        };
+        extractor.extract(&ctx).into_iter().collect()
+    }
+
+    fn assert_feat(feats: &HashMap<String, f64>, name: &str, expected: f64) {
+        let value = feats[name];
+        assert!((value - expected).abs() < 1e-9, "{}: {} != {}", name, value, expected);
+    }
+
+    fn bsp_at(fixture: &ChanFixture, bi_idx: usize, bs_type: BspType) -> BSPoint<MockBi> {
+        BSPoint::new(fixture.bi_lst[bi_idx].clone(), true, bs_type, None, None)
+    }
+
+    #[test]
+    fn test_bi_shape() {
+        let fixture = fixture();
+        let feats = extract(&BiShapeExtractor, &fixture, &bsp_at(&fixture, 6, BspType::T1));
+        assert_feat(&feats, "bsp_bi_klu_cnt", 2.0);
+        assert_feat(&feats, "bsp_bi_amp_rate", 24.0 / 79.0);
+        assert_feat(&feats, "bsp_bi_klu_amp_rate", 12.0 / 79.0);
+    }
+
+    #[test]
+    fn test_macd_ratio_without_macd() {
+        let fixture = fixture();
+        let feats = extract(&MacdRatioExtractor, &fixture, &bsp_at(&fixture, 6, BspType::T1));
+        // 没有计算MACD时不输出面积和峰值之比
+        assert_eq!(feats.len(), 2);
+        assert_feat(&feats, "bsp_amp_ratio", 24.0 / (12.0 + 1e-7));
+        assert_feat(&feats, "bsp_slope_ratio", (24.0 / 79.0 / 2.0) / (12.0 / 78.0 / 2.0 + 1e-7));
+
+        // 第一笔没有同向的前一笔
+        assert!(extract(&MacdRatioExtractor, &fixture, &bsp_at(&fixture, 1, BspType::T1)).is_empty());
+    }
+
+    #[test]
+    fn test_zs() {
+        let fixture = fixture();
+        let feats = extract(&ZsExtractor, &fixture, &bsp_at(&fixture, 6, BspType::T1));
+        assert_feat(&feats, "zs_height_rate", 12.0 / 72.0);
+        assert_feat(&feats, "zs_peak_height_rate", 20.0 / 72.0);
+        assert_feat(&feats, "bsp_zs_dis_rate", -17.0 / 72.0);
+        assert_feat(&feats, "seg_zs_cnt", 1.0);
+        assert_feat(&feats, "zs_bi_cnt", 5.0);
+        assert_feat(&feats, "zs_klu_cnt", 6.0);
+
+        // 线段之外的笔没有特征
+        assert!(extract(&ZsExtractor, &fixture, &bsp_at(&fixture, 12, BspType::T3A)).is_empty());
+    }
+
+    #[test]
+    fn test_zs_cnt_excludes_later_zs() {
+        // 两个中枢在同一个线段中，笔9结束时第二个中枢（笔8~10）还没有结束
+        let fixture = build_chan_fixture(&VALS, &[(0, 11, false)], &[(1, 5), (8, 10)]);
+        let feats = extract(&ZsExtractor, &fixture, &bsp_at(&fixture, 9, BspType::T2));
+        assert_feat(&feats, "seg_zs_cnt", 1.0);
+        let feats = extract(&ZsExtractor, &fixture, &bsp_at(&fixture, 11, BspType::T2));
+        assert_feat(&feats, "seg_zs_cnt", 2.0);
+    }
+
+    #[test]
+    fn test_indicator_snapshot() {
+        let mut fixture = fixture();
+        let klu = &mut fixture.klus[7];
+        klu.rsi = Some(30.0);
+        klu.atr = Some(1.1);
+        klu.vwap = Some(50.0);
+        let feats = extract(&IndicatorSnapshotExtractor, &fixture, &bsp_at(&fixture, 6, BspType::T1));
+        assert_eq!(feats.len(), 3);
+        assert_feat(&feats, "klu_rsi", 30.0);
+        assert_feat(&feats, "klu_atr_rate", 0.02);
+        assert_feat(&feats, "klu_vwap_dev", 0.1);
+    }
+
+    #[test]
+    fn test_relate_bsp1() {
+        let fixture = fixture();
+        let bsp1_lst = Box::new(vec![bsp_at(&fixture, 6, BspType::T1)]);
+        let bsp2 = BSPoint::new(
+            fixture.bi_lst[8].clone(),
+            true,
+            BspType::T2,
+            Some(Handle::new(&bsp1_lst, 0)),
+            None,
+        );
+        let feats = extract(&RelateBsp1Extractor, &fixture, &bsp2);
+        assert_feat(&feats, "relate_bsp1_klu_dis", 2.0);
+        assert_feat(&feats, "relate_bsp1_bi_dis", 2.0);
+        assert_feat(&feats, "relate_bsp1_price_rate", 62.0 / 55.0 - 1.0);
+
+        assert!(extract(&RelateBsp1Extractor, &fixture, &bsp1_lst[0]).is_empty());
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Index;
+
+/// 买卖点特征，key为特征名，按名字排序便于导出稳定的列
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Features {
+    features: BTreeMap<String, f64>,
+}
+
+impl Features {
+    pub fn new(init_feat: Option<HashMap<String, f64>>) -> Self {
+        Self {
+            features: init_feat.map(|feat| feat.into_iter().collect()).unwrap_or_default(),
+        }
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = (&String, f64)> {
+        self.features.iter().map(|(k, v)| (k, *v))
+    }
+
+    pub fn get(&self, k: &str) -> Option<f64> {
+        self.features.get(k).copied()
+    }
+
+    pub fn contains(&self, k: &str) -> bool {
+        self.features.contains_key(k)
+    }
+
+    pub fn len(&self) -> usize {
+        self.features.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    /// 批量添加特征，同名特征会被覆盖
+    pub fn add_feat<I: IntoIterator<Item = (String, f64)>>(&mut self, feats: I) {
+        self.features.extend(feats);
+    }
+
+    pub fn add(&mut self, k: impl Into<String>, v: f64) {
+        self.features.insert(k.into(), v);
+    }
+}
+
+impl Index<&str> for Features {
+    type Output = f64;
+
+    fn index(&self, k: &str) -> &f64 {
+        &self.features[k]
+    }
+}
+
+impl From<HashMap<String, f64>> for Features {
+    fn from(feat: HashMap<String, f64>) -> Self {
+        Self::new(Some(feat))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_and_overwrite() {
+        let mut features = Features::new(Some(HashMap::from([("b".to_string(), 1.0)])));
+        features.add("a", 2.0);
+        features.add_feat([("b".to_string(), 3.0), ("c".to_string(), 4.0)]);
+
+        assert_eq!(features["b"], 3.0);
+        assert_eq!(features.get("d"), None);
+        let keys: Vec<&String> = features.items().map(|(k, _)| k).collect();
+        assert_eq!(keys, ["a", "b", "c"]);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::buy_sell_point::bs_point::BSPoint;
+use crate::buy_sell_point::bs_point_list::BSPointList;
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::line_trait::LineTrait;
+
+use super::features::Features;
+
+/// 止损价的取法
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopRule {
+    /// 买卖点所在笔的终点，即买卖点之后那一笔的起点价格
+    BspPeak,
+    /// 按入场价的比例，如0.03表示买点下方3%、卖点上方3%
+    Rate(f64),
+}
+
+#[derive(Debug, Clone)]
+pub struct LabelConfig {
+    /// 计算远期收益的K线数
+    pub horizons: Vec<usize>,
+    /// 计算MFE/MAE以及判断止盈止损先后的最大K线数
+    pub max_hold: usize,
+    /// 止盈比例，None时不判断止盈止损先后
+    pub target_rate: Option<f64>,
+    pub stop: StopRule,
+}
+
+impl Default for LabelConfig {
+    fn default() -> Self {
+        Self {
+            horizons: vec![1, 5, 10, 20],
+            max_hold: 20,
+            target_rate: Some(0.05),
+            stop: StopRule::BspPeak,
+        }
+    }
+}
+
+/// 给历史买卖点打标签：远期收益、最大有利/不利波动、止盈止损哪个先触发
+///
+/// 标签只用买卖点之后的K线，单独存放在`BSPoint.labels`，不会混入特征；
+/// 所有收益都按买卖方向调整，正数表示买卖点判断正确。
+/// 之后K线不足的标签不输出，导出时视为缺失
+#[derive(Debug, Clone, Default)]
+pub struct BspLabeler {
+    pub config: LabelConfig,
+}
+
+impl BspLabeler {
+    pub fn new(config: LabelConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn label<T: LineTrait>(&self, bsp: &BSPoint<T>) -> Features {
+        self.label_klu(bsp.is_buy, &bsp.klu, bsp.bi.borrow().get_end_val())
+    }
+
+    /// 以klu的收盘价入场，bsp_peak为买卖点所在笔的终点价格，StopRule::BspPeak时作为止损价
+    pub fn label_klu(&self, is_buy: bool, klu: &KLineUnit, bsp_peak: f64) -> Features {
+        let entry = klu.close;
+        let stop = match self.config.stop {
+            StopRule::BspPeak => bsp_peak,
+            StopRule::Rate(rate) => {
+                if is_buy {
+                    entry * (1.0 - rate)
+                } else {
+                    entry * (1.0 + rate)
+                }
+            }
+        };
+        let bars = std::iter::successors(klu.next(), |klu| klu.next()).map(|klu| (klu.high, klu.low, klu.close));
+        self.label_bars(is_buy, entry, stop, bars)
+    }
+
+    /// 给列表中的全部买卖点打标签
+    pub fn label_all<T: LineTrait>(&self, bsp_list: &BSPointList<T>) {
+        for bsp in bsp_list.iter() {
+            let labels = self.label(bsp.borrow());
+            bsp.borrow_mut().labels = labels;
+        }
+    }
+
+    /// bars为买卖点之后的(high, low, close)
+    pub fn label_bars<I>(&self, is_buy: bool, entry: f64, stop: f64, bars: I) -> Features
+    where
+        I: IntoIterator<Item = (f64, f64, f64)>,
+    {
+        let max_horizon = self.config.horizons.iter().copied().max().unwrap_or(0);
+        let bars: Vec<(f64, f64, f64)> = bars.into_iter().take(max_horizon.max(self.config.max_hold)).collect();
+        let sign = if is_buy { 1.0 } else { -1.0 };
+        // 方向调整后的收益率
+        let ret = |price: f64| sign * (price / entry - 1.0);
+
+        let mut labels = Features::default();
+        for &horizon in &self.config.horizons {
+            if let Some(&(_, _, close)) = bars.get(horizon.wrapping_sub(1)) {
+                labels.add(format!("ret_{}", horizon), ret(close));
+            }
+        }
+
+        let hold = &bars[..bars.len().min(self.config.max_hold)];
+        if hold.len() == self.config.max_hold && !hold.is_empty() {
+            let (mut mfe, mut mae) = (0.0f64, 0.0f64);
+            for &(high, low, _) in hold {
+                let (favourable, adverse) = if is_buy { (high, low) } else { (low, high) };
+                mfe = mfe.max(ret(favourable));
+                mae = mae.max(-ret(adverse));
+            }
+            labels.add("mfe", mfe);
+            labels.add("mae", mae);
+        }
+
+        if let Some(target_rate) = self.config.target_rate {
+            let target = entry * (1.0 + sign * target_rate);
+            let hit = hold.iter().enumerate().find_map(|(idx, &(high, low, _))| {
+                let (stop_hit, target_hit) = if is_buy {
+                    (low <= stop, high >= target)
+                } else {
+                    (high >= stop, low <= target)
+                };
+                // 同一根K线内先后未知，保守地算作止损
+                if stop_hit {
+                    Some((-1.0, idx + 1))
+                } else if target_hit {
+                    Some((1.0, idx + 1))
+                } else {
+                    None
+                }
+            });
+            match hit {
+                Some((first, bar_cnt)) => {
+                    labels.add("hit_first", first);
+                    labels.add("hit_bars", bar_cnt as f64);
+                }
+                // 持有期内均未触发，只有看完整个持有期才能确定
+                None if hold.len() == self.config.max_hold => labels.add("hit_first", 0.0),
+                None => {}
+            }
+        }
+        labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labeler() -> BspLabeler {
+        BspLabeler::new(LabelConfig {
+            horizons: vec![1, 3, 10],
+            max_hold: 4,
+            target_rate: Some(0.1),
+            stop: StopRule::BspPeak,
+        })
+    }
+
+    #[test]
+    fn test_buy_labels() {
+        let bars = [(10.5, 9.8, 10.2), (11.2, 10.1, 11.0), (11.0, 10.6, 10.8), (10.9, 9.0, 9.5), (12.0, 11.0, 11.5)];
+        let labels = labeler().label_bars(true, 10.0, 9.5, bars);
+
+        assert!((labels["ret_1"] - 0.02).abs() < 1e-12);
+        assert!((labels["ret_3"] - 0.08).abs() < 1e-12);
+        // 之后的K线不足10根
+        assert!(!labels.contains("ret_10"));
+        assert!((labels["mfe"] - 0.12).abs() < 1e-12);
+        assert!((labels["mae"] - 0.1).abs() < 1e-12);
+        assert_eq!(labels["hit_first"], 1.0);
+        assert_eq!(labels["hit_bars"], 2.0);
+    }
+
+    #[test]
+    fn test_sell_labels_stop_first() {
+        let bars = [(10.2, 9.9, 10.0), (10.6, 9.7, 10.4), (10.0, 8.8, 9.0), (9.2, 8.5, 8.6)];
+        let labels = labeler().label_bars(false, 10.0, 10.5, bars);
+
+        assert!((labels["ret_1"] - 0.0).abs() < 1e-12);
+        assert!((labels["ret_3"] - 0.1).abs() < 1e-12);
+        assert!((labels["mfe"] - 0.15).abs() < 1e-12);
+        assert_eq!(labels["hit_first"], -1.0);
+        assert_eq!(labels["hit_bars"], 2.0);
+    }
+
+    #[test]
+    fn test_no_hit_needs_full_hold() {
+        let bars = [(10.1, 9.9, 10.0), (10.1, 9.9, 10.0)];
+        let labels = labeler().label_bars(true, 10.0, 9.0, bars);
+        assert!(!labels.contains("hit_first"));
+        assert!(!labels.contains("mfe"));
+
+        let labels = labeler().label_bars(true, 10.0, 9.0, [(10.1, 9.9, 10.0); 4]);
+        assert_eq!(labels["hit_first"], 0.0);
+    }
+}
@@ -0,0 +1,5 @@
+pub mod dataset;
+pub mod feature_extractor;
+pub mod features;
+pub mod labeler;
+pub mod scorer;
@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::common::chan_exception::{ChanException, ErrCode};
+
+use super::features::Features;
+
+/// 买卖点打分模型，输入买卖点特征，输出成功概率
+pub trait BspScorer: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn score(&self, features: &Features) -> Result<f64, ChanException>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScorerType {
+    Logistic,
+    XGBoost,
+    LightGBM,
+}
+
+impl std::str::FromStr for ScorerType {
+    type Err = ChanException;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lr" | "logistic" => Ok(Self::Logistic),
+            "xgboost" => Ok(Self::XGBoost),
+            "lightgbm" => Ok(Self::LightGBM),
+            _ => Err(ChanException::new(format!("unknown scorer type: {}", s), ErrCode::ModelError)),
+        }
+    }
+}
+
+/// 加载模型时的可选参数，只对XGBoost生效
+#[derive(Debug, Clone, Default)]
+pub struct ScorerOption {
+    /// 覆盖模型json里的base_score，两者都没有时取0.5
+    pub base_score: Option<f64>,
+    /// 特征映射文件，把训练时的`fN`特征名还原为特征名
+    pub fmap: Option<String>,
+}
+
+/// 从json文件加载模型
+pub fn load_scorer(
+    path: &str,
+    scorer_type: ScorerType,
+    option: &ScorerOption,
+) -> Result<Arc<dyn BspScorer>, ChanException> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| ChanException::new(format!("read model {} fail: {}", path, e), ErrCode::ModelError))?;
+    Ok(match scorer_type {
+        ScorerType::Logistic => Arc::new(LogisticRegression::from_json(&content)?),
+        ScorerType::XGBoost => {
+            let mut model = GbdtModel::from_xgboost_json(&content, option.base_score)?;
+            if let Some(fmap) = &option.fmap {
+                let fmap_content = std::fs::read_to_string(fmap)
+                    .map_err(|e| model_err(format!("read fmap {} fail: {}", fmap, e)))?;
+                model.rename_features(&parse_fmap(&fmap_content)?);
+            }
+            Arc::new(model)
+        }
+        ScorerType::LightGBM => Arc::new(GbdtModel::from_lightgbm_json(&content)?),
+    })
+}
+
+/// 解析XGBoost的fmap文件，每行`<idx>\t<name>\t<type>`，返回`fN` -> 特征名
+pub fn parse_fmap(content: &str) -> Result<HashMap<String, String>, ChanException> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut items = line.split('\t');
+            match (items.next().map(|idx| idx.trim().parse::<usize>()), items.next()) {
+                (Some(Ok(idx)), Some(name)) => Ok((format!("f{}", idx), name.to_string())),
+                _ => Err(model_err(format!("bad fmap line: {}", line))),
+            }
+        })
+        .collect()
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+fn logit(p: f64) -> f64 {
+    (p / (1.0 - p)).ln()
+}
+
+fn model_err(msg: impl Into<String>) -> ChanException {
+    ChanException::new(msg, ErrCode::ModelError)
+}
+
+/// 逻辑回归，json格式`{"intercept": 0.1, "weights": {"feat": 0.5}, "missing": 0.0}`
+///
+/// 缺失的特征按missing取值（默认0）
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogisticRegression {
+    pub intercept: f64,
+    pub weights: HashMap<String, f64>,
+    #[serde(default)]
+    pub missing: f64,
+}
+
+impl LogisticRegression {
+    pub fn from_json(content: &str) -> Result<Self, ChanException> {
+        serde_json::from_str(content).map_err(|e| model_err(format!("parse logistic model fail: {}", e)))
+    }
+}
+
+impl BspScorer for LogisticRegression {
+    fn name(&self) -> &str {
+        "logistic"
+    }
+
+    fn score(&self, features: &Features) -> Result<f64, ChanException> {
+        let margin = self.weights.iter().fold(self.intercept, |acc, (name, weight)| {
+            acc + weight * features.get(name).unwrap_or(self.missing)
+        });
+        Ok(sigmoid(margin))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum TreeNode {
+    Leaf(f64),
+    Split {
+        feature: String,
+        threshold: f64,
+        /// true时value <= threshold走左边(LightGBM)，否则value < threshold走左边(XGBoost)
+        inclusive: bool,
+        /// 特征缺失时是否走左边
+        default_left: bool,
+        left: Box<TreeNode>,
+        right: Box<TreeNode>,
+    },
+}
+
+impl TreeNode {
+    fn rename_features(&mut self, names: &HashMap<String, String>) {
+        if let TreeNode::Split {
+            feature, left, right, ..
+        } = self
+        {
+            if let Some(name) = names.get(feature.as_str()) {
+                *feature = name.clone();
+            }
+            left.rename_features(names);
+            right.rename_features(names);
+        }
+    }
+
+    fn predict(&self, features: &Features) -> f64 {
+        let mut node = self;
+        loop {
+            match node {
+                TreeNode::Leaf(value) => return *value,
+                TreeNode::Split {
+                    feature,
+                    threshold,
+                    inclusive,
+                    default_left,
+                    left,
+                    right,
+                } => {
+                    let go_left = match features.get(feature) {
+                        Some(value) if value.is_nan() => *default_left,
+                        Some(value) if *inclusive => value <= *threshold,
+                        Some(value) => value < *threshold,
+                        None => *default_left,
+                    };
+                    node = if go_left { left } else { right };
+                }
+            }
+        }
+    }
+}
+
+/// 梯度提升树，纯Rust计算，支持XGBoost的`dump_model(dump_format="json")`
+/// 和LightGBM的`dump_model()`两种json格式，输出sigmoid(各树叶子之和 + base_margin)
+#[derive(Debug, Clone)]
+pub struct GbdtModel {
+    trees: Vec<TreeNode>,
+    pub base_margin: f64,
+}
+
+impl GbdtModel {
+    pub fn tree_cnt(&self) -> usize {
+        self.trees.len()
+    }
+
+    /// 支持两种格式：`dump_model(dump_format="json")`输出的树数组，
+    /// 或者`{"base_score": 0.5, "trees": [...]}`，base_score可以是数字或字符串（如`"5E-1"`）；
+    /// logistic目标下base_margin = logit(base_score)，base_score参数优先于json里的值，都没有时取0.5
+    pub fn from_xgboost_json(content: &str, base_score: Option<f64>) -> Result<Self, ChanException> {
+        let value: Value =
+            serde_json::from_str(content).map_err(|e| model_err(format!("parse xgboost model fail: {}", e)))?;
+        let (trees, json_base_score) = match &value {
+            Value::Array(trees) => (trees, None),
+            Value::Object(_) => {
+                let trees = value["trees"]
+                    .as_array()
+                    .ok_or_else(|| model_err("xgboost model missing trees"))?;
+                (trees, parse_base_score(&value["base_score"])?)
+            }
+            _ => return Err(model_err("xgboost dump should be an array of trees")),
+        };
+        let base_score = base_score.or(json_base_score).unwrap_or(0.5);
+        if !(base_score > 0.0 && base_score < 1.0) {
+            return Err(model_err(format!("base_score should be in (0, 1): {}", base_score)));
+        }
+        let trees = trees.iter().map(parse_xgboost_node).collect::<Result<_, _>>()?;
+        Ok(Self {
+            trees,
+            base_margin: logit(base_score),
+        })
+    }
+
+    /// 按names替换分裂特征名，用于把`fN`还原为fmap里的特征名
+    pub fn rename_features(&mut self, names: &HashMap<String, String>) {
+        for tree in &mut self.trees {
+            tree.rename_features(names);
+        }
+    }
+
+    pub fn from_lightgbm_json(content: &str) -> Result<Self, ChanException> {
+        let value: Value =
+            serde_json::from_str(content).map_err(|e| model_err(format!("parse lightgbm model fail: {}", e)))?;
+        let feature_names: Vec<String> = value["feature_names"]
+            .as_array()
+            .ok_or_else(|| model_err("lightgbm model missing feature_names"))?
+            .iter()
+            .map(|name| name.as_str().unwrap_or_default().to_string())
+            .collect();
+        let trees = value["tree_info"]
+            .as_array()
+            .ok_or_else(|| model_err("lightgbm model missing tree_info"))?
+            .iter()
+            .map(|tree| parse_lightgbm_node(&tree["tree_structure"], &feature_names))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { trees, base_margin: 0.0 })
+    }
+}
+
+impl BspScorer for GbdtModel {
+    fn name(&self) -> &str {
+        "gbdt"
+    }
+
+    fn score(&self, features: &Features) -> Result<f64, ChanException> {
+        let margin = self.trees.iter().map(|tree| tree.predict(features)).sum::<f64>();
+        Ok(sigmoid(margin + self.base_margin))
+    }
+}
+
+fn get_f64(node: &Value, key: &str) -> Result<f64, ChanException> {
+    node[key]
+        .as_f64()
+        .ok_or_else(|| model_err(format!("tree node missing {}: {}", key, node)))
+}
+
+fn parse_base_score(value: &Value) -> Result<Option<f64>, ChanException> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Number(num) => Ok(num.as_f64()),
+        Value::String(num) => num
+            .parse()
+            .map(Some)
+            .map_err(|_| model_err(format!("bad base_score: {}", value))),
+        _ => Err(model_err(format!("bad base_score: {}", value))),
+    }
+}
+
+fn parse_xgboost_node(node: &Value) -> Result<TreeNode, ChanException> {
+    if let Some(leaf) = node["leaf"].as_f64() {
+        return Ok(TreeNode::Leaf(leaf));
+    }
+    let feature = node["split"]
+        .as_str()
+        .ok_or_else(|| model_err(format!("tree node missing split: {}", node)))?
+        .to_string();
+    let children = node["children"]
+        .as_array()
+        .ok_or_else(|| model_err(format!("tree node missing children: {}", node)))?;
+    let find_child = |key: &str| -> Result<TreeNode, ChanException> {
+        let child_id = node[key].as_u64();
+        let child = children
+            .iter()
+            .find(|child| child["nodeid"].as_u64() == child_id)
+            .ok_or_else(|| model_err(format!("tree node {} child not found: {}", key, node)))?;
+        parse_xgboost_node(child)
+    };
+    Ok(TreeNode::Split {
+        feature,
+        threshold: get_f64(node, "split_condition")?,
+        inclusive: false,
+        default_left: node["missing"] == node["yes"],
+        left: Box::new(find_child("yes")?),
+        right: Box::new(find_child("no")?),
+    })
+}
+
+fn parse_lightgbm_node(node: &Value, feature_names: &[String]) -> Result<TreeNode, ChanException> {
+    if let Some(leaf) = node["leaf_value"].as_f64() {
+        return Ok(TreeNode::Leaf(leaf));
+    }
+    let feature_idx = node["split_feature"]
+        .as_u64()
+        .ok_or_else(|| model_err(format!("tree node missing split_feature: {}", node)))?;
+    let feature = feature_names
+        .get(feature_idx as usize)
+        .ok_or_else(|| model_err(format!("split_feature {} out of range", feature_idx)))?
+        .clone();
+    if node["decision_type"].as_str().unwrap_or("<=") != "<=" {
+        return Err(model_err(format!("unsupported decision_type: {}", node["decision_type"])));
+    }
+    Ok(TreeNode::Split {
+        feature,
+        threshold: get_f64(node, "threshold")?,
+        inclusive: true,
+        default_left: node["default_left"].as_bool().unwrap_or(true),
+        left: Box::new(parse_lightgbm_node(&node["left_child"], feature_names)?),
+        right: Box::new(parse_lightgbm_node(&node["right_child"], feature_names)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(items: &[(&str, f64)]) -> Features {
+        let mut res = Features::default();
+        res.add_feat(items.iter().map(|(k, v)| (k.to_string(), *v)));
+        res
+    }
+
+    #[test]
+    fn test_logistic() {
+        let model =
+            LogisticRegression::from_json(r#"{"intercept": -1.0, "weights": {"a": 2.0, "b": -0.5}}"#).unwrap();
+        let score = model.score(&features(&[("a", 1.0), ("b", 2.0)])).unwrap();
+        assert!((score - 0.5).abs() < 1e-12);
+        // b缺失按0计算
+        let score = model.score(&features(&[("a", 1.0)])).unwrap();
+        assert!((score - sigmoid(1.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_xgboost_dump() {
+        let dump = r#"[
+            {"nodeid": 0, "depth": 0, "split": "a", "split_condition": 1.5, "yes": 1, "no": 2, "missing": 2,
+             "children": [
+                {"nodeid": 1, "leaf": -0.4},
+                {"nodeid": 2, "depth": 1, "split": "b", "split_condition": 0.0, "yes": 3, "no": 4, "missing": 3,
+                 "children": [{"nodeid": 3, "leaf": 0.1}, {"nodeid": 4, "leaf": 0.6}]}
+             ]},
+            {"nodeid": 0, "leaf": 0.2}
+        ]"#;
+        let model = GbdtModel::from_xgboost_json(dump, None).unwrap();
+        assert_eq!(model.tree_cnt(), 2);
+        let check = |items: &[(&str, f64)], margin: f64| {
+            assert!((model.score(&features(items)).unwrap() - sigmoid(margin)).abs() < 1e-12);
+        };
+        check(&[("a", 1.0)], -0.2);
+        // 等于阈值走右边
+        check(&[("a", 1.5), ("b", 1.0)], 0.8);
+        // a缺失走右边，b缺失走左边
+        check(&[], 0.3);
+    }
+
+    #[test]
+    fn test_xgboost_base_score() {
+        let dump = r#"{"base_score": "2E-1", "trees": [{"nodeid": 0, "leaf": 0.3}]}"#;
+        let model = GbdtModel::from_xgboost_json(dump, None).unwrap();
+        assert!((model.base_margin - logit(0.2)).abs() < 1e-12);
+        assert!((model.score(&features(&[])).unwrap() - sigmoid(0.3 + logit(0.2))).abs() < 1e-12);
+        // 参数优先于json
+        let model = GbdtModel::from_xgboost_json(dump, Some(0.5)).unwrap();
+        assert!(model.base_margin.abs() < 1e-12);
+        // 树数组没有base_score，取0.5
+        let model = GbdtModel::from_xgboost_json(r#"[{"nodeid": 0, "leaf": 0.3}]"#, None).unwrap();
+        assert!(model.base_margin.abs() < 1e-12);
+
+        assert!(GbdtModel::from_xgboost_json(r#"[{"nodeid": 0, "leaf": 0.3}]"#, Some(1.0)).is_err());
+        assert!(GbdtModel::from_xgboost_json(r#"{"base_score": "x", "trees": []}"#, None).is_err());
+    }
+
+    #[test]
+    fn test_xgboost_fmap() {
+        let fmap = parse_fmap("0\t_unused\tq\n1\tis_buy\ti\n3\ta\tq\n").unwrap();
+        assert_eq!(fmap["f3"], "a");
+        assert_eq!(fmap["f1"], "is_buy");
+        assert!(parse_fmap("a\tb\tq").is_err());
+
+        let dump = r#"[{"nodeid": 0, "split": "f3", "split_condition": 1.5, "yes": 1, "no": 2, "missing": 2,
+            "children": [{"nodeid": 1, "leaf": -0.4}, {"nodeid": 2, "leaf": 0.6}]}]"#;
+        let mut model = GbdtModel::from_xgboost_json(dump, None).unwrap();
+        // 未映射前按f3查找，a不生效，缺失走右边
+        assert!((model.score(&features(&[("a", 1.0)])).unwrap() - sigmoid(0.6)).abs() < 1e-12);
+        model.rename_features(&fmap);
+        assert!((model.score(&features(&[("a", 1.0)])).unwrap() - sigmoid(-0.4)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_lightgbm_dump() {
+        let dump = r#"{
+            "feature_names": ["a", "b"],
+            "tree_info": [{"tree_structure": {
+                "split_feature": 1, "threshold": 2.0, "decision_type": "<=", "default_left": false,
+                "left_child": {"leaf_value": -0.3},
+                "right_child": {"leaf_value": 0.7}
+            }}]
+        }"#;
+        let model = GbdtModel::from_lightgbm_json(dump).unwrap();
+        let score = |items: &[(&str, f64)]| model.score(&features(items)).unwrap();
+        assert!((score(&[("b", 2.0)]) - sigmoid(-0.3)).abs() < 1e-12);
+        assert!((score(&[]) - sigmoid(0.7)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_bad_model() {
+        let err = GbdtModel::from_xgboost_json(r#"{"not": "a dump"}"#, None).unwrap_err();
+        assert_eq!(err.errcode, ErrCode::ModelError);
+        assert!("svm".parse::<ScorerType>().is_err());
+    }
+}
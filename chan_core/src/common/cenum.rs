@@ -130,7 +130,7 @@ pub enum AuType {
 }
 
 /// Trend analysis types
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumString, serde::Serialize, serde::Deserialize)]
 pub enum TrendType {
     #[strum(serialize = "mean")]
     Mean,
@@ -190,25 +190,53 @@ pub enum MacdAlgo {
     AmountAvg,
     TurnrateAvg,
     Rsi,
+    /// 振幅除以起点的ATR，需开启cal_atr
+    AtrAmp,
+    /// 首尾OBV之差的绝对值，需开启cal_obv
+    Obv,
+}
+
+/// Data fields of a kline, also the keys of kl_dict and trade_info
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumString)]
+pub enum DataField {
+    #[strum(serialize = "time_key")]
+    FieldTime,
+    #[strum(serialize = "open")]
+    FieldOpen,
+    #[strum(serialize = "high")]
+    FieldHigh,
+    #[strum(serialize = "low")]
+    FieldLow,
+    #[strum(serialize = "close")]
+    FieldClose,
+    /// 成交量
+    #[strum(serialize = "volume")]
+    FieldVolume,
+    /// 成交额
+    #[strum(serialize = "turnover")]
+    FieldTurnover,
+    /// 换手率
+    #[strum(serialize = "turnover_rate")]
+    FieldTurnrate,
 }
 
-/// Data field constants
-#[derive(Debug, Clone)]
-pub struct DataField;
-
 impl DataField {
-    pub const FIELD_TIME: &'static str = "time_key";
-    pub const FIELD_OPEN: &'static str = "open";
-    pub const FIELD_HIGH: &'static str = "high";
-    pub const FIELD_LOW: &'static str = "low";
-    pub const FIELD_CLOSE: &'static str = "close";
-    pub const FIELD_VOLUME: &'static str = "volume"; // 成交量
-    pub const FIELD_TURNOVER: &'static str = "turnover"; // 成交额
-    pub const FIELD_TURNRATE: &'static str = "turnover_rate"; // 换手率
-}
-
-pub const TRADE_INFO_LST: &[&str] = &[
-    DataField::FIELD_VOLUME,
-    DataField::FIELD_TURNOVER,
-    DataField::FIELD_TURNRATE,
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::FieldTime => "time_key",
+            Self::FieldOpen => "open",
+            Self::FieldHigh => "high",
+            Self::FieldLow => "low",
+            Self::FieldClose => "close",
+            Self::FieldVolume => "volume",
+            Self::FieldTurnover => "turnover",
+            Self::FieldTurnrate => "turnover_rate",
+        }
+    }
+}
+
+pub const TRADE_INFO_LST: &[DataField] = &[
+    DataField::FieldVolume,
+    DataField::FieldTurnover,
+    DataField::FieldTurnrate,
 ];
@@ -6,7 +6,7 @@ pub enum FxCheckMethod {
     Totally,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display, serde::Serialize, serde::Deserialize)]
 pub enum BiDir {
     Up,
     Down,
@@ -1,4 +1,9 @@
+pub mod cenum;
 pub mod chan_exception;
 pub mod enums;
 pub mod handle;
+pub mod sample_data;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod time;
 pub mod utils;
@@ -0,0 +1,27 @@
+//! 确定性的示例K线，单元测试和命令行的测试共用同一份走势
+
+use std::collections::HashMap;
+
+use crate::common::cenum::DataField;
+use crate::common::time::Time;
+use crate::trade::optimize::KlData;
+
+/// 带上升趋势、振幅缓慢变化的正弦走势，每天一根，每根KLU的高低点相差0.4
+pub fn sine_kl_data(cnt: usize) -> Vec<KlData> {
+    (0..cnt)
+        .map(|idx| {
+            let x = idx as f64;
+            let price = 10.0 + 0.02 * x + 3.0 * (x / 4.0).sin() * (1.0 + 0.5 * (x / 37.0).sin());
+            KlData {
+                time: Time::new(1_704_067_200 + idx as i64 * 86_400),
+                kl_dict: HashMap::from([
+                    (DataField::FieldOpen, price),
+                    (DataField::FieldHigh, price + 0.2),
+                    (DataField::FieldLow, price - 0.2),
+                    (DataField::FieldClose, price),
+                    (DataField::FieldVolume, 1000.0),
+                ]),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,177 @@
+//! 单元测试共用的笔序列构造工具
+
+use std::collections::HashMap;
+
+use crate::common::cenum::DataField;
+use crate::common::enums::BiDir;
+use crate::common::handle::Handle;
+use crate::kline::kline_unit::KLineUnit;
+use crate::seg::seg::Seg;
+use crate::seg::seg_list_comm::SegListComm;
+use crate::traits::line_trait::LineTrait;
+use crate::zs::zs::ZS;
+
+pub use crate::common::sample_data::sine_kl_data;
+
+/// 只保留端点信息的笔，用于构造固定的笔序列
+#[derive(Debug, Clone)]
+pub struct MockBi {
+    pub handle: Handle<MockBi>,
+    pub idx: usize,
+    pub begin_klu: Handle<KLineUnit>,
+    pub end_klu: Handle<KLineUnit>,
+    pub begin_val: f64,
+    pub end_val: f64,
+    pub seg_idx: Option<usize>,
+    /// 所属线段的方向，由build_seg_list或set_parent_seg设置
+    pub parent_seg_dir: Option<BiDir>,
+}
+
+impl MockBi {
+    pub fn dir(&self) -> BiDir {
+        if self.is_up() { BiDir::Up } else { BiDir::Down }
+    }
+    pub fn pre(&self) -> Option<Handle<MockBi>> { self.handle.prev() }
+    pub fn high(&self) -> f64 { self._high() }
+    pub fn low(&self) -> f64 { self._low() }
+    pub fn set_parent_seg(&mut self, seg: Handle<Seg<MockBi>>) { self.parent_seg_dir = Some(seg.borrow().dir); }
+    pub fn set_bsp<B>(&mut self, _bsp: B) {}
+}
+
+impl LineTrait for MockBi {
+    fn get_begin_klu(&self) -> Handle<KLineUnit> { self.begin_klu.clone() }
+    fn get_end_klu(&self) -> Handle<KLineUnit> { self.end_klu.clone() }
+    fn _low(&self) -> f64 { self.begin_val.min(self.end_val) }
+    fn _high(&self) -> f64 { self.begin_val.max(self.end_val) }
+    fn idx(&self) -> usize { self.idx }
+    fn seg_idx(&self) -> Option<usize> { self.seg_idx }
+    fn set_seg_idx(&mut self, idx: usize) { self.seg_idx = Some(idx); }
+    fn get_begin_val(&self) -> f64 { self.begin_val }
+    fn get_end_val(&self) -> f64 { self.end_val }
+    fn is_down(&self) -> bool { self.end_val < self.begin_val }
+    fn is_up(&self) -> bool { self.end_val > self.begin_val }
+    fn is_sure(&self) -> bool { true }
+    fn parent_seg_dir(&self) -> Option<BiDir> { self.parent_seg_dir }
+}
+
+/// 每个价格生成一根开高低收都等于该价格的KLU
+pub fn build_klu_lst(vals: &[f64]) -> Box<Vec<KLineUnit>> {
+    let mut klus = Box::new(Vec::new());
+    for (idx, val) in vals.iter().enumerate() {
+        let kl_dict = HashMap::from([
+            (DataField::FieldOpen, *val),
+            (DataField::FieldHigh, *val),
+            (DataField::FieldLow, *val),
+            (DataField::FieldClose, *val),
+        ]);
+        let klu = KLineUnit::new(&klus, idx, &kl_dict, false).unwrap();
+        klus.push(klu);
+    }
+    klus
+}
+
+/// 依次连接vals中相邻的端点生成笔，第i个端点对应第i根KLU
+pub fn build_bi_lst(vals: &[f64]) -> (Box<Vec<KLineUnit>>, Box<Vec<MockBi>>) {
+    let klus = build_klu_lst(vals);
+    let mut bis = Box::new(Vec::new());
+    for idx in 0..vals.len() - 1 {
+        let bi = MockBi {
+            handle: Handle::new(&bis, idx),
+            idx,
+            begin_klu: Handle::new(&klus, idx),
+            end_klu: Handle::new(&klus, idx + 1),
+            begin_val: vals[idx],
+            end_val: vals[idx + 1],
+            seg_idx: None,
+            parent_seg_dir: None,
+        };
+        bis.push(bi);
+    }
+    (klus, bis)
+}
+
+/// 笔列表中每根笔的Handle
+#[allow(clippy::borrowed_box)]
+pub fn bi_handles(bis: &Box<Vec<MockBi>>) -> Vec<Handle<MockBi>> {
+    (0..bis.len()).map(|idx| Handle::new(bis, idx)).collect()
+}
+
+/// 按给定区间手工构造线段，segs为每个线段的(起始笔, 结束笔, 是否确定)
+///
+/// 线段内的笔设置seg_idx和parent_seg_dir，线段之外的笔保持None；相邻线段互相设置pre/next
+pub fn build_seg_list(
+    bi_lst: &[Handle<MockBi>],
+    segs: &[(usize, usize, bool)],
+) -> (Box<Vec<Seg<MockBi>>>, SegListComm<MockBi>) {
+    let mut seg_box = Box::new(Vec::new());
+    for (idx, (begin, end, is_sure)) in segs.iter().enumerate() {
+        for bi in &bi_lst[*begin..=*end] {
+            bi.borrow_mut().seg_idx = Some(idx);
+        }
+        let mut seg = Seg::new_with_handle(
+            Handle::new(&seg_box, idx),
+            bi_lst[*begin].clone(),
+            bi_lst[*end].clone(),
+            Some(*is_sure),
+            None,
+            None,
+        )
+        .unwrap();
+        seg.bi_list = bi_lst[*begin..=*end].to_vec();
+        for bi in &seg.bi_list {
+            bi.borrow_mut().parent_seg_dir = Some(seg.dir);
+        }
+        seg_box.push(seg);
+    }
+    let mut seg_list = SegListComm::new(None, None);
+    seg_list.lst = (0..seg_box.len()).map(|idx| Handle::new(&seg_box, idx)).collect();
+    for idx in 1..seg_list.len() {
+        seg_list.lst[idx - 1].borrow_mut().next = Some(seg_list.lst[idx].clone());
+        seg_list.lst[idx].borrow_mut().pre = Some(seg_list.lst[idx - 1].clone());
+    }
+    (seg_box, seg_list)
+}
+
+/// 笔、线段和中枢都按给定区间手工构造，不依赖线段和中枢算法
+pub struct ChanFixture {
+    pub klus: Box<Vec<KLineUnit>>,
+    _bis: Box<Vec<MockBi>>,
+    _zs_lst: Box<Vec<ZS<MockBi>>>,
+    _segs: Box<Vec<Seg<MockBi>>>,
+    pub bi_lst: Vec<Handle<MockBi>>,
+    pub seg_list: SegListComm<MockBi>,
+}
+
+/// segs见build_seg_list，zs_ranges为每个中枢的(起始笔, 结束笔)；
+/// 中枢的进出笔取其前后各一笔，并挂到起始笔所在的线段上
+pub fn build_chan_fixture(vals: &[f64], segs: &[(usize, usize, bool)], zs_ranges: &[(usize, usize)]) -> ChanFixture {
+    let (klus, bis) = build_bi_lst(vals);
+    let bi_lst = bi_handles(&bis);
+    let (seg_box, seg_list) = build_seg_list(&bi_lst, segs);
+
+    let mut zs_lst = Box::new(Vec::new());
+    for (begin, end) in zs_ranges {
+        let mut zs = ZS::new(Some(&bi_lst[*begin..=*end]), true);
+        zs.set_bi_in(bi_lst[begin - 1].clone());
+        if let Some(bi_out) = bi_lst.get(end + 1) {
+            zs.set_bi_out(bi_out.clone());
+        }
+        zs.set_bi_lst(bi_lst[*begin..=*end].to_vec());
+        zs_lst.push(zs);
+    }
+    // add_zs插入到头部，所以倒序加入
+    for (zs_idx, (zs_begin, _)) in zs_ranges.iter().enumerate().rev() {
+        if let Some(seg_idx) = bi_lst[*zs_begin].borrow().seg_idx {
+            seg_list.lst[seg_idx].borrow_mut().add_zs(Handle::new(&zs_lst, zs_idx));
+        }
+    }
+
+    ChanFixture {
+        klus,
+        _bis: bis,
+        _zs_lst: zs_lst,
+        _segs: seg_box,
+        bi_lst,
+        seg_list,
+    }
+}
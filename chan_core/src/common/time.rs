@@ -1,5 +1,7 @@
-use chrono::{DateTime, NaiveDateTime, Timelike, Utc};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::fmt;
 
 #[derive(Debug, Clone)]
 pub struct CTime {
@@ -87,10 +89,6 @@ impl PartialOrd for CTime {
     }
 }
 
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
-use serde::{Deserialize, Serialize};
-use std::fmt;
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Time {
     pub timestamp: i64,
@@ -129,6 +127,10 @@ impl Time {
     pub fn to_date_str(&self) -> String {
         self.datetime.format("%Y%m%d").to_string()
     }
+
+    pub fn date(&self) -> NaiveDate {
+        self.datetime.date()
+    }
 }
 
 impl fmt::Display for Time {
@@ -3,20 +3,43 @@ use crate::bi::bi_config::BiConfig;
 use crate::buy_sell_point::bs_point_config::BSPointConfig;
 use crate::common::{
     chan_exception::{ChanException, ErrCode},
-    enums::TrendType,
+    cenum::TrendType,
     utils::parse_inf,
 };
 use crate::math::{
+    atr::ATR,
     boll::BollModel,
-    demark::DemarkEngine,
-    kdj::KDJ,
+    demark::{DemarkConfig, DemarkEngine},
+    kdj::KDJModel,
+    ma_ribbon::{MaRibbon, MaType},
     macd::MACD,
+    obv::OBV,
     rsi::RSI,
     trend_model::TrendModel,
+    vwap::VWAP,
 };
 use crate::seg::seg_config::SegConfig;
+use crate::trade::exit_rule::ExitRuleConfig;
+use crate::traits::metric_trait::MetricModel;
 use crate::zs::zs_config::ZSConfig;
 
+/// 买卖点参数，默认值见`set_bsp_config`
+const BSP_PARA_KEYS: [&str; 13] = [
+    "divergence_rate",
+    "min_zs_cnt",
+    "bsp1_only_multibi_zs",
+    "max_bs2_rate",
+    "macd_algo",
+    "bs1_peak",
+    "bs_type",
+    "bsp2_follow_1",
+    "bsp3_follow_1",
+    "bsp3_peak",
+    "bsp2s_follow_2",
+    "max_bsp2s_lv",
+    "strict_bsp3",
+];
+
 /// Chan analysis configuration
 #[derive(Debug, Clone)]
 pub struct ChanConfig {
@@ -33,14 +56,26 @@ pub struct ChanConfig {
     pub print_err_time: bool,
     pub mean_metrics: Vec<i32>,
     pub trend_metrics: Vec<i32>,
-    pub macd_config: HashMap<String, i32>,
+    pub macd_config: HashMap<String, u32>,
     pub cal_demark: bool,
     pub cal_rsi: bool,
     pub cal_kdj: bool,
-    pub rsi_cycle: i32,
-    pub kdj_cycle: i32,
-    pub demark_config: HashMap<String, bool>,
-    pub boll_n: i32,
+    pub rsi_cycle: usize,
+    pub kdj_cycle: usize,
+    /// K、D的平滑周期
+    pub kdj_k_period: usize,
+    pub kdj_d_period: usize,
+    pub demark_config: DemarkConfig,
+    pub boll_n: usize,
+    pub boll_k: f64,
+    pub cal_atr: bool,
+    pub atr_cycle: usize,
+    pub ema_metrics: Vec<usize>,
+    pub sma_metrics: Vec<usize>,
+    pub cal_obv: bool,
+    pub cal_vwap: bool,
+    /// 回测和模拟盘使用的出场规则
+    pub exit_rules: Vec<ExitRuleConfig>,
     pub bs_point_conf: BSPointConfig,
     pub seg_bs_point_conf: BSPointConfig,
 }
@@ -59,31 +94,22 @@ impl ChanConfig {
         );
 
         let seg_conf = SegConfig::new(
-            conf.get("seg_algo").unwrap_or("chan".to_string()),
-            conf.get("left_seg_method").unwrap_or("peak".to_string()),
-        );
+            Some(conf.get("seg_algo").unwrap_or("chan".to_string())),
+            Some(&conf.get("left_seg_method").unwrap_or("peak".to_string())),
+        )?;
 
         let zs_conf = ZSConfig::new(
-            conf.get("zs_combine").unwrap_or(true),
-            conf.get("zs_combine_mode").unwrap_or("zs".to_string()),
-            conf.get("one_bi_zs").unwrap_or(false),
-            conf.get("zs_algo").unwrap_or("normal".to_string()),
-        );
+            Some(conf.get("zs_combine").unwrap_or(true)),
+            Some(&conf.get("zs_combine_mode").unwrap_or("zs".to_string())),
+            Some(conf.get("one_bi_zs").unwrap_or(false)),
+            Some(&conf.get("zs_algo").unwrap_or("normal".to_string())),
+        )?;
 
         let mut macd_config = HashMap::new();
         macd_config.insert("fast".to_string(), 12);
         macd_config.insert("slow".to_string(), 26);
         macd_config.insert("signal".to_string(), 9);
 
-        let mut demark_config = HashMap::new();
-        demark_config.insert("demark_len".to_string(), 9);
-        demark_config.insert("setup_bias".to_string(), 4);
-        demark_config.insert("countdown_bias".to_string(), 2);
-        demark_config.insert("max_countdown".to_string(), 13);
-        demark_config.insert("tiaokong_st".to_string(), true);
-        demark_config.insert("setup_cmp2close".to_string(), true);
-        demark_config.insert("countdown_cmp2close".to_string(), true);
-
         let mut config = Self {
             bi_conf,
             seg_conf,
@@ -104,19 +130,30 @@ impl ChanConfig {
             cal_kdj: conf.get("cal_kdj").unwrap_or(false),
             rsi_cycle: conf.get("rsi_cycle").unwrap_or(14),
             kdj_cycle: conf.get("kdj_cycle").unwrap_or(9),
-            demark_config: conf.get("demark").unwrap_or(demark_config),
+            kdj_k_period: conf.get("kdj_k_period").unwrap_or(3),
+            kdj_d_period: conf.get("kdj_d_period").unwrap_or(3),
+            demark_config: conf.get("demark").unwrap_or_default(),
             boll_n: conf.get("boll_n").unwrap_or(20),
+            boll_k: conf.get("boll_k").unwrap_or(2.0),
+            cal_atr: conf.get("cal_atr").unwrap_or(false),
+            atr_cycle: conf.get("atr_cycle").unwrap_or(14),
+            ema_metrics: conf.get("ema_metrics").unwrap_or_else(Vec::new),
+            sma_metrics: conf.get("sma_metrics").unwrap_or_else(Vec::new),
+            cal_obv: conf.get("cal_obv").unwrap_or(false),
+            cal_vwap: conf.get("cal_vwap").unwrap_or(false),
+            exit_rules: conf.get("exit_rules").unwrap_or_else(Vec::new),
             bs_point_conf: BSPointConfig::default(),
             seg_bs_point_conf: BSPointConfig::default(),
         };
 
+        config.check_metric_para()?;
         config.set_bsp_config(&mut conf)?;
         conf.check()?;
 
         Ok(config)
     }
 
-    pub fn get_metric_model(&self) -> Vec<Box<dyn MetricModel>> {
+    pub fn get_metric_model(&self) -> Result<Vec<Box<dyn MetricModel>>, ChanException> {
         let mut res: Vec<Box<dyn MetricModel>> = Vec::new();
         
         // Add MACD
@@ -128,29 +165,21 @@ impl ChanConfig {
 
         // Add mean trend models
         for mean_t in &self.mean_metrics {
-            res.push(Box::new(TrendModel::new(TrendType::Mean, *mean_t)));
+            res.push(Box::new(TrendModel::new(TrendType::Mean, *mean_t)?));
         }
 
         // Add max/min trend models
         for trend_t in &self.trend_metrics {
-            res.push(Box::new(TrendModel::new(TrendType::Max, *trend_t)));
-            res.push(Box::new(TrendModel::new(TrendType::Min, *trend_t)));
+            res.push(Box::new(TrendModel::new(TrendType::Max, *trend_t)?));
+            res.push(Box::new(TrendModel::new(TrendType::Min, *trend_t)?));
         }
 
         // Add BOLL model
-        res.push(Box::new(BollModel::new(self.boll_n)));
+        res.push(Box::new(BollModel::new(self.boll_n, self.boll_k)?));
 
         // Add Demark if enabled
         if self.cal_demark {
-            res.push(Box::new(DemarkEngine::new(
-                self.demark_config["demark_len"],
-                self.demark_config["setup_bias"],
-                self.demark_config["countdown_bias"],
-                self.demark_config["max_countdown"],
-                self.demark_config["tiaokong_st"],
-                self.demark_config["setup_cmp2close"],
-                self.demark_config["countdown_cmp2close"],
-            )));
+            res.push(Box::new(DemarkEngine::new(self.demark_config.clone())));
         }
 
         // Add RSI if enabled
@@ -160,15 +189,82 @@ impl ChanConfig {
 
         // Add KDJ if enabled
         if self.cal_kdj {
-            res.push(Box::new(KDJ::new(self.kdj_cycle)));
+            res.push(Box::new(KDJModel::new(self.kdj_cycle, self.kdj_k_period, self.kdj_d_period)));
+        }
+
+        if self.cal_atr {
+            res.push(Box::new(ATR::new(self.atr_cycle)));
+        }
+
+        if !self.ema_metrics.is_empty() {
+            res.push(Box::new(MaRibbon::new(MaType::Ema, &self.ema_metrics)));
         }
 
-        res
+        if !self.sma_metrics.is_empty() {
+            res.push(Box::new(MaRibbon::new(MaType::Sma, &self.sma_metrics)));
+        }
+
+        if self.cal_obv {
+            res.push(Box::new(OBV::new()));
+        }
+
+        if self.cal_vwap {
+            res.push(Box::new(VWAP::new()));
+        }
+
+        Ok(res)
+    }
+
+    /// 是否只影响买卖点计算的参数（含`-buy`、`-seg`等后缀的写法）
+    ///
+    /// 这类参数不同的配置，K线合并、笔、线段和中枢的结果完全相同
+    pub fn is_bsp_para(key: &str) -> bool {
+        BSP_PARA_KEYS.contains(&key)
+            || ["-buy", "-sell", "-segbuy", "-segsell", "-seg"]
+                .iter()
+                .any(|suffix| key.ends_with(suffix))
+    }
+
+    /// 指标周期必须为正数，布林带周期至少为2
+    fn check_metric_para(&self) -> Result<(), ChanException> {
+        let periods = [
+            ("atr_cycle", &[self.atr_cycle][..]),
+            ("ema_metrics", &self.ema_metrics[..]),
+            ("sma_metrics", &self.sma_metrics[..]),
+            ("rsi_cycle", &[self.rsi_cycle][..]),
+            ("kdj_cycle", &[self.kdj_cycle][..]),
+            ("kdj_k_period", &[self.kdj_k_period][..]),
+            ("kdj_d_period", &[self.kdj_d_period][..]),
+        ];
+        for (name, lst) in periods {
+            if lst.contains(&0) {
+                return Err(ChanException::new(
+                    format!("{} must be positive, got {:?}", name, lst),
+                    ErrCode::ParaError,
+                ));
+            }
+        }
+        for (name, lst) in [("mean_metrics", &self.mean_metrics), ("trend_metrics", &self.trend_metrics)] {
+            if lst.iter().any(|&period| period <= 0) {
+                return Err(ChanException::new(
+                    format!("{} must be positive, got {:?}", name, lst),
+                    ErrCode::ParaError,
+                ));
+            }
+        }
+        if self.boll_n < 2 {
+            return Err(ChanException::new(
+                format!("boll_n must be greater than 1, got {}", self.boll_n),
+                ErrCode::ParaError,
+            ));
+        }
+        Ok(())
     }
 
     fn set_bsp_config(&mut self, conf: &mut ConfigWithCheck) -> Result<(), ChanException> {
         let mut para_dict = HashMap::new();
-        para_dict.insert("divergence_rate", serde_json::Value::from(f64::INFINITY));
+        // json中没有inf，写成字符串由BSPointConfig解析
+        para_dict.insert("divergence_rate", serde_json::Value::from("inf"));
         para_dict.insert("min_zs_cnt", serde_json::Value::from(1));
         para_dict.insert("bsp1_only_multibi_zs", serde_json::Value::from(true));
         para_dict.insert("max_bs2_rate", serde_json::Value::from(0.9999));
@@ -238,4 +334,93 @@ impl ChanConfig {
 
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_config(conf: serde_json::Value) -> Result<ChanConfig, ChanException> {
+        ChanConfig::new(Some(serde_json::from_value(conf).unwrap()))
+    }
+
+    #[test]
+    fn test_metric_period_must_be_positive() {
+        for conf in [
+            serde_json::json!({"cal_atr": true, "atr_cycle": 0}),
+            serde_json::json!({"ema_metrics": [5, 0]}),
+            serde_json::json!({"sma_metrics": [0]}),
+        ] {
+            assert_eq!(new_config(conf).unwrap_err().errcode, ErrCode::ParaError);
+        }
+    }
+
+    fn assert_para_error(conf: serde_json::Value) {
+        let err = new_config(conf.clone()).unwrap_err();
+        assert_eq!(err.errcode, ErrCode::ParaError, "{}", conf);
+    }
+
+    #[test]
+    fn test_boll_n_must_be_greater_than_1() {
+        assert_para_error(serde_json::json!({"boll_n": 0}));
+        assert_para_error(serde_json::json!({"boll_n": 1}));
+        assert_eq!(new_config(serde_json::json!({"boll_n": 2})).unwrap().boll_n, 2);
+    }
+
+    #[test]
+    fn test_rsi_cycle_must_be_positive() {
+        assert_para_error(serde_json::json!({"cal_rsi": true, "rsi_cycle": 0}));
+    }
+
+    #[test]
+    fn test_kdj_cycle_must_be_positive() {
+        assert_para_error(serde_json::json!({"cal_kdj": true, "kdj_cycle": 0}));
+    }
+
+    #[test]
+    fn test_kdj_k_period_must_be_positive() {
+        assert_para_error(serde_json::json!({"cal_kdj": true, "kdj_k_period": 0}));
+    }
+
+    #[test]
+    fn test_kdj_d_period_must_be_positive() {
+        assert_para_error(serde_json::json!({"cal_kdj": true, "kdj_d_period": 0}));
+    }
+
+    #[test]
+    fn test_mean_metrics_must_be_positive() {
+        assert_para_error(serde_json::json!({"mean_metrics": [5, 0]}));
+        assert_para_error(serde_json::json!({"mean_metrics": [-3]}));
+    }
+
+    #[test]
+    fn test_trend_metrics_must_be_positive() {
+        assert_para_error(serde_json::json!({"trend_metrics": [0]}));
+        assert_para_error(serde_json::json!({"trend_metrics": [10, -1]}));
+    }
+
+    #[test]
+    fn test_metric_models_from_config() {
+        let config = new_config(serde_json::json!({
+            "cal_atr": true,
+            "atr_cycle": 10,
+            "ema_metrics": [5, 10],
+            "cal_obv": true,
+            "cal_vwap": true,
+        }))
+        .unwrap();
+        let names: Vec<String> = config.get_metric_model().unwrap().iter().map(|model| model.name()).collect();
+        for name in ["atr_10", "ema_5_10", "obv", "vwap"] {
+            assert!(names.contains(&name.to_string()), "{} not in {:?}", name, names);
+        }
+    }
+
+    #[test]
+    fn test_short_boll_period_is_para_error() {
+        let mut config = ChanConfig::new(None).unwrap();
+        for boll_n in [0, 1] {
+            config.boll_n = boll_n;
+            assert_eq!(config.get_metric_model().err().unwrap().errcode, ErrCode::ParaError);
+        }
+    }
+}
@@ -3,7 +3,7 @@ use crate::common::{
     enums::KlineDir,
     chan_exception::{ChanException, ErrCode},
     handle::Handle,
-    data_field::DataField,
+    cenum::DataField,
 };
 use super::{kline::KLine, kline_unit::KLineUnit};
 use crate::impl_handle;
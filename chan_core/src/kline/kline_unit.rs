@@ -1,7 +1,6 @@
 use std::collections::HashMap;
 use crate::common::{
-    data_field::DataField,
-    trend_type::TrendType,
+    cenum::{DataField, TrendType},
     time::Time,
     chan_exception::{ChanException, ErrCode},
     handle::{Handle, Indexable, AsHandle},
@@ -11,11 +10,13 @@ use crate::math::{
     boll::{BollMetric, BollModel},
     demark::{DemarkEngine, DemarkIndex},
     kdj::KDJ,
+    ma_ribbon::MaType,
     macd::{MACD, MACDItem},
     rsi::RSI,
     trend_model::TrendModel,
 };
 use crate::kline::trade_info::TradeInfo;
+use crate::traits::metric_trait::MetricModel;
 
 #[derive(Debug)]
 pub struct KLineUnit {
@@ -37,6 +38,12 @@ pub struct KLineUnit {
     pub boll: Option<BollMetric>,
     pub rsi: Option<f64>,
     pub kdj: Option<KDJ>,
+    pub atr: Option<f64>,
+    pub ma: HashMap<MaType, HashMap<usize, f64>>,
+    pub obv: Option<f64>,
+    pub vwap: Option<f64>,
+    /// 用户注册的指标，key为指标名称
+    pub custom_metric: HashMap<String, f64>,
 }
 
 // Automatically implement AsHandle and Indexable traits
@@ -68,6 +75,11 @@ impl KLineUnit {
             boll: None,
             rsi: None,
             kdj: None,
+            atr: None,
+            ma: HashMap::new(),
+            obv: None,
+            vwap: None,
+            custom_metric: HashMap::new(),
         };
 
         unit.check(autofix)?;
@@ -135,12 +147,12 @@ impl KLineUnit {
         }
     }
 
-    // ... other methods would follow similar patterns
-}
+    /// 滚动均值/最大值/最小值，未配置该周期时为None
+    pub fn get_trend(&self, trend_type: TrendType, period: i32) -> Option<f64> {
+        self.trend.get(&trend_type)?.get(&period).copied()
+    }
 
-// Define a trait for metric models to implement
-pub trait MetricModel {
-    fn update_kline_unit(&mut self, klu: &mut KLineUnit);
+    // ... other methods would follow similar patterns
 }
 
 // Implement Clone for KLineUnit
@@ -164,6 +176,11 @@ impl Clone for KLineUnit {
             boll: self.boll.clone(),
             rsi: self.rsi,
             kdj: self.kdj.clone(),
+            atr: self.atr,
+            ma: self.ma.clone(),
+            obv: self.obv,
+            vwap: self.vwap,
+            custom_metric: self.custom_metric.clone(),
         }
     }
 }
@@ -1,11 +1,13 @@
 pub mod analyzer;
 pub mod bi;
 pub mod buy_sell_point;
+pub mod chan_model;
 pub mod common;
 pub mod config;
 pub mod kline;
 pub mod math;
 pub mod seg;
+pub mod trade;
 pub mod traits;
 pub mod zs;
 
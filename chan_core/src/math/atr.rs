@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::metric_trait::Indicator;
+
+/// 平均真实波幅，Wilder平滑；不足一个周期时取已有TR的均值
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ATR {
+    period: usize,
+    pre_close: Option<f64>,
+    cnt: usize,
+    atr: f64,
+}
+
+impl ATR {
+    /// period须大于0，由`ChanConfig::new`校验
+    pub fn new(period: usize) -> Self {
+        Self {
+            period,
+            pre_close: None,
+            cnt: 0,
+            atr: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, high: f64, low: f64, close: f64) -> f64 {
+        let tr = match self.pre_close.replace(close) {
+            Some(pre_close) => (high - low).max((high - pre_close).abs()).max((low - pre_close).abs()),
+            None => high - low,
+        };
+        self.cnt += 1;
+        if self.cnt <= self.period {
+            self.atr += (tr - self.atr) / self.cnt as f64;
+        } else {
+            let period = self.period as f64;
+            self.atr = (self.atr * (period - 1.0) + tr) / period;
+        }
+        self.atr
+    }
+}
+
+impl Indicator for ATR {
+    type Output = f64;
+
+    fn name(&self) -> String {
+        format!("atr_{}", self.period)
+    }
+
+    fn update(&mut self, klu: &KLineUnit) -> f64 {
+        self.add(klu.high, klu.low, klu.close)
+    }
+
+    fn store(&self, klu: &mut KLineUnit, output: f64) {
+        klu.atr = Some(output);
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.period);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atr_warmup_and_wilder() {
+        let bars = [(11.0, 9.0, 10.0), (12.0, 10.5, 11.5), (11.0, 10.0, 10.2), (13.0, 12.0, 12.5)];
+        let mut model = ATR::new(2);
+        let res: Vec<f64> = bars.iter().map(|&(h, l, c)| model.add(h, l, c)).collect();
+        // TR: 2.0, 2.0, 1.5(跳空取前收), 2.8
+        assert!((res[0] - 2.0).abs() < 1e-12);
+        assert!((res[1] - 2.0).abs() < 1e-12);
+        assert!((res[2] - 1.75).abs() < 1e-12);
+        assert!((res[3] - 2.275).abs() < 1e-12);
+    }
+}
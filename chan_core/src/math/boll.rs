@@ -1,3 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+use crate::common::chan_exception::{ChanException, ErrCode};
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::metric_trait::Indicator;
+
 #[derive(Debug, Clone)]
 pub struct BollMetric {
     pub up: f64,
@@ -5,40 +11,225 @@ pub struct BollMetric {
     pub down: f64,
 }
 
-#[derive(Debug)]
+/// 避免下轨为0时后续计算除零
+fn truncate(x: f64) -> f64 {
+    if x != 0.0 {
+        x
+    } else {
+        1e-7
+    }
+}
+
+/// 滑动窗口的布林带
+///
+/// 窗口数据存放在环形缓冲区中，用Welford方法增量维护均值和偏差平方和：窗口未满时逐个加入，
+/// 满了之后同时移出最旧的价格、加入新价格，每根K线O(1)。
+/// 为了避免价格本身的大数相减，Welford维护的是相对基准shift的偏差；
+/// 每滚动一整个窗口、或累计舍入误差可能影响标准差时，按定义重算一次并更新基准
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BollModel {
     period: usize,
     k: f64,
+    /// 环形缓冲区，未满时按顺序追加
     prices: Vec<f64>,
+    /// 最旧数据在prices中的位置
+    head: usize,
+    /// 偏差的基准
+    shift: f64,
+    /// 窗口内(x - shift)的均值
+    mean: f64,
+    /// 窗口内偏差平方和 sum((x - mid)^2)
+    m2: f64,
+    /// 上次重算以来m2累计舍入误差的上界
+    drift: f64,
 }
 
+/// 标准差允许的误差，超过时重算
+const STD_TOLERANCE: f64 = 5e-14;
+
 impl BollModel {
-    pub fn new(period: usize, k: f64) -> Self {
-        Self {
+    /// period：均线周期，至少为2；k：上下轨的标准差倍数
+    pub fn new(period: usize, k: f64) -> Result<Self, ChanException> {
+        if period < 2 {
+            return Err(ChanException::new(
+                format!("boll period must be greater than 1, got {}", period),
+                ErrCode::ParaError,
+            ));
+        }
+        Ok(Self {
             period,
             k,
             prices: Vec::with_capacity(period),
-        }
+            head: 0,
+            shift: 0.0,
+            mean: 0.0,
+            m2: 0.0,
+            drift: 0.0,
+        })
     }
 
     pub fn add(&mut self, price: f64) -> BollMetric {
-        self.prices.push(price);
-        if self.prices.len() > self.period {
-            self.prices.remove(0);
+        if self.prices.is_empty() {
+            self.shift = price;
+        }
+        let d = price - self.shift;
+        if self.prices.len() < self.period {
+            // Welford加入
+            self.prices.push(price);
+            let delta = d - self.mean;
+            self.mean += delta / self.prices.len() as f64;
+            let inc = delta * (d - self.mean);
+            self.m2 += inc;
+            self.drift += f64::EPSILON * (inc.abs() + self.m2);
+        } else {
+            // Welford同时移出最旧的价格、加入新价格
+            let old = std::mem::replace(&mut self.prices[self.head], price);
+            self.head = (self.head + 1) % self.period;
+            let old_d = old - self.shift;
+            let old_mean = self.mean;
+            let delta = d - old_d;
+            self.mean += delta / self.period as f64;
+            let inc = delta * (d - self.mean + old_d - old_mean);
+            self.m2 += inc;
+            self.drift += f64::EPSILON * (inc.abs() + self.m2.abs());
+            if self.head == 0 {
+                self.resync();
+            }
         }
 
-        let mid = self.prices.iter().sum::<f64>() / self.prices.len() as f64;
-        
-        let variance = self.prices.iter()
-            .map(|&x| (x - mid).powi(2))
-            .sum::<f64>() / self.prices.len() as f64;
-        
+        let n = self.prices.len() as f64;
+        let mut variance = (self.m2 / n).max(0.0);
+        // 标准差在方差接近0时对误差最敏感：std误差约为 方差误差 / (2 * std)
+        if self.drift > STD_TOLERANCE * 2.0 * n * variance.sqrt() {
+            self.resync();
+            variance = (self.m2 / n).max(0.0);
+        }
+        let mid = self.shift + self.mean;
         let std_dev = variance.sqrt();
-        
+
         BollMetric {
             up: mid + self.k * std_dev,
             mid,
-            down: mid - self.k * std_dev,
+            down: truncate(mid - self.k * std_dev),
         }
     }
-} 
\ No newline at end of file
+
+    /// 按定义重新计算，并以当前均值作为新的基准
+    fn resync(&mut self) {
+        let n = self.prices.len() as f64;
+        let mean = self.prices.iter().sum::<f64>() / n;
+        self.shift = mean;
+        // shift是舍入后的均值，偏差的均值并不严格为0
+        self.mean = self.prices.iter().map(|&x| x - mean).sum::<f64>() / n;
+        self.m2 = self.prices.iter().map(|&x| (x - mean).powi(2)).sum::<f64>();
+        self.drift = 0.0;
+    }
+}
+
+impl Indicator for BollModel {
+    type Output = BollMetric;
+
+    fn name(&self) -> String {
+        format!("boll_{}_{}", self.period, self.k)
+    }
+
+    fn update(&mut self, klu: &KLineUnit) -> BollMetric {
+        self.add(klu.close)
+    }
+
+    fn store(&self, klu: &mut KLineUnit, output: BollMetric) {
+        klu.boll = Some(output);
+    }
+
+    fn reset(&mut self) {
+        self.prices.clear();
+        self.head = 0;
+        self.shift = 0.0;
+        self.mean = 0.0;
+        self.m2 = 0.0;
+        self.drift = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOSES: [f64; 36] = [
+        20.0, 20.49, 20.27, 19.16, 17.67, 16.58, 16.37, 16.87, 17.35, 17.11, 15.99, 14.5, 13.42, 13.24, 13.74, 14.21,
+        13.96, 12.83, 12.0, 13.46, 13.52, 12.81, 13.12, 14.72, 16.0, 15.82, 15.17, 15.74, 17.42, 18.49, 18.11, 17.57,
+        18.4, 20.11, 20.93, 20.39,
+    ];
+
+    #[test]
+    fn test_boll_reference_values() {
+        let mut model = BollModel::new(5, 2.0).unwrap();
+        let metrics: Vec<BollMetric> = CLOSES.iter().map(|&close| model.add(close)).collect();
+        let expected = [
+            (20.2360517639, 18.536, 16.8359482361),
+            (21.5776045113, 19.024, 16.4703954887),
+            (22.0321755426, 19.48, 16.9278244574),
+        ];
+        for (metric, (up, mid, down)) in metrics[metrics.len() - 3..].iter().zip(expected) {
+            assert!((metric.up - up).abs() < 1e-9);
+            assert!((metric.mid - mid).abs() < 1e-9);
+            assert!((metric.down - down).abs() < 1e-9);
+        }
+    }
+
+    /// 逐根对整个窗口求均值和方差的朴素实现
+    fn naive(period: usize, k: f64, arr: &[f64]) -> Vec<BollMetric> {
+        (0..arr.len())
+            .map(|i| {
+                let window = &arr[(i + 1).saturating_sub(period)..=i];
+                let mid = window.iter().sum::<f64>() / window.len() as f64;
+                let std_dev = (window.iter().map(|&x| (x - mid).powi(2)).sum::<f64>() / window.len() as f64).sqrt();
+                BollMetric {
+                    up: mid + k * std_dev,
+                    mid,
+                    down: truncate(mid - k * std_dev),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_boll_matches_naive_on_long_series() {
+        // 确定性的随机游走
+        let mut seed: u64 = 42;
+        let mut price = 100.0;
+        let arr: Vec<f64> = (0..200_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                price += ((seed >> 11) as f64 / (1u64 << 53) as f64 - 0.5) * 0.2;
+                price
+            })
+            .collect();
+        for period in [2, 20, 99] {
+            let mut model = BollModel::new(period, 2.0).unwrap();
+            for (metric, expected) in arr.iter().map(|&v| model.add(v)).zip(naive(period, 2.0, &arr)) {
+                assert!((metric.mid - expected.mid).abs() < 1e-12);
+                assert!((metric.up - expected.up).abs() < 1e-12);
+                assert!((metric.down - expected.down).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_boll_rejects_short_period() {
+        for period in [0, 1] {
+            let err = BollModel::new(period, 2.0).unwrap_err();
+            assert_eq!(err.errcode, ErrCode::ParaError);
+        }
+    }
+
+    #[test]
+    fn test_boll_k_scales_band_width() {
+        let mut narrow = BollModel::new(5, 1.0).unwrap();
+        let mut wide = BollModel::new(5, 3.0).unwrap();
+        for &close in &CLOSES[..10] {
+            let (n, w) = (narrow.add(close), wide.add(close));
+            assert!(((w.up - w.mid) - 3.0 * (n.up - n.mid)).abs() < 1e-9);
+        }
+    }
+}
@@ -1,97 +1,452 @@
-use serde::{Serialize, Deserialize};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DemarkIndex {
-    pub setup_trend: i32,      // -1: down, 0: none, 1: up
-    pub setup_idx: i32,        // setup count
-    pub countdown_trend: i32,   // -1: down, 0: none, 1: up
-    pub countdown_idx: i32,     // countdown count
+use crate::common::enums::BiDir;
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::metric_trait::Indicator;
+
+/// Demark参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DemarkConfig {
+    /// setup完成需要的计数
+    pub demark_len: usize,
+    /// setup与前第几根比较
+    pub setup_bias: usize,
+    /// countdown与前第几根比较
+    pub countdown_bias: usize,
+    /// countdown的最大计数
+    pub max_countdown: usize,
+    /// 第一根跳空时是否跟前一根的close比
+    pub tiaokong_st: bool,
+    /// setup是否与收盘价比较，否则与最高/最低价比较
+    pub setup_cmp2close: bool,
+    /// countdown是否与收盘价比较，否则与最高/最低价比较
+    pub countdown_cmp2close: bool,
 }
 
-impl Default for DemarkIndex {
+impl Default for DemarkConfig {
     fn default() -> Self {
         Self {
-            setup_trend: 0,
-            setup_idx: 0,
-            countdown_trend: 0,
-            countdown_idx: 0,
+            demark_len: 9,
+            setup_bias: 4,
+            countdown_bias: 2,
+            max_countdown: 13,
+            tiaokong_st: true,
+            setup_cmp2close: true,
+            countdown_cmp2close: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DemarkType {
+    Setup,
+    Countdown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DemarkItem {
+    pub demark_type: DemarkType,
+    pub dir: BiDir,
+    pub idx: usize,
+}
+
+/// 某根K线上所有序列的setup/countdown计数
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DemarkIndex {
+    pub data: Vec<DemarkItem>,
+}
+
+impl DemarkIndex {
+    pub fn add(&mut self, dir: BiDir, demark_type: DemarkType, idx: usize) {
+        self.data.push(DemarkItem { demark_type, dir, idx });
+    }
+
+    pub fn get_setup(&self) -> impl Iterator<Item = &DemarkItem> {
+        self.data.iter().filter(|item| item.demark_type == DemarkType::Setup)
+    }
+
+    pub fn get_countdown(&self) -> impl Iterator<Item = &DemarkItem> {
+        self.data.iter().filter(|item| item.demark_type == DemarkType::Countdown)
+    }
+
+    pub fn update(&mut self, demark_index: &DemarkIndex) {
+        self.data.extend(demark_index.data.iter().cloned());
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct DemarkKl {
+    close: f64,
+    high: f64,
+    low: f64,
+}
+
+impl DemarkKl {
+    fn v(&self, is_close: bool, dir: BiDir) -> f64 {
+        if is_close {
+            self.close
+        } else if dir == BiDir::Up {
+            self.high
+        } else {
+            self.low
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DemarkCountdown {
+    dir: BiDir,
+    kl_list: Vec<DemarkKl>,
+    idx: usize,
+    tdst_peak: f64,
+    finish: bool,
+}
+
+impl DemarkCountdown {
+    fn update(&mut self, kl: DemarkKl, config: &DemarkConfig) -> bool {
+        if self.finish {
+            return false;
+        }
+        self.kl_list.push(kl);
+        if self.kl_list.len() <= config.countdown_bias {
+            return false;
+        }
+        if self.idx == config.max_countdown {
+            self.finish = true;
+            return false;
+        }
+        // 突破TDST则countdown失效
+        if (self.dir == BiDir::Down && kl.high > self.tdst_peak) || (self.dir == BiDir::Up && kl.low < self.tdst_peak) {
+            self.finish = true;
+            return false;
+        }
+        let cmp_val = self.kl_list[self.kl_list.len() - 1 - config.countdown_bias].v(config.countdown_cmp2close, self.dir);
+        if (self.dir == BiDir::Down && kl.close < cmp_val) || (self.dir == BiDir::Up && kl.close > cmp_val) {
+            self.idx += 1;
+            return true;
+        }
+        false
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DemarkSetup {
+    dir: BiDir,
+    kl_list: Vec<DemarkKl>,
+    /// 跳空时用
+    pre_kl: DemarkKl,
+    countdown: Option<DemarkCountdown>,
+    setup_finished: bool,
+    idx: usize,
+    tdst_peak: Option<f64>,
+    /// 缓存用
+    last_demark_index: DemarkIndex,
+}
+
+impl DemarkSetup {
+    fn new(dir: BiDir, kl_list: &[DemarkKl], pre_kl: DemarkKl) -> Self {
+        Self {
+            dir,
+            kl_list: kl_list.to_vec(),
+            pre_kl,
+            countdown: None,
+            setup_finished: false,
+            idx: 0,
+            tdst_peak: None,
+            last_demark_index: DemarkIndex::default(),
+        }
+    }
+
+    fn update(&mut self, kl: DemarkKl, config: &DemarkConfig) -> &DemarkIndex {
+        self.last_demark_index = DemarkIndex::default();
+        if !self.setup_finished {
+            self.kl_list.push(kl);
+            let cmp_val = self.kl_list[self.kl_list.len() - 1 - config.setup_bias].v(config.setup_cmp2close, self.dir);
+            if (self.dir == BiDir::Down && kl.close < cmp_val) || (self.dir == BiDir::Up && kl.close > cmp_val) {
+                self.idx += 1;
+                self.last_demark_index.add(self.dir, DemarkType::Setup, self.idx);
+            } else {
+                self.setup_finished = true;
+            }
         }
+        if self.idx == config.demark_len && !self.setup_finished && self.countdown.is_none() {
+            let tdst_peak = self.cal_tdst_peak(config);
+            self.countdown = Some(DemarkCountdown {
+                dir: self.dir,
+                kl_list: self.kl_list[..self.kl_list.len() - 1].to_vec(),
+                idx: 0,
+                tdst_peak,
+                finish: false,
+            });
+        }
+        if let Some(countdown) = self.countdown.as_mut() {
+            if countdown.update(kl, config) {
+                self.last_demark_index.add(self.dir, DemarkType::Countdown, countdown.idx);
+            }
+        }
+        &self.last_demark_index
+    }
+
+    fn cal_tdst_peak(&mut self, config: &DemarkConfig) -> f64 {
+        assert_eq!(self.kl_list.len(), config.setup_bias + config.demark_len);
+        let arr = &self.kl_list[config.setup_bias..];
+        let res = if self.dir == BiDir::Down {
+            let res = arr.iter().map(|kl| kl.high).fold(f64::NEG_INFINITY, f64::max);
+            if config.tiaokong_st && arr[0].high < self.pre_kl.close {
+                res.max(self.pre_kl.close)
+            } else {
+                res
+            }
+        } else {
+            let res = arr.iter().map(|kl| kl.low).fold(f64::INFINITY, f64::min);
+            if config.tiaokong_st && arr[0].low > self.pre_kl.close {
+                res.min(self.pre_kl.close)
+            } else {
+                res
+            }
+        };
+        self.tdst_peak = Some(res);
+        res
+    }
+
+    fn is_invalid(&self) -> bool {
+        (self.setup_finished && self.countdown.is_none())
+            || self.countdown.as_ref().is_some_and(|countdown| countdown.finish)
     }
 }
 
-#[derive(Debug)]
+/// 九转序列：setup + countdown
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DemarkEngine {
-    last_close: Option<f64>,
-    setup_count: i32,
-    setup_trend: i32,
-    countdown_ref_idx: i32,
-    countdown_count: i32,
-    countdown_trend: i32,
+    config: DemarkConfig,
+    kl_lst: Vec<DemarkKl>,
+    series: Vec<DemarkSetup>,
 }
 
 impl DemarkEngine {
-    pub fn new() -> Self {
+    pub fn new(config: DemarkConfig) -> Self {
         Self {
-            last_close: None,
-            setup_count: 0,
-            setup_trend: 0,
-            countdown_ref_idx: 0,
-            countdown_count: 0,
-            countdown_trend: 0,
-        }
-    }
-
-    pub fn update(&mut self, close: f64) -> DemarkIndex {
-        let mut index = DemarkIndex::default();
-
-        if let Some(last_close) = self.last_close {
-            // Setup phase
-            if close < last_close {
-                if self.setup_trend >= 0 {
-                    self.setup_count = 1;
-                    self.setup_trend = -1;
-                } else {
-                    self.setup_count += 1;
-                }
-            } else if close > last_close {
-                if self.setup_trend <= 0 {
-                    self.setup_count = 1;
-                    self.setup_trend = 1;
-                } else {
-                    self.setup_count += 1;
+            config,
+            kl_lst: Vec::new(),
+            series: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, close: f64, high: f64, low: f64) -> DemarkIndex {
+        self.kl_lst.push(DemarkKl { close, high, low });
+        let setup_bias = self.config.setup_bias;
+        let len = self.kl_lst.len();
+        if len <= setup_bias + 1 {
+            return DemarkIndex::default();
+        }
+
+        let cmp_close = self.kl_lst[len - 1 - setup_bias].close;
+        let new_dir = if close < cmp_close {
+            Some(BiDir::Down)
+        } else if close > cmp_close {
+            Some(BiDir::Up)
+        } else {
+            None
+        };
+        if let Some(dir) = new_dir {
+            if !self.series.iter().any(|series| series.dir == dir && !series.setup_finished) {
+                self.series.push(DemarkSetup::new(
+                    dir,
+                    &self.kl_lst[len - setup_bias - 1..len - 1],
+                    self.kl_lst[len - setup_bias - 2],
+                ));
+            }
+            // 反向的setup被打断
+            for series in self.series.iter_mut() {
+                if series.dir != dir && series.countdown.is_none() && !series.setup_finished {
+                    series.setup_finished = true;
                 }
             }
+        }
 
-            // Reset if setup count reaches 9
-            if self.setup_count >= 9 {
-                self.setup_count = 0;
-                self.countdown_ref_idx = self.setup_trend;
-                self.countdown_trend = self.setup_trend;
-                self.countdown_count = 0;
-            }
+        self.clear();
+        self.clean_series_from_setup_finish();
 
-            // Countdown phase
-            if self.countdown_ref_idx != 0 {
-                if (self.countdown_trend > 0 && close <= last_close) ||
-                   (self.countdown_trend < 0 && close >= last_close) {
-                    self.countdown_count += 1;
-                }
+        let result = self.cal_result();
+        self.clear();
+        result
+    }
 
-                if self.countdown_count >= 13 {
-                    self.countdown_count = 0;
-                    self.countdown_ref_idx = 0;
-                    self.countdown_trend = 0;
-                }
+    fn cal_result(&self) -> DemarkIndex {
+        let mut demark_index = DemarkIndex::default();
+        for series in &self.series {
+            demark_index.update(&series.last_demark_index);
+        }
+        demark_index
+    }
+
+    fn clear(&mut self) {
+        self.series.retain(|series| !series.is_invalid());
+    }
+
+    /// 有序列完成setup时，只保留这一个序列
+    fn clean_series_from_setup_finish(&mut self) {
+        let kl = *self.kl_lst.last().expect("kl_lst should not be empty");
+        let mut finished_setup = None;
+        for (idx, series) in self.series.iter_mut().enumerate() {
+            let demark_idx = series.update(kl, &self.config);
+            if demark_idx.get_setup().any(|setup| setup.idx == self.config.demark_len) {
+                assert!(finished_setup.is_none());
+                finished_setup = Some(idx);
             }
         }
+        if let Some(finished_idx) = finished_setup {
+            let series = self.series.swap_remove(finished_idx);
+            self.series = vec![series];
+        }
+    }
+}
+
+impl Indicator for DemarkEngine {
+    type Output = DemarkIndex;
+
+    fn name(&self) -> String {
+        "demark".to_string()
+    }
+
+    fn update(&mut self, klu: &KLineUnit) -> DemarkIndex {
+        DemarkEngine::update(self, klu.close, klu.high, klu.low)
+    }
+
+    fn store(&self, klu: &mut KLineUnit, output: DemarkIndex) {
+        klu.demark = output;
+    }
+
+    fn reset(&mut self) {
+        self.kl_lst.clear();
+        self.series.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DemarkType::{Countdown, Setup};
+
+    const CLOSES: [f64; 36] = [
+        20.0, 20.49, 20.27, 19.16, 17.67, 16.58, 16.37, 16.87, 17.35, 17.11, 15.99, 14.5, 13.42, 13.24, 13.74, 14.21,
+        13.96, 12.83, 12.0, 13.46, 13.52, 12.81, 13.12, 14.72, 16.0, 15.82, 15.17, 15.74, 17.42, 18.49, 18.11, 17.57,
+        18.4, 20.11, 20.93, 20.39,
+    ];
+    const HIGHS: [f64; 36] = [
+        20.3, 20.99, 20.97, 19.46, 18.17, 17.28, 16.67, 17.37, 18.05, 17.41, 16.49, 15.2, 13.72, 13.74, 14.44, 14.51,
+        14.46, 13.53, 12.3, 13.96, 14.22, 13.11, 13.62, 15.42, 16.3, 16.32, 15.87, 16.04, 17.92, 19.19, 18.41, 18.07,
+        19.1, 20.41, 21.43, 21.09,
+    ];
+    const LOWS: [f64; 36] = [
+        19.65, 20.04, 19.72, 18.51, 17.32, 16.13, 15.82, 16.22, 17.0, 16.66, 15.44, 13.85, 13.07, 12.79, 13.19, 13.56,
+        13.61, 12.38, 11.45, 12.81, 13.17, 12.36, 12.57, 14.07, 15.65, 15.37, 14.62, 15.09, 17.07, 18.04, 17.56, 16.92,
+        18.05, 19.66, 20.38, 19.74,
+    ];
 
-        self.last_close = Some(close);
+    type BarResult = (usize, Vec<(DemarkType, BiDir, usize)>);
 
-        index.setup_trend = self.setup_trend;
-        index.setup_idx = self.setup_count;
-        index.countdown_trend = self.countdown_trend;
-        index.countdown_idx = self.countdown_count;
+    /// 返回有计数的K线序号及其计数，用于和chan.py的输出对比
+    fn run(config: DemarkConfig, bar_cnt: usize) -> Vec<BarResult> {
+        let mut engine = DemarkEngine::new(config);
+        (0..bar_cnt)
+            .map(|i| (i, engine.update(CLOSES[i], HIGHS[i], LOWS[i])))
+            .filter(|(_, index)| !index.data.is_empty())
+            .map(|(i, index)| (i, index.data.iter().map(|item| (item.demark_type, item.dir, item.idx)).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_demark_default_config() {
+        let expected = vec![
+            (5, vec![(Setup, BiDir::Down, 1)]),
+            (6, vec![(Setup, BiDir::Down, 2)]),
+            (7, vec![(Setup, BiDir::Down, 3)]),
+            (8, vec![(Setup, BiDir::Down, 4)]),
+            (9, vec![(Setup, BiDir::Up, 1)]),
+            (10, vec![(Setup, BiDir::Down, 1)]),
+            (11, vec![(Setup, BiDir::Down, 2)]),
+            (12, vec![(Setup, BiDir::Down, 3)]),
+            (13, vec![(Setup, BiDir::Down, 4)]),
+            (14, vec![(Setup, BiDir::Down, 5)]),
+            (15, vec![(Setup, BiDir::Down, 6)]),
+            (16, vec![(Setup, BiDir::Up, 1)]),
+            (17, vec![(Setup, BiDir::Down, 1)]),
+            (18, vec![(Setup, BiDir::Down, 2)]),
+            (19, vec![(Setup, BiDir::Down, 3)]),
+            (20, vec![(Setup, BiDir::Down, 4)]),
+            (21, vec![(Setup, BiDir::Down, 5)]),
+            (22, vec![(Setup, BiDir::Up, 1)]),
+            (23, vec![(Setup, BiDir::Up, 2)]),
+            (24, vec![(Setup, BiDir::Up, 3)]),
+            (25, vec![(Setup, BiDir::Up, 4)]),
+            (26, vec![(Setup, BiDir::Up, 5)]),
+            (27, vec![(Setup, BiDir::Up, 6)]),
+            (28, vec![(Setup, BiDir::Up, 7)]),
+            (29, vec![(Setup, BiDir::Up, 8)]),
+            (30, vec![(Setup, BiDir::Up, 9), (Countdown, BiDir::Up, 1)]),
+            (31, vec![(Setup, BiDir::Up, 10)]),
+            (32, vec![(Setup, BiDir::Up, 11), (Countdown, BiDir::Up, 2)]),
+            (33, vec![(Setup, BiDir::Up, 12), (Countdown, BiDir::Up, 3)]),
+            (34, vec![(Setup, BiDir::Up, 13), (Countdown, BiDir::Up, 4)]),
+            (35, vec![(Setup, BiDir::Up, 14), (Countdown, BiDir::Up, 5)]),
+        ];
+        assert_eq!(run(DemarkConfig::default(), CLOSES.len()), expected);
+    }
 
-        index
+    #[test]
+    fn test_demark_short_series_with_countdown_limit() {
+        let config = DemarkConfig {
+            demark_len: 4,
+            setup_bias: 1,
+            countdown_bias: 1,
+            max_countdown: 3,
+            ..DemarkConfig::default()
+        };
+        let expected = vec![
+            (2, vec![(Setup, BiDir::Down, 1)]),
+            (3, vec![(Setup, BiDir::Down, 2)]),
+            (4, vec![(Setup, BiDir::Down, 3)]),
+            (5, vec![(Setup, BiDir::Down, 4), (Countdown, BiDir::Down, 1)]),
+            (6, vec![(Setup, BiDir::Down, 5), (Countdown, BiDir::Down, 2)]),
+            (7, vec![(Setup, BiDir::Up, 1)]),
+            (8, vec![(Setup, BiDir::Up, 2)]),
+            (9, vec![(Countdown, BiDir::Down, 3), (Setup, BiDir::Down, 1)]),
+            (10, vec![(Setup, BiDir::Down, 2)]),
+            (11, vec![(Setup, BiDir::Down, 3)]),
+            (12, vec![(Setup, BiDir::Down, 4), (Countdown, BiDir::Down, 1)]),
+            (13, vec![(Setup, BiDir::Down, 5), (Countdown, BiDir::Down, 2)]),
+            (14, vec![(Setup, BiDir::Up, 1)]),
+            (15, vec![(Setup, BiDir::Up, 2)]),
+            (16, vec![(Countdown, BiDir::Down, 3), (Setup, BiDir::Down, 1)]),
+        ];
+        assert_eq!(run(config, 17), expected);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_demark_cmp_to_high_low() {
+        let config = DemarkConfig {
+            demark_len: 4,
+            setup_bias: 1,
+            countdown_bias: 1,
+            max_countdown: 3,
+            tiaokong_st: false,
+            setup_cmp2close: false,
+            countdown_cmp2close: false,
+        };
+        let expected = vec![
+            (3, vec![(Setup, BiDir::Down, 1)]),
+            (4, vec![(Setup, BiDir::Down, 2)]),
+            (5, vec![(Setup, BiDir::Down, 3)]),
+            (7, vec![(Setup, BiDir::Up, 1)]),
+            (10, vec![(Setup, BiDir::Down, 1)]),
+            (11, vec![(Setup, BiDir::Down, 2)]),
+            (12, vec![(Setup, BiDir::Down, 3)]),
+            (17, vec![(Setup, BiDir::Down, 1)]),
+            (18, vec![(Setup, BiDir::Down, 2)]),
+            (19, vec![(Setup, BiDir::Up, 1)]),
+        ];
+        assert_eq!(run(config, 20), expected);
+    }
+}
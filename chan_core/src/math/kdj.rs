@@ -1,3 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::metric_trait::Indicator;
+
 #[derive(Debug, Clone)]
 pub struct KDJ {
     pub k: f64,
@@ -5,19 +10,19 @@ pub struct KDJ {
     pub j: f64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KDJModel {
     rsv_period: usize,
     k_period: usize,
     d_period: usize,
     highs: Vec<f64>,
     lows: Vec<f64>,
-    closes: Vec<f64>,
     last_k: f64,
     last_d: f64,
 }
 
 impl KDJModel {
+    /// rsv_period：RSV的周期；k_period/d_period：K、D的平滑周期，常用9,3,3
     pub fn new(rsv_period: usize, k_period: usize, d_period: usize) -> Self {
         Self {
             rsv_period,
@@ -25,42 +30,113 @@ impl KDJModel {
             d_period,
             highs: Vec::with_capacity(rsv_period),
             lows: Vec::with_capacity(rsv_period),
-            closes: Vec::with_capacity(rsv_period),
             last_k: 50.0,
             last_d: 50.0,
         }
     }
 
-    pub fn add(&mut self, high: f64, low: f64, close: f64) {
+    pub fn add(&mut self, high: f64, low: f64, close: f64) -> KDJ {
         self.highs.push(high);
         self.lows.push(low);
-        self.closes.push(close);
 
         if self.highs.len() > self.rsv_period {
             self.highs.remove(0);
             self.lows.remove(0);
-            self.closes.remove(0);
         }
 
-        let rsv = if self.highs.len() == self.rsv_period {
-            let highest = self.highs.iter().max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
-            let lowest = self.lows.iter().min_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
-            let close = self.closes.last().unwrap();
-            ((close - lowest) / (highest - lowest)) * 100.0
+        // 不足一个周期时按已有的K线计算
+        let highest = self.highs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let lowest = self.lows.iter().copied().fold(f64::INFINITY, f64::min);
+        let rsv = if highest != lowest {
+            100.0 * (close - lowest) / (highest - lowest)
         } else {
             0.0
         };
 
-        self.last_k = if self.k_period == 1 {
-            rsv
-        } else {
-            (self.last_k * (self.k_period - 1) + rsv) / self.k_period
-        };
+        let k_period = self.k_period as f64;
+        let d_period = self.d_period as f64;
+        self.last_k = (self.last_k * (k_period - 1.0) + rsv) / k_period;
+        self.last_d = (self.last_d * (d_period - 1.0) + self.last_k) / d_period;
 
-        self.last_d = if self.d_period == 1 {
-            self.last_k
-        } else {
-            (self.last_d * (self.d_period - 1) + self.last_k) / self.d_period
-        };
+        KDJ {
+            k: self.last_k,
+            d: self.last_d,
+            j: 3.0 * self.last_k - 2.0 * self.last_d,
+        }
+    }
+}
+
+impl Indicator for KDJModel {
+    type Output = KDJ;
+
+    fn name(&self) -> String {
+        format!("kdj_{}_{}_{}", self.rsv_period, self.k_period, self.d_period)
+    }
+
+    fn update(&mut self, klu: &KLineUnit) -> KDJ {
+        self.add(klu.high, klu.low, klu.close)
+    }
+
+    fn store(&self, klu: &mut KLineUnit, output: KDJ) {
+        klu.kdj = Some(output);
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.rsv_period, self.k_period, self.d_period);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOSES: [f64; 36] = [
+        20.0, 20.49, 20.27, 19.16, 17.67, 16.58, 16.37, 16.87, 17.35, 17.11, 15.99, 14.5, 13.42, 13.24, 13.74, 14.21,
+        13.96, 12.83, 12.0, 13.46, 13.52, 12.81, 13.12, 14.72, 16.0, 15.82, 15.17, 15.74, 17.42, 18.49, 18.11, 17.57,
+        18.4, 20.11, 20.93, 20.39,
+    ];
+    const HIGHS: [f64; 36] = [
+        20.3, 20.99, 20.97, 19.46, 18.17, 17.28, 16.67, 17.37, 18.05, 17.41, 16.49, 15.2, 13.72, 13.74, 14.44, 14.51,
+        14.46, 13.53, 12.3, 13.96, 14.22, 13.11, 13.62, 15.42, 16.3, 16.32, 15.87, 16.04, 17.92, 19.19, 18.41, 18.07,
+        19.1, 20.41, 21.43, 21.09,
+    ];
+    const LOWS: [f64; 36] = [
+        19.65, 20.04, 19.72, 18.51, 17.32, 16.13, 15.82, 16.22, 17.0, 16.66, 15.44, 13.85, 13.07, 12.79, 13.19, 13.56,
+        13.61, 12.38, 11.45, 12.81, 13.17, 12.36, 12.57, 14.07, 15.65, 15.37, 14.62, 15.09, 17.07, 18.04, 17.56, 16.92,
+        18.05, 19.66, 20.38, 19.74,
+    ];
+
+    #[test]
+    fn test_kdj_reference_values() {
+        let mut model = KDJModel::new(9, 3, 3);
+        let items: Vec<KDJ> = (0..CLOSES.len()).map(|i| model.add(HIGHS[i], LOWS[i], CLOSES[i])).collect();
+        let expected = [
+            (85.26284287893023, 81.91950436480212, 91.94951990718644),
+            (87.72784728394663, 83.85561867118362, 95.47230450947265),
+            (86.35063635964475, 84.687291234004, 89.67732661092629),
+        ];
+        for (item, (k, d, j)) in items[items.len() - 3..].iter().zip(expected) {
+            assert!((item.k - k).abs() < 1e-9);
+            assert!((item.d - d).abs() < 1e-9);
+            assert!((item.j - j).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_kdj_flat_window() {
+        let mut model = KDJModel::new(9, 3, 3);
+        let item = model.add(10.0, 10.0, 10.0);
+        assert!((item.k - 100.0 / 3.0).abs() < 1e-9);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_kdj_smoothing_periods() {
+        // 平滑周期为1时K等于RSV，D等于K
+        let mut model = KDJModel::new(9, 1, 1);
+        model.add(12.0, 8.0, 10.0);
+        let item = model.add(11.0, 9.0, 11.0);
+        assert!((item.k - 75.0).abs() < 1e-9);
+        assert!((item.d - 75.0).abs() < 1e-9);
+        assert!((item.j - 75.0).abs() < 1e-9);
+    }
+}
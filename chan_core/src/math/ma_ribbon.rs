@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use strum_macros::Display;
+
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::metric_trait::Indicator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+pub enum MaType {
+    #[strum(serialize = "sma")]
+    Sma,
+    #[strum(serialize = "ema")]
+    Ema,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MaLine {
+    period: usize,
+    /// Sma：窗口内的收盘价
+    window: VecDeque<f64>,
+    sum: f64,
+    /// Ema：上一根的值，第一根取收盘价
+    ema: Option<f64>,
+}
+
+impl MaLine {
+    fn new(period: usize) -> Self {
+        Self {
+            period,
+            window: VecDeque::with_capacity(period),
+            sum: 0.0,
+            ema: None,
+        }
+    }
+
+    fn add(&mut self, ma_type: MaType, value: f64) -> f64 {
+        match ma_type {
+            MaType::Sma => {
+                self.window.push_back(value);
+                self.sum += value;
+                if self.window.len() > self.period {
+                    self.sum -= self.window.pop_front().unwrap();
+                }
+                self.sum / self.window.len() as f64
+            }
+            MaType::Ema => {
+                let ema = match self.ema {
+                    Some(pre) => (2.0 * value + (self.period as f64 - 1.0) * pre) / (self.period as f64 + 1.0),
+                    None => value,
+                };
+                self.ema = Some(ema);
+                ema
+            }
+        }
+    }
+}
+
+/// 一组不同周期的均线，结果按周期存放在`KLineUnit.ma`中
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaRibbon {
+    pub ma_type: MaType,
+    lines: Vec<MaLine>,
+}
+
+impl MaRibbon {
+    /// 每个周期须大于0，由`ChanConfig::new`校验
+    pub fn new(ma_type: MaType, periods: &[usize]) -> Self {
+        Self {
+            ma_type,
+            lines: periods.iter().map(|&period| MaLine::new(period)).collect(),
+        }
+    }
+
+    pub fn periods(&self) -> Vec<usize> {
+        self.lines.iter().map(|line| line.period).collect()
+    }
+
+    /// 返回(周期, 均线值)，顺序与构造时的周期一致
+    pub fn add(&mut self, value: f64) -> Vec<(usize, f64)> {
+        let ma_type = self.ma_type;
+        self.lines.iter_mut().map(|line| (line.period, line.add(ma_type, value))).collect()
+    }
+}
+
+impl Indicator for MaRibbon {
+    type Output = Vec<(usize, f64)>;
+
+    fn name(&self) -> String {
+        let periods: Vec<String> = self.lines.iter().map(|line| line.period.to_string()).collect();
+        format!("{}_{}", self.ma_type, periods.join("_"))
+    }
+
+    fn update(&mut self, klu: &KLineUnit) -> Self::Output {
+        self.add(klu.close)
+    }
+
+    fn store(&self, klu: &mut KLineUnit, output: Self::Output) {
+        klu.ma.entry(self.ma_type).or_default().extend(output);
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.ma_type, &self.periods());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sma_and_ema() {
+        let closes = [10.0, 11.0, 12.0, 13.0];
+        let mut sma = MaRibbon::new(MaType::Sma, &[1, 3]);
+        let mut ema = MaRibbon::new(MaType::Ema, &[3]);
+        let sma_res: Vec<Vec<(usize, f64)>> = closes.iter().map(|&c| sma.add(c)).collect();
+        let ema_res: Vec<f64> = closes.iter().map(|&c| ema.add(c)[0].1).collect();
+
+        assert_eq!(sma_res[3], vec![(1, 13.0), (3, 12.0)]);
+        assert_eq!(sma_res[1], vec![(1, 11.0), (3, 10.5)]);
+        assert_eq!(ema_res, vec![10.0, 10.5, 11.25, 12.125]);
+    }
+}
@@ -1,3 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::metric_trait::Indicator;
+
 #[derive(Debug, Clone)]
 pub struct MACDItem {
     pub dif: f64,
@@ -5,7 +10,7 @@ pub struct MACDItem {
     pub macd: f64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MACD {
     short_ema: f64,
     long_ema: f64,
@@ -59,4 +64,24 @@ impl MACD {
             macd: 2.0 * (dif - self.dea),
         }
     }
+}
+
+impl Indicator for MACD {
+    type Output = MACDItem;
+
+    fn name(&self) -> String {
+        format!("macd_{}_{}_{}", self.short_period, self.long_period, self.dea_period)
+    }
+
+    fn update(&mut self, klu: &KLineUnit) -> MACDItem {
+        self.add(klu.close)
+    }
+
+    fn store(&self, klu: &mut KLineUnit, output: MACDItem) {
+        klu.macd = Some(output);
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.short_period, self.long_period, self.dea_period);
+    }
 } 
\ No newline at end of file
@@ -1,6 +1,11 @@
+pub mod atr;
 pub mod boll;
 pub mod demark;
 pub mod kdj;
+pub mod ma_ribbon;
 pub mod macd;
+pub mod obv;
 pub mod rsi;
-pub mod trend_model; 
\ No newline at end of file
+pub mod trend_line;
+pub mod trend_model;
+pub mod vwap; 
\ No newline at end of file
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use crate::common::cenum::DataField;
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::metric_trait::Indicator;
+
+/// 能量潮：收涨累加成交量，收跌累减，缺少成交量时按0处理
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OBV {
+    pre_close: Option<f64>,
+    obv: f64,
+}
+
+impl OBV {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, close: f64, volume: f64) -> f64 {
+        if let Some(pre_close) = self.pre_close {
+            if close > pre_close {
+                self.obv += volume;
+            } else if close < pre_close {
+                self.obv -= volume;
+            }
+        }
+        self.pre_close = Some(close);
+        self.obv
+    }
+}
+
+impl Indicator for OBV {
+    type Output = f64;
+
+    fn name(&self) -> String {
+        "obv".to_string()
+    }
+
+    fn update(&mut self, klu: &KLineUnit) -> f64 {
+        let volume = klu.trade_info.metric.get(DataField::FieldVolume.as_str()).copied().flatten();
+        self.add(klu.close, volume.unwrap_or(0.0))
+    }
+
+    fn store(&self, klu: &mut KLineUnit, output: f64) {
+        klu.obv = Some(output);
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_obv() {
+        let mut model = OBV::new();
+        let res: Vec<f64> = [(10.0, 100.0), (11.0, 200.0), (11.0, 300.0), (10.5, 50.0)]
+            .iter()
+            .map(|&(close, volume)| model.add(close, volume))
+            .collect();
+        assert_eq!(res, vec![0.0, 200.0, 200.0, 150.0]);
+    }
+}
@@ -1,9 +1,23 @@
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::metric_trait::Indicator;
+
+/// Wilder平滑的RSI
+///
+/// 与之前按窗口等权平均的RSI结果不同：第一根K线返回50而不是None，之后每根都有值
+/// （不足一个周期时累计值仍除以period）；超出窗口的涨跌幅按(period-1)/period衰减而不是直接丢弃。
+/// 只有上涨没有下跌时为100，没有任何涨跌时为50
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RSI {
     period: usize,
     last_price: Option<f64>,
-    gains: Vec<f64>,
-    losses: Vec<f64>,
+    diff_cnt: usize,
+    /// 不足一个周期时，已有涨跌幅的累计值
+    up_sum: f64,
+    down_sum: f64,
+    up: f64,
+    down: f64,
 }
 
 impl RSI {
@@ -11,43 +25,94 @@ impl RSI {
         Self {
             period,
             last_price: None,
-            gains: Vec::with_capacity(period),
-            losses: Vec::with_capacity(period),
+            diff_cnt: 0,
+            up_sum: 0.0,
+            down_sum: 0.0,
+            up: 0.0,
+            down: 0.0,
         }
     }
 
-    pub fn add(&mut self, price: f64) -> Option<f64> {
-        if let Some(last_price) = self.last_price {
-            let change = price - last_price;
-            
-            if change >= 0.0 {
-                self.gains.push(change);
-                self.losses.push(0.0);
-            } else {
-                self.gains.push(0.0);
-                self.losses.push(-change);
-            }
-
-            if self.gains.len() > self.period {
-                self.gains.remove(0);
-                self.losses.remove(0);
-            }
-
-            if self.gains.len() == self.period {
-                let avg_gain = self.gains.iter().sum::<f64>() / self.period as f64;
-                let avg_loss = self.losses.iter().sum::<f64>() / self.period as f64;
-
-                if avg_loss == 0.0 {
-                    Some(100.0)
-                } else {
-                    let rs = avg_gain / avg_loss;
-                    Some(100.0 - (100.0 / (1.0 + rs)))
-                }
-            } else {
-                None
-            }
+    pub fn add(&mut self, price: f64) -> f64 {
+        let last_price = match self.last_price.replace(price) {
+            Some(last_price) => last_price,
+            None => return 50.0,
+        };
+        let diff = price - last_price;
+        let (upval, downval) = if diff > 0.0 { (diff, 0.0) } else { (0.0, -diff) };
+        let period = self.period as f64;
+
+        self.diff_cnt += 1;
+        if self.diff_cnt < self.period {
+            // 前period-1根的均值仍然除以period，与chan.py一致
+            self.up_sum += upval;
+            self.down_sum += downval;
+            self.up = self.up_sum / period;
+            self.down = self.down_sum / period;
         } else {
-            None
-        }.map(|rsi| rsi.max(0.0).min(100.0))
+            self.up = (self.up * (period - 1.0) + upval) / period;
+            self.down = (self.down * (period - 1.0) + downval) / period;
+        }
+
+        if self.down == 0.0 {
+            return if self.up > 0.0 { 100.0 } else { 50.0 };
+        }
+        100.0 - 100.0 / (1.0 + self.up / self.down)
+    }
+}
+
+impl Indicator for RSI {
+    type Output = f64;
+
+    fn name(&self) -> String {
+        format!("rsi_{}", self.period)
+    }
+
+    fn update(&mut self, klu: &KLineUnit) -> f64 {
+        self.add(klu.close)
+    }
+
+    fn store(&self, klu: &mut KLineUnit, output: f64) {
+        klu.rsi = Some(output);
     }
-} 
\ No newline at end of file
+
+    fn reset(&mut self) {
+        *self = Self::new(self.period);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOSES: [f64; 36] = [
+        20.0, 20.49, 20.27, 19.16, 17.67, 16.58, 16.37, 16.87, 17.35, 17.11, 15.99, 14.5, 13.42, 13.24, 13.74, 14.21,
+        13.96, 12.83, 12.0, 13.46, 13.52, 12.81, 13.12, 14.72, 16.0, 15.82, 15.17, 15.74, 17.42, 18.49, 18.11, 17.57,
+        18.4, 20.11, 20.93, 20.39,
+    ];
+
+    #[test]
+    fn test_rsi_reference_values() {
+        let mut model = RSI::new(6);
+        let values: Vec<f64> = CLOSES.iter().map(|&close| model.add(close)).collect();
+        assert_eq!(values[0], 50.0);
+        let expected = [79.99764372877927, 82.97741015069266, 74.23788552203739];
+        for (value, expected) in values[values.len() - 3..].iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_rsi_without_loss() {
+        let mut model = RSI::new(6);
+        let values: Vec<f64> = (0..10).map(|i| model.add(10.0 + i as f64)).collect();
+        assert_eq!(values[0], 50.0);
+        assert!(values[1..].iter().all(|&value| value == 100.0));
+    }
+
+    #[test]
+    fn test_rsi_flat_price() {
+        let mut model = RSI::new(6);
+        assert!((0..10).all(|_| model.add(10.0) == 50.0));
+    }
+}
@@ -0,0 +1,280 @@
+use std::marker::PhantomData;
+
+use crate::common::enums::{BiDir, TrendLineSide};
+use crate::common::handle::Handle;
+use crate::common::time::Time;
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::line_trait::LineTrait;
+
+/// 趋势线上的点，x为KLU序号
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: usize,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: usize, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    pub fn cal_slope(&self, p: &Point) -> f64 {
+        if self.x != p.x {
+            (self.y - p.y) / (self.x as f64 - p.x as f64)
+        } else {
+            f64::INFINITY
+        }
+    }
+}
+
+/// 过点p、斜率为slope的直线
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Line {
+    pub p: Point,
+    pub slope: f64,
+}
+
+impl Line {
+    /// 点到直线的距离
+    pub fn cal_dis(&self, p: &Point) -> f64 {
+        (self.slope * p.x as f64 - p.y + self.p.y - self.slope * self.p.x as f64).abs()
+            / (self.slope.powi(2) + 1.0).sqrt()
+    }
+
+    /// 直线在y轴上的截距
+    pub fn intercept(&self) -> f64 {
+        self.p.y - self.slope * self.p.x as f64
+    }
+
+    /// 直线在KLU序号x处的价格
+    pub fn y_at(&self, x: usize) -> f64 {
+        self.p.y + self.slope * (x as f64 - self.p.x as f64)
+    }
+}
+
+/// 线段内笔端点拟合的趋势线
+///
+/// Inside取每一笔起点（线段内部的回调端点），Outside取每一笔终点（线段的外沿端点），
+/// 在所有候选直线中选择到全部端点距离之和最小的一条
+#[derive(Debug, Clone)]
+pub struct TrendLine<T> {
+    pub line: Option<Line>,
+    pub side: TrendLineSide,
+    pub dir: BiDir,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: LineTrait> TrendLine<T> {
+    /// 方向取最后一笔的方向，笔列表为空时返回None
+    pub fn new(lst: &[Handle<T>], side: TrendLineSide) -> Option<Self> {
+        let dir = if lst.last()?.borrow().is_up() { BiDir::Up } else { BiDir::Down };
+        let mut trend_line = Self {
+            line: None,
+            side,
+            dir,
+            _phantom: PhantomData,
+        };
+        trend_line.cal(lst);
+        Some(trend_line)
+    }
+
+    fn cal(&mut self, lst: &[Handle<T>]) {
+        // 从最后一笔开始，每隔一笔取一个点（即与线段同向的笔）
+        let all_p: Vec<Point> = lst
+            .iter()
+            .rev()
+            .step_by(2)
+            .map(|bi| {
+                let bi = bi.borrow();
+                match self.side {
+                    TrendLineSide::Inside => Point::new(bi.get_begin_klu().index(), bi.get_begin_val()),
+                    TrendLineSide::Outside => Point::new(bi.get_end_klu().index(), bi.get_end_val()),
+                }
+            })
+            .collect();
+
+        let mut bench = f64::INFINITY;
+        let mut c_p = &all_p[..];
+        while c_p.len() > 1 {
+            let (line, idx) = cal_tl(c_p, self.dir, self.side);
+            let dis = if line.slope.is_finite() {
+                all_p.iter().map(|p| line.cal_dis(p)).sum::<f64>()
+            } else {
+                f64::INFINITY
+            };
+            if dis < bench {
+                bench = dis;
+                self.line = Some(line);
+            }
+            c_p = &c_p[idx..];
+        }
+    }
+
+    /// 趋势线斜率，未能拟合出趋势线时为None
+    pub fn slope(&self) -> Option<f64> {
+        self.line.map(|line| line.slope)
+    }
+
+    /// 趋势线在KLU序号0处的截距，未能拟合出趋势线时为None
+    pub fn intercept(&self) -> Option<f64> {
+        self.line.map(|line| line.intercept())
+    }
+
+    /// 趋势线在KLU序号klu_idx处的价格，参数是KLU序号而不是时间
+    pub fn price_at_idx(&self, klu_idx: usize) -> Option<f64> {
+        self.line.map(|line| line.y_at(klu_idx))
+    }
+
+    /// 趋势线在time时刻的价格
+    ///
+    /// klus为按时间排序的K线，取第一根时间不早于time的KLU的序号；
+    /// time晚于最后一根KLU时，按最后两根KLU的时间间隔向后外推序号
+    pub fn price_at_time(&self, time: &Time, klus: &[KLineUnit]) -> Option<f64> {
+        let pos = klus.partition_point(|klu| klu.time.timestamp < time.timestamp);
+        let klu_idx = match klus.get(pos) {
+            Some(klu) => klu.index(),
+            None => {
+                let [.., prev, last] = klus else {
+                    return None;
+                };
+                let interval = last.time.timestamp - prev.time.timestamp;
+                if interval <= 0 {
+                    return None;
+                }
+                let bars_after = (time.timestamp - last.time.timestamp + interval - 1) / interval;
+                last.index() + bars_after as usize
+            }
+        };
+        self.price_at_idx(klu_idx)
+    }
+
+    /// 趋势线是否连接的是低点（下方的线），否则连接的是高点
+    pub fn is_lower_line(&self) -> bool {
+        matches!(
+            (self.dir, self.side),
+            (BiDir::Up, TrendLineSide::Inside) | (BiDir::Down, TrendLineSide::Outside)
+        )
+    }
+
+    /// 收盘价是否突破趋势线：下方的线跌破，上方的线升破
+    pub fn is_break(&self, klu: &KLineUnit) -> bool {
+        match self.price_at_idx(klu.index()) {
+            Some(price) if self.is_lower_line() => klu.close < price,
+            Some(price) => klu.close > price,
+            None => false,
+        }
+    }
+
+    /// 在后续K线中找到第一根突破趋势线的KLU
+    pub fn find_breakout<'a, I>(&self, klus: I) -> Option<&'a KLineUnit>
+    where
+        I: IntoIterator<Item = &'a KLineUnit>,
+    {
+        klus.into_iter().find(|klu| self.is_break(klu))
+    }
+}
+
+fn init_peak_slope(dir: BiDir, side: TrendLineSide) -> f64 {
+    match (side, dir) {
+        (TrendLineSide::Inside, _) => 0.0,
+        (TrendLineSide::Outside, BiDir::Up) => f64::INFINITY,
+        (TrendLineSide::Outside, BiDir::Down) => f64::NEG_INFINITY,
+    }
+}
+
+/// 以c_p[0]为起点找到最贴合的直线，返回直线以及下一轮的起点位置
+fn cal_tl(c_p: &[Point], dir: BiDir, side: TrendLineSide) -> (Line, usize) {
+    let p = c_p[0];
+    let mut peak_slope = init_peak_slope(dir, side);
+    let mut idx = 1;
+    for (point_idx, p2) in c_p[1..].iter().enumerate() {
+        let slope = p.cal_slope(p2);
+        if (dir == BiDir::Up && slope < 0.0) || (dir == BiDir::Down && slope > 0.0) {
+            continue;
+        }
+        let is_peak = match side {
+            TrendLineSide::Inside => {
+                (dir == BiDir::Up && slope > peak_slope) || (dir == BiDir::Down && slope < peak_slope)
+            }
+            TrendLineSide::Outside => {
+                (dir == BiDir::Up && slope < peak_slope) || (dir == BiDir::Down && slope > peak_slope)
+            }
+        };
+        if is_peak {
+            peak_slope = slope;
+            idx = point_idx + 1;
+        }
+    }
+    (Line { p, slope: peak_slope }, idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::test_util::{bi_handles, build_bi_lst, MockBi};
+
+    // 前5笔构成一个向上的线段，低点和高点都在斜率2.5的直线上
+    const VALS: [f64; 8] = [10.0, 20.0, 15.0, 25.0, 20.0, 30.0, 26.0, 24.0];
+
+    #[test]
+    fn test_empty_bi_list() {
+        assert!(TrendLine::<MockBi>::new(&[], TrendLineSide::Inside).is_none());
+    }
+
+    #[test]
+    fn test_single_bi_has_no_line() {
+        let (_klus, bis) = build_bi_lst(&VALS);
+        let bi_lst = bi_handles(&bis);
+        let trend_line = TrendLine::new(&bi_lst[..1], TrendLineSide::Outside).unwrap();
+        assert_eq!(trend_line.dir, BiDir::Up);
+        assert!(trend_line.line.is_none());
+        assert!(trend_line.price_at_idx(3).is_none());
+    }
+
+    #[test]
+    fn test_fit_inside_and_outside() {
+        let (_klus, bis) = build_bi_lst(&VALS);
+        let bi_lst = bi_handles(&bis);
+
+        let support = TrendLine::new(&bi_lst[..5], TrendLineSide::Inside).unwrap();
+        assert!(support.is_lower_line());
+        assert_eq!(support.slope(), Some(2.5));
+        assert_eq!(support.intercept(), Some(10.0));
+
+        let resistance = TrendLine::new(&bi_lst[..5], TrendLineSide::Outside).unwrap();
+        assert!(!resistance.is_lower_line());
+        assert_eq!(resistance.slope(), Some(2.5));
+        assert_eq!(resistance.price_at_idx(7), Some(35.0));
+    }
+
+    #[test]
+    fn test_price_at_time() {
+        let (mut klus, bis) = build_bi_lst(&VALS);
+        let bi_lst = bi_handles(&bis);
+        let support = TrendLine::new(&bi_lst[..5], TrendLineSide::Inside).unwrap();
+        let day = 86_400;
+        let t0 = 1_704_067_200;
+        for (idx, klu) in klus.iter_mut().enumerate() {
+            klu.time = Time::new(t0 + idx as i64 * day);
+        }
+        // 恰好是某根KLU的时间
+        assert_eq!(support.price_at_time(&klus[2].time, &klus), Some(15.0));
+        // 两根KLU之间取后一根
+        assert_eq!(support.price_at_time(&Time::new(t0 + 3 * day - 1), &klus), Some(17.5));
+        // 最后一根KLU之后按日线间隔外推：序号7之后两天为序号9
+        assert_eq!(support.price_at_time(&Time::new(t0 + 9 * day), &klus), Some(32.5));
+        assert!(support.price_at_time(&Time::new(t0), &klus[..0]).is_none());
+    }
+
+    #[test]
+    fn test_find_breakout() {
+        let (klus, bis) = build_bi_lst(&VALS);
+        let bi_lst = bi_handles(&bis);
+        let support = TrendLine::new(&bi_lst[..5], TrendLineSide::Inside).unwrap();
+        // 恰好落在趋势线上不算突破
+        assert!(!support.is_break(&klus[4]));
+        // 26 > 25 未跌破，24 < 27.5 跌破
+        let breakout = support.find_breakout(&klus[5..]).unwrap();
+        assert_eq!(breakout.index(), 7);
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::cenum::TrendType;
+use crate::common::chan_exception::{ChanException, ErrCode};
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::metric_trait::Indicator;
+
+/// 收盘价的滚动均值/最大值/最小值
+///
+/// 均值维护窗口内的累加和，每滑过一个周期按窗口重新求和以消除累计误差；
+/// 最大/最小值用单调队列，每根KLU均摊O(1)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrendModel {
+    pub trend_type: TrendType,
+    pub period: i32,
+    /// 已加入的数据个数，作为单调队列中的序号
+    cnt: usize,
+    /// Mean：窗口内的全部数据
+    window: VecDeque<f64>,
+    sum: f64,
+    /// Max/Min：(序号, 值)，值单调不增(Max)或不减(Min)，队首即窗口极值
+    peaks: VecDeque<(usize, f64)>,
+}
+
+impl TrendModel {
+    pub fn new(trend_type: TrendType, period: i32) -> Result<Self, ChanException> {
+        if period <= 0 {
+            return Err(ChanException::new(
+                format!("{} trend period must be positive, got {}", trend_type, period),
+                ErrCode::ParaError,
+            ));
+        }
+        Ok(Self {
+            trend_type,
+            period,
+            cnt: 0,
+            window: VecDeque::with_capacity(period as usize),
+            sum: 0.0,
+            peaks: VecDeque::new(),
+        })
+    }
+
+    pub fn add(&mut self, value: f64) -> f64 {
+        let period = self.period as usize;
+        let idx = self.cnt;
+        self.cnt += 1;
+        match self.trend_type {
+            TrendType::Mean => {
+                self.window.push_back(value);
+                self.sum += value;
+                if self.window.len() > period {
+                    self.sum -= self.window.pop_front().unwrap();
+                }
+                if self.cnt % period == 0 {
+                    self.sum = self.window.iter().sum();
+                }
+                self.sum / self.window.len() as f64
+            }
+            TrendType::Max | TrendType::Min => {
+                let is_max = self.trend_type == TrendType::Max;
+                while let Some(&(_, back)) = self.peaks.back() {
+                    if (is_max && back <= value) || (!is_max && back >= value) {
+                        self.peaks.pop_back();
+                    } else {
+                        break;
+                    }
+                }
+                self.peaks.push_back((idx, value));
+                while self.peaks.front().is_some_and(|&(front_idx, _)| front_idx + period <= idx) {
+                    self.peaks.pop_front();
+                }
+                self.peaks.front().unwrap().1
+            }
+        }
+    }
+}
+
+impl Indicator for TrendModel {
+    type Output = f64;
+
+    fn name(&self) -> String {
+        format!("{}_{}", self.trend_type, self.period)
+    }
+
+    fn update(&mut self, klu: &KLineUnit) -> f64 {
+        self.add(klu.close)
+    }
+
+    fn store(&self, klu: &mut KLineUnit, output: f64) {
+        klu.trend.entry(self.trend_type).or_default().insert(self.period, output);
+    }
+
+    fn reset(&mut self) {
+        self.cnt = 0;
+        self.window.clear();
+        self.sum = 0.0;
+        self.peaks.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 与chan.py一致的朴素实现：每次对整个窗口求值
+    fn naive(trend_type: TrendType, period: usize, arr: &[f64]) -> Vec<f64> {
+        (0..arr.len())
+            .map(|i| {
+                let window = &arr[(i + 1).saturating_sub(period)..=i];
+                match trend_type {
+                    TrendType::Mean => window.iter().sum::<f64>() / window.len() as f64,
+                    TrendType::Max => window.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                    TrendType::Min => window.iter().copied().fold(f64::INFINITY, f64::min),
+                }
+            })
+            .collect()
+    }
+
+    fn prices() -> Vec<f64> {
+        // 带平台和重复值的确定性序列
+        (0..500)
+            .map(|i| {
+                let x = i as f64;
+                ((x * 0.37).sin() * 10.0 + (x * 0.05).cos() * 5.0).round() / 2.0 + 100.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rolling_matches_naive() {
+        let arr = prices();
+        for trend_type in [TrendType::Mean, TrendType::Max, TrendType::Min] {
+            for period in [1, 2, 5, 20, 600] {
+                let mut model = TrendModel::new(trend_type, period).unwrap();
+                let res: Vec<f64> = arr.iter().map(|&v| model.add(v)).collect();
+                for (a, b) in res.iter().zip(naive(trend_type, period as usize, &arr)) {
+                    assert!((a - b).abs() < 1e-9, "{} {}: {} != {}", trend_type, period, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_peak_queue_is_bounded() {
+        // 单调下跌时Max队列长度不超过周期
+        let mut model = TrendModel::new(TrendType::Max, 10).unwrap();
+        for i in 0..1000 {
+            assert_eq!(model.add(1000.0 - i as f64), 1000.0 - (i as f64 - 9.0).max(0.0));
+            assert!(model.peaks.len() <= 10);
+        }
+    }
+
+    #[test]
+    fn test_mean_resync_drops_rounding_error() {
+        // 1e16加1会被舍入，之后减去1e16时累加和丢掉了1
+        let mut model = TrendModel::new(TrendType::Mean, 2).unwrap();
+        let res: Vec<f64> = [1e16, 1.0, 1.0, 1.0, 1.0].iter().map(|&v| model.add(v)).collect();
+        assert_eq!(&res[3..], &[1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_invalid_period() {
+        for period in [0, -5] {
+            let err = TrendModel::new(TrendType::Mean, period).unwrap_err();
+            assert_eq!(err.errcode, ErrCode::ParaError);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+
+use crate::common::cenum::DataField;
+use crate::kline::kline_unit::KLineUnit;
+use crate::traits::metric_trait::Indicator;
+
+/// 按交易日重置的成交量加权均价，价格取(high+low+close)/3
+///
+/// 当日累计成交量为0时返回当根的典型价格
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VWAP {
+    /// 当前交易日，公元以来的天数
+    session: Option<i32>,
+    pv_sum: f64,
+    volume_sum: f64,
+}
+
+impl VWAP {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, session: i32, high: f64, low: f64, close: f64, volume: f64) -> f64 {
+        if self.session != Some(session) {
+            self.session = Some(session);
+            self.pv_sum = 0.0;
+            self.volume_sum = 0.0;
+        }
+        let typical_price = (high + low + close) / 3.0;
+        self.pv_sum += typical_price * volume;
+        self.volume_sum += volume;
+        if self.volume_sum > 0.0 {
+            self.pv_sum / self.volume_sum
+        } else {
+            typical_price
+        }
+    }
+}
+
+impl Indicator for VWAP {
+    type Output = f64;
+
+    fn name(&self) -> String {
+        "vwap".to_string()
+    }
+
+    fn update(&mut self, klu: &KLineUnit) -> f64 {
+        let volume = klu.trade_info.metric.get(DataField::FieldVolume.as_str()).copied().flatten();
+        let session = klu.time.date().num_days_from_ce();
+        self.add(session, klu.high, klu.low, klu.close, volume.unwrap_or(0.0))
+    }
+
+    fn store(&self, klu: &mut KLineUnit, output: f64) {
+        klu.vwap = Some(output);
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vwap_resets_each_session() {
+        let mut model = VWAP::new();
+        assert_eq!(model.add(1, 12.0, 9.0, 9.0, 100.0), 10.0);
+        assert_eq!(model.add(1, 13.0, 11.0, 12.0, 300.0), 11.5);
+        assert_eq!(model.add(2, 21.0, 19.0, 20.0, 0.0), 20.0);
+        assert_eq!(model.add(2, 22.0, 20.0, 21.0, 10.0), 21.0);
+    }
+}
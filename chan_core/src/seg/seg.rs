@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 use crate::common::{
-    enums::{BiDir, MacdAlgo, TrendLineSide},
+    enums::{BiDir, TrendLineSide},
     chan_exception::{ChanException, ErrCode},
     handle::Handle,
 };
@@ -9,6 +9,7 @@ use crate::kline::kline_unit::KLineUnit;
 use crate::math::trend_line::TrendLine;
 use crate::zs::zs::Zs;
 use crate::buy_sell_point::bs_point::BsPoint;
+use crate::traits::line_trait::LineTrait;
 use super::eigen_fx::EigenFX;
 
 /// 线段结构
@@ -207,44 +208,6 @@ where T: Clone + std::fmt::Debug
         self.get_end_klu().index() - self.get_begin_klu().index() + 1
     }
 
-    /// Calculate MACD metric
-    pub fn cal_macd_metric(&self, macd_algo: MacdAlgo, is_reverse: bool) -> Result<f64, ChanException> {
-        match macd_algo {
-            MacdAlgo::Slope => Ok(self.cal_macd_slope()),
-            MacdAlgo::Amp => Ok(self.cal_macd_amp()),
-            _ => Err(ChanException::new(
-                format!("unsupport macd_algo={:?} of Seg, should be one of slope/amp", macd_algo),
-                ErrCode::ParaError,
-            )),
-        }
-    }
-
-    /// Calculate MACD slope
-    pub fn cal_macd_slope(&self) -> f64 {
-        let begin_klu = self.get_begin_klu();
-        let end_klu = self.get_end_klu();
-        
-        if self.is_up() {
-            (end_klu.high() - begin_klu.low()) / end_klu.high() / 
-            (end_klu.index() as f64 - begin_klu.index() as f64 + 1.0)
-        } else {
-            (begin_klu.high() - end_klu.low()) / begin_klu.high() / 
-            (end_klu.index() as f64 - begin_klu.index() as f64 + 1.0)
-        }
-    }
-
-    /// Calculate MACD amplitude
-    pub fn cal_macd_amp(&self) -> f64 {
-        let begin_klu = self.get_begin_klu();
-        let end_klu = self.get_end_klu();
-        
-        if self.is_down() {
-            (begin_klu.high() - end_klu.low()) / begin_klu.high()
-        } else {
-            (end_klu.high() - begin_klu.low()) / begin_klu.low()
-        }
-    }
-
     /// Update bi list
     pub fn update_bi_list(&mut self, bi_lst: &[Handle<T>], idx1: usize, idx2: usize) {
         for bi_idx in idx1..=idx2 {
@@ -255,14 +218,8 @@ where T: Clone + std::fmt::Debug
         }
 
         if self.bi_list.len() >= 3 {
-            self.support_trend_line = Some(TrendLine::new(
-                &self.bi_list,
-                TrendLineSide::Inside,
-            ));
-            self.resistance_trend_line = Some(TrendLine::new(
-                &self.bi_list,
-                TrendLineSide::Outside,
-            ));
+            self.support_trend_line = TrendLine::new(&self.bi_list, TrendLineSide::Inside);
+            self.resistance_trend_line = TrendLine::new(&self.bi_list, TrendLineSide::Outside);
         }
     }
 
@@ -289,6 +246,25 @@ where T: Clone + std::fmt::Debug
     }
 }
 
+impl<T> LineTrait for Seg<T>
+where T: LineTrait + Clone + std::fmt::Debug
+{
+    fn get_begin_klu(&self) -> Handle<KLineUnit> { Seg::get_begin_klu(self) }
+    fn get_end_klu(&self) -> Handle<KLineUnit> { Seg::get_end_klu(self) }
+    fn _low(&self) -> f64 { self.low() }
+    fn _high(&self) -> f64 { self.high() }
+    fn idx(&self) -> usize { self.index() }
+    fn end_bi_idx(&self) -> usize { self.end_bi.borrow().end_bi_idx() }
+    fn seg_idx(&self) -> Option<usize> { self.seg_idx }
+    fn set_seg_idx(&mut self, idx: usize) { self.seg_idx = Some(idx); }
+    fn get_begin_val(&self) -> f64 { Seg::get_begin_val(self) }
+    fn get_end_val(&self) -> f64 { Seg::get_end_val(self) }
+    fn is_down(&self) -> bool { Seg::is_down(self) }
+    fn is_up(&self) -> bool { Seg::is_up(self) }
+    fn is_sure(&self) -> bool { self.is_sure }
+    fn parent_seg_dir(&self) -> Option<BiDir> { self.parent_seg.as_ref().map(|seg| seg.dir) }
+}
+
 impl<T> std::fmt::Display for Seg<T>
 where T: Clone + std::fmt::Debug
 {
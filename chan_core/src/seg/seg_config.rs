@@ -0,0 +1,39 @@
+use crate::common::{
+    chan_exception::{ChanException, ErrCode},
+    enums::LeftSegMethod,
+};
+
+/// 线段的配置结构
+#[derive(Debug, Clone)]
+pub struct SegConfig {
+    /// 线段算法："chan"、"1+1" 或 "break"
+    pub seg_algo: String,
+
+    /// 最后一个确定线段之后剩余笔的处理方法
+    pub left_method: LeftSegMethod,
+}
+
+impl SegConfig {
+    /// Create a new SegConfig
+    pub fn new(seg_algo: Option<String>, left_method: Option<&str>) -> Result<Self, ChanException> {
+        let left_method = match left_method.unwrap_or("peak") {
+            "all" => LeftSegMethod::All,
+            "peak" => LeftSegMethod::Peak,
+            unknown => return Err(ChanException::new(
+                format!("unknown left_seg_method={}", unknown),
+                ErrCode::ParaError,
+            )),
+        };
+
+        Ok(Self {
+            seg_algo: seg_algo.unwrap_or_else(|| "chan".to_string()),
+            left_method,
+        })
+    }
+}
+
+impl Default for SegConfig {
+    fn default() -> Self {
+        Self::new(None, None).expect("Default SegConfig creation should never fail")
+    }
+}
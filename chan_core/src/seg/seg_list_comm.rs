@@ -1,7 +1,6 @@
 use super::seg::Seg;
 use super::seg_config::SegConfig;
 use crate::bi::bi::Bi;
-use crate::bi::bi_list::BiList;
 use crate::common::{
     chan_exception::{ChanException, ErrCode},
     enums::{BiDir, LeftSegMethod, SegType},
@@ -9,7 +8,7 @@ use crate::common::{
 };
 
 /// 线段列表通用结构
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SegListComm<T> {
     pub lst: Vec<Handle<Seg<T>>>,
     pub lv: SegType,
@@ -176,12 +175,6 @@ where
                     Some("0seg_collect_all".to_string()),
                 )?;
             }
-            _ => {
-                return Err(ChanException::new(
-                    format!("unknown seg left_method = {:?}", self.config.left_method),
-                    ErrCode::ParaError,
-                ))
-            }
         }
         Ok(())
     }
@@ -223,9 +216,70 @@ where
                 }
             }
         }
+        self.collect_left_as_seg(bi_lst)
+    }
+
+    /// Collect segments after the last seg
+    ///
+    /// 剩余部分突破了最后一个线段的尾部时强制按峰值划分；
+    /// 否则剩余部分的处理由left_seg_method决定：all整体作为一个线段，peak先找峰值再收集剩余
+    pub fn collect_segs(&mut self, bi_lst: &[Handle<T>]) -> Result<(), ChanException> {
+        if self.lst.is_empty() {
+            return Ok(());
+        }
+        let last_bi = bi_lst.last().expect("bi_lst should not be empty").clone();
+        let last_seg_end_bi = self.lst.last().unwrap().borrow().end_bi.clone();
+        let (last_bi_idx, last_end_idx) = (last_bi.borrow().idx, last_seg_end_bi.borrow().idx);
+        if last_bi_idx < last_end_idx + 3 {
+            return Ok(());
+        }
+
+        let last_bi_end_val = last_bi.borrow().get_end_val();
+        let last_seg_end_val = last_seg_end_bi.borrow().get_end_val();
+        if last_seg_end_bi.borrow().is_down() && last_bi_end_val <= last_seg_end_val {
+            if let Some(peak_bi) = Self::find_peak_bi(bi_lst[last_end_idx + 3..].iter(), true) {
+                self.add_new_seg(
+                    bi_lst,
+                    peak_bi.borrow().idx,
+                    Some(false),
+                    Some(BiDir::Up),
+                    None,
+                    Some("collectleft_find_high_force".to_string()),
+                )?;
+                self.collect_left_seg(bi_lst)?;
+            }
+        } else if last_seg_end_bi.borrow().is_up() && last_bi_end_val >= last_seg_end_val {
+            if let Some(peak_bi) = Self::find_peak_bi(bi_lst[last_end_idx + 3..].iter(), false) {
+                self.add_new_seg(
+                    bi_lst,
+                    peak_bi.borrow().idx,
+                    Some(false),
+                    Some(BiDir::Down),
+                    None,
+                    Some("collectleft_find_low_force".to_string()),
+                )?;
+                self.collect_left_seg(bi_lst)?;
+            }
+        } else {
+            // 剩下线段的尾部相比于最后一个线段的尾部，高低关系和最后一个虚线段的方向一致
+            match self.config.left_method {
+                // 容易找不到二类买卖点！！
+                LeftSegMethod::All => self.collect_left_as_seg(bi_lst)?,
+                LeftSegMethod::Peak => self.collect_left_seg_peak_method(&last_seg_end_bi, bi_lst)?,
+            }
+        }
         Ok(())
     }
 
+    /// Collect the unfinished segments after the last sure seg
+    pub fn collect_left_seg(&mut self, bi_lst: &[Handle<T>]) -> Result<(), ChanException> {
+        if self.lst.is_empty() {
+            self.collect_first_seg(bi_lst)
+        } else {
+            self.collect_segs(bi_lst)
+        }
+    }
+
     /// Try to add new segment
     pub fn try_add_new_seg(
         &mut self,
@@ -336,29 +390,36 @@ where
         self.lst.iter().any(|seg| seg.is_sure)
     }
 
-    /// Collect left segment as a new segment
+    /// Collect all bis after the last seg as one unsure seg
+    ///
+    /// 最后一笔与最后一个线段尾笔同向时，线段截止到倒数第二笔，保证线段方向与最后一个线段相反
     pub fn collect_left_as_seg(&mut self, bi_lst: &[Handle<T>]) -> Result<(), ChanException> {
+        // 第一个线段可能因为SegEndValueErr没有加入
         if self.lst.is_empty() {
             return Ok(());
         }
-
-        let last_seg_end_bi = &self.lst.last().unwrap().borrow().end_bi;
-        let left_bi_cnt = bi_lst.len() - last_seg_end_bi.borrow().idx - 1;
-
-        if left_bi_cnt >= 3 {
-            let last_bi = bi_lst.last().unwrap();
-            let dir = if last_bi.borrow().get_end_val() >= last_seg_end_bi.borrow().get_end_val() {
-                BiDir::Up
-            } else {
-                BiDir::Down
-            };
+        let last_bi = bi_lst.last().expect("bi_lst should not be empty").clone();
+        let last_seg_end_bi = self.lst.last().unwrap().borrow().end_bi.clone();
+        if last_seg_end_bi.borrow().idx + 1 >= bi_lst.len() {
+            return Ok(());
+        }
+        if last_seg_end_bi.borrow().is_up() == last_bi.borrow().is_up() {
+            self.add_new_seg(
+                bi_lst,
+                last_bi.borrow().idx - 1,
+                Some(false),
+                None,
+                None,
+                Some("collect_left_1".to_string()),
+            )?;
+        } else {
             self.add_new_seg(
                 bi_lst,
                 last_bi.borrow().idx,
                 Some(false),
-                Some(dir),
                 None,
-                Some("collect_left".to_string()),
+                None,
+                Some("collect_left_0".to_string()),
             )?;
         }
         Ok(())
@@ -390,7 +451,7 @@ where
     }
 
     /// Update segment list
-    pub fn update(&mut self, bi_list: &BiList<T>) -> Result<(), ChanException> {
+    pub fn update(&mut self, bi_lst: &[Handle<T>]) -> Result<(), ChanException> {
         // Clear segments after last sure segment
         if let Some(last_sure_idx) = self.lst.iter().rposition(|seg| seg.borrow().is_sure) {
             self.lst.truncate(last_sure_idx + 1);
@@ -398,46 +459,7 @@ where
             self.lst.clear();
         }
 
-        // If empty, collect first segment
-        if self.lst.is_empty() {
-            self.collect_first_seg(&bi_list.lst)?;
-            return Ok(());
-        }
-
-        // Process remaining segments based on left method
-        let last_seg_end_bi = self.lst.last().unwrap().borrow().end_bi.clone();
-        match self.config.left_method {
-            LeftSegMethod::Peak => {
-                self.collect_left_seg_peak_method(&last_seg_end_bi, &bi_list.lst)?;
-            }
-            LeftSegMethod::All => {
-                if bi_list.lst.len() - last_seg_end_bi.borrow().idx > 3 {
-                    let last_bi = bi_list.lst.last().unwrap();
-                    let dir = if last_bi.borrow().get_end_val()
-                        >= last_seg_end_bi.borrow().get_end_val()
-                    {
-                        BiDir::Up
-                    } else {
-                        BiDir::Down
-                    };
-                    self.add_new_seg(
-                        &bi_list.lst,
-                        last_bi.borrow().idx,
-                        Some(false),
-                        Some(dir),
-                        None,
-                        Some("update_collect_all".to_string()),
-                    )?;
-                }
-            }
-            _ => {
-                return Err(ChanException::new(
-                    format!("unknown seg left_method = {:?}", self.config.left_method),
-                    ErrCode::ParaError,
-                ))
-            }
-        }
-        Ok(())
+        self.collect_left_seg(bi_lst)
     }
 
     /// Get segment direction
@@ -511,3 +533,107 @@ where
         Self::new(None, None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::test_util::{bi_handles, build_bi_lst};
+
+    fn collect(vals: &[f64], left_method: &str) -> Vec<(usize, usize, BiDir, String)> {
+        let (_klus, bis) = build_bi_lst(vals);
+        let bi_lst = bi_handles(&bis);
+        let config = SegConfig::new(None, Some(left_method)).unwrap();
+        let mut seg_list = SegListComm::new(Some(config), None);
+        seg_list.collect_left_seg(&bi_lst).unwrap();
+        seg_list
+            .iter()
+            .map(|seg| {
+                let seg = seg.borrow();
+                (seg.start_bi.borrow().idx, seg.end_bi.borrow().idx, seg.dir, seg.reason.clone())
+            })
+            .collect()
+    }
+
+    const RISING: [f64; 8] = [10.0, 20.0, 15.0, 25.0, 18.0, 30.0, 22.0, 28.0];
+    const FALLING: [f64; 7] = [30.0, 20.0, 25.0, 15.0, 22.0, 10.0, 18.0];
+
+    #[test]
+    fn test_left_seg_all_collects_everything_as_one_seg() {
+        assert_eq!(
+            collect(&RISING, "all"),
+            vec![(0, 6, BiDir::Up, "0seg_collect_all".to_string())]
+        );
+        assert_eq!(
+            collect(&FALLING, "all"),
+            vec![(0, 5, BiDir::Down, "0seg_collect_all".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_left_seg_peak_splits_at_peak_bi() {
+        assert_eq!(
+            collect(&RISING, "peak"),
+            vec![
+                (0, 4, BiDir::Up, "0seg_find_high".to_string()),
+                (5, 5, BiDir::Down, "collect_left_1".to_string()),
+            ]
+        );
+        assert_eq!(
+            collect(&FALLING, "peak"),
+            vec![
+                (0, 4, BiDir::Down, "0seg_find_low".to_string()),
+                (5, 5, BiDir::Up, "collect_left_0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_left_seg_method_too_few_bis() {
+        for left_method in ["all", "peak"] {
+            assert!(collect(&RISING[..3], left_method).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_unknown_left_seg_method() {
+        assert!(SegConfig::new(None, Some("unknown")).is_err());
+    }
+
+    #[test]
+    fn test_swallowed_seg_end_value_err_leaves_list_empty() {
+        // 向上的确定线段终点8低于起点10，第一个线段加入失败
+        let (_klus, bis) = build_bi_lst(&[10.0, 20.0, 5.0, 8.0]);
+        let bi_lst = bi_handles(&bis);
+        let config = SegConfig::new(None, Some("peak")).unwrap();
+        let mut seg_list = SegListComm::new(Some(config), None);
+        let added = seg_list
+            .add_new_seg(&bi_lst, 2, Some(true), None, Some(false), None)
+            .unwrap();
+        assert!(!added);
+        assert!(seg_list.is_empty());
+
+        // 线段列表为空时收集剩余笔直接跳过
+        seg_list.collect_segs(&bi_lst).unwrap();
+        seg_list.collect_left_as_seg(&bi_lst).unwrap();
+        assert!(seg_list.is_empty());
+
+        // 之后仍然按第一个线段重新收集
+        seg_list.collect_left_seg(&bi_lst).unwrap();
+        let seg = seg_list.first().unwrap().borrow();
+        assert_eq!((seg.start_bi.borrow().idx, seg.end_bi.borrow().idx), (0, 0));
+        assert!(!seg.is_sure);
+    }
+
+    #[test]
+    fn test_sure_seg_error_on_non_empty_list_is_raised() {
+        let (_klus, bis) = build_bi_lst(&RISING);
+        let bi_lst = bi_handles(&bis);
+        let mut seg_list = SegListComm::new(None, None);
+        assert!(seg_list.add_new_seg(&bi_lst, 2, Some(false), None, Some(false), None).unwrap());
+        // 第二个线段从bi3起点25指定向上，但bi5终点22更低
+        let err = seg_list
+            .add_new_seg(&bi_lst, 5, Some(true), Some(BiDir::Up), Some(false), None)
+            .unwrap_err();
+        assert_eq!(err.errcode, ErrCode::SegEndValueErr);
+    }
+}
@@ -0,0 +1,894 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::analyzer::Analyzer;
+use crate::bi::bi::Bi;
+use crate::buy_sell_point::bs_point_list::BSPointList;
+use crate::common::cenum::BspType;
+use crate::common::chan_exception::{ChanException, ErrCode};
+use crate::common::time::Time;
+use crate::kline::kline_unit::KLineUnit;
+use crate::seg::seg::Seg;
+use crate::traits::line_trait::LineTrait;
+
+use super::broker::{Broker, Order, OrderSide, OrderStatus, OrderType, SimBroker, SimBrokerConfig};
+use super::optimize::KlData;
+use super::record_store::{signal_key, RecordStore};
+use super::strategy::{BspEvent, Signal, StepContext, Strategy};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BacktestConfig {
+    /// 模拟券商的初始资金、佣金、滑点、每手股数和T+1
+    pub broker: SimBrokerConfig,
+    /// 每次开仓使用的资金比例
+    pub position_rate: f64,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            broker: SimBrokerConfig::default(),
+            position_rate: 1.0,
+        }
+    }
+}
+
+/// 回测用到的K线价格
+#[derive(Debug, Clone, Copy)]
+pub struct Bar {
+    pub idx: usize,
+    pub time: Time,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+impl Bar {
+    pub fn from_klu(idx: usize, klu: &KLineUnit) -> Self {
+        Self {
+            idx,
+            time: klu.time,
+            open: klu.open,
+            high: klu.high,
+            low: klu.low,
+            close: klu.close,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub qty: u64,
+    /// 券商回报的成交价，已含滑点
+    pub entry_price: f64,
+    pub entry_time: Time,
+    pub entry_commission: f64,
+    /// 开仓买卖点的类型，用于分类统计
+    pub bsp_type: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Trade {
+    pub bsp_type: String,
+    pub entry_time: Time,
+    pub exit_time: Time,
+    pub entry_price: f64,
+    pub exit_price: f64,
+    pub qty: u64,
+    /// 扣除双向佣金后的盈亏
+    pub pnl: f64,
+}
+
+/// 单标的多头账户，只按券商的成交回报记账，成交价、佣金、整手和T+1都由券商决定
+#[derive(Debug, Clone)]
+pub struct Portfolio {
+    pub initial_cash: f64,
+    pub cash: f64,
+    pub position: Option<Position>,
+    pub trades: Vec<Trade>,
+}
+
+impl Portfolio {
+    pub fn new(initial_cash: f64) -> Self {
+        Self {
+            initial_cash,
+            cash: initial_cash,
+            position: None,
+            trades: Vec::new(),
+        }
+    }
+
+    /// 记录买单成交，已有持仓时返回RecordAlreadyOpened
+    pub fn on_buy_fill(&mut self, order: &Order, bsp_type: &str) -> Result<(), ChanException> {
+        if self.position.is_some() {
+            return Err(ChanException::new("position already opened", ErrCode::RecordAlreadyOpened));
+        }
+        let (price, time) = fill_of(order)?;
+        self.cash -= price * order.qty as f64 + order.commission;
+        self.position = Some(Position {
+            qty: order.qty,
+            entry_price: price,
+            entry_time: time,
+            entry_commission: order.commission,
+            bsp_type: bsp_type.to_string(),
+        });
+        Ok(())
+    }
+
+    /// 记录卖单成交，没有持仓时返回RecordNotOpened
+    pub fn on_sell_fill(&mut self, order: &Order) -> Result<(), ChanException> {
+        let (price, time) = fill_of(order)?;
+        self.close_position(price, time, order.commission)
+    }
+
+    /// 回测结束时按price估值平仓，不计卖出成本
+    pub fn mark_to_market(&mut self, price: f64, time: Time) -> Result<(), ChanException> {
+        self.close_position(price, time, 0.0)
+    }
+
+    fn close_position(&mut self, price: f64, time: Time, commission: f64) -> Result<(), ChanException> {
+        let position = self
+            .position
+            .take()
+            .ok_or_else(|| ChanException::new("no position to close", ErrCode::RecordNotOpened))?;
+        let amount = price * position.qty as f64;
+        self.cash += amount - commission;
+        self.trades.push(Trade {
+            pnl: amount - commission - position.entry_price * position.qty as f64 - position.entry_commission,
+            bsp_type: position.bsp_type,
+            entry_time: position.entry_time,
+            exit_time: time,
+            entry_price: position.entry_price,
+            exit_price: price,
+            qty: position.qty,
+        });
+        Ok(())
+    }
+
+    /// 按price估算的总资产
+    pub fn equity(&self, price: f64) -> f64 {
+        self.cash + self.position.as_ref().map_or(0.0, |p| p.qty as f64 * price)
+    }
+}
+
+fn fill_of(order: &Order) -> Result<(f64, Time), ChanException> {
+    match (order.status, order.fill_price, order.fill_time) {
+        (OrderStatus::Filled, Some(price), Some(time)) => Ok((price, time)),
+        _ => Err(ChanException::new(
+            format!("order {} is {:?}, not filled", order.id, order.status),
+            ErrCode::CommonError,
+        )),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeStat {
+    pub trade_cnt: usize,
+    pub win_cnt: usize,
+    pub pnl: f64,
+}
+
+impl TypeStat {
+    fn add(&mut self, trade: &Trade) {
+        self.trade_cnt += 1;
+        self.win_cnt += (trade.pnl > 0.0) as usize;
+        self.pnl += trade.pnl;
+    }
+
+    pub fn win_rate(&self) -> f64 {
+        if self.trade_cnt == 0 {
+            0.0
+        } else {
+            self.win_cnt as f64 / self.trade_cnt as f64
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BacktestReport {
+    pub initial_cash: f64,
+    pub final_equity: f64,
+    /// 每根K线收盘时的总资产
+    pub equity_curve: Vec<(Time, f64)>,
+    pub trades: Vec<Trade>,
+    /// 被拒绝的指令，如资金不足一手
+    pub rejected: Vec<(Time, ChanException)>,
+}
+
+impl BacktestReport {
+    pub fn total_return(&self) -> f64 {
+        self.total_return_since(0)
+    }
+
+    pub fn max_drawdown(&self) -> f64 {
+        self.max_drawdown_since(0)
+    }
+
+    /// 从第start根K线开盘到结束的收益，以前一根K线收盘时的总资产为基准
+    ///
+    /// 前面的K线只用于预热Analyzer时，用这一组`*_since`只统计后面的部分
+    pub fn total_return_since(&self, start: usize) -> f64 {
+        let base = match start {
+            0 => self.initial_cash,
+            _ => self.equity_curve.get(start - 1).map_or(self.final_equity, |&(_, equity)| equity),
+        };
+        self.final_equity / base - 1.0
+    }
+
+    pub fn max_drawdown_since(&self, start: usize) -> f64 {
+        let mut peak = f64::NEG_INFINITY;
+        let mut max_dd = 0.0f64;
+        for &(_, equity) in self.equity_curve.iter().skip(start) {
+            peak = peak.max(equity);
+            max_dd = max_dd.max((peak - equity) / peak);
+        }
+        max_dd
+    }
+
+    /// 在第start根K线及之后平仓的交易
+    pub fn trades_since(&self, start: usize) -> &[Trade] {
+        let Some(&(start_time, _)) = self.equity_curve.get(start) else {
+            return &[];
+        };
+        let pos = self
+            .trades
+            .partition_point(|trade| trade.exit_time.timestamp < start_time.timestamp);
+        &self.trades[pos..]
+    }
+
+    pub fn win_rate(&self) -> f64 {
+        let mut stat = TypeStat::default();
+        self.trades.iter().for_each(|trade| stat.add(trade));
+        stat.win_rate()
+    }
+
+    /// 按开仓买卖点类型分类统计
+    pub fn stat_by_bsp_type(&self) -> BTreeMap<String, TypeStat> {
+        let mut res: BTreeMap<String, TypeStat> = BTreeMap::new();
+        for trade in &self.trades {
+            res.entry(trade.bsp_type.clone()).or_default().add(trade);
+        }
+        res
+    }
+}
+
+/// 逐K线驱动Analyzer的事件回测
+///
+/// 每根K线收盘后把新出现的买卖点交给策略，指令在下一根K线开盘时报给券商，默认的SimBroker按开盘价撮合，
+/// 策略只能看到当时已经计算出的状态，不会用到未来数据。同一根K线的多个指令按`Signal::priority`取一个，
+/// T+1下当天不能平仓的卖单被拒后在之后的K线重试。结束时剩余仓位按最后收盘价估值平仓
+pub struct Backtester<S: Strategy> {
+    pub analyzer: Analyzer,
+    pub session: TradeSession<S>,
+}
+
+impl<S: Strategy> Backtester<S> {
+    /// analyzer会被切换为逐K线计算模式
+    pub fn new(mut analyzer: Analyzer, strategy: S, config: BacktestConfig) -> Self {
+        analyzer.step_calculation = true;
+        Self {
+            analyzer,
+            session: TradeSession::new(strategy, config),
+        }
+    }
+
+    /// 把信号和仓位写入指定的记录库
+    pub fn with_record_store(mut self, symbol: &str, record_store: RecordStore) -> Self {
+        self.session = self.session.with_record_store(symbol, record_store);
+        self
+    }
+
+    /// 通过指定的券商下单
+    pub fn with_broker(mut self, broker: Box<dyn Broker + Send>) -> Self {
+        self.session = self.session.with_broker(broker);
+        self
+    }
+
+    /// 第bar_idx根K线之前只预热Analyzer，不交易
+    pub fn with_trade_start(mut self, bar_idx: usize) -> Self {
+        self.session = self.session.with_trade_start(bar_idx);
+        self
+    }
+
+    pub fn step(&mut self, kl_data: &KlData) -> Result<(), ChanException> {
+        let klu = self.analyzer.new_klu(&kl_data.kl_dict, kl_data.time, true)?;
+        let bar = self.session.begin_bar(&klu);
+        self.analyzer.add_single_klu(klu)?;
+        self.session.end_bar(
+            &bar,
+            &self.analyzer,
+            &self.analyzer.bs_point_lst,
+            &self.analyzer.seg_bs_point_lst,
+        );
+        Ok(())
+    }
+
+    pub fn run<'a, I: IntoIterator<Item = &'a KlData>>(mut self, bars: I) -> Result<BacktestReport, ChanException> {
+        for kl_data in bars {
+            self.step(kl_data)?;
+        }
+        Ok(self.finish())
+    }
+
+    pub fn finish(self) -> BacktestReport {
+        self.session.finish()
+    }
+}
+
+/// 回测中与Analyzer无关的部分：策略、券商、账户、待报的指令和记录库
+///
+/// 买卖点列表由外部传入，参数优化时多组买卖点参数可以共用同一个Analyzer的K线合并、笔、线段和中枢
+pub struct TradeSession<S: Strategy> {
+    pub strategy: S,
+    /// 成交价、佣金、整手和T+1都由券商决定，默认为按BacktestConfig配置的SimBroker
+    pub broker: Box<dyn Broker + Send>,
+    pub portfolio: Portfolio,
+    position_rate: f64,
+    /// 从这根K线开始交易，之前的K线只用于预热
+    trade_start: usize,
+    /// 已经回调过的买卖点及其类型
+    seen_bsp: SeenBsp,
+    /// 待报给券商的指令
+    pending: Option<PendingSignal>,
+    /// 已报给券商、还没有结果的订单
+    order: Option<(u64, PendingSignal)>,
+    last_bar: Option<Bar>,
+    /// 记录每个开仓信号的生命周期，可以换成文件存储与模拟盘共用
+    pub record_store: RecordStore,
+    symbol: String,
+    open_record_id: Option<u64>,
+    equity_curve: Vec<(Time, f64)>,
+    rejected: Vec<(Time, ChanException)>,
+}
+
+/// 指令、买卖点类型，开仓指令带上信号在record_store中的id
+type PendingSignal = (Signal, String, Option<u64>);
+
+impl<S: Strategy> TradeSession<S> {
+    pub fn new(strategy: S, config: BacktestConfig) -> Self {
+        Self {
+            strategy,
+            portfolio: Portfolio::new(config.broker.initial_cash),
+            broker: Box::new(SimBroker::new(config.broker)),
+            position_rate: config.position_rate,
+            trade_start: 0,
+            seen_bsp: SeenBsp::new(),
+            pending: None,
+            order: None,
+            last_bar: None,
+            record_store: RecordStore::in_memory(1),
+            symbol: String::new(),
+            open_record_id: None,
+            equity_curve: Vec::new(),
+            rejected: Vec::new(),
+        }
+    }
+
+    pub fn with_record_store(mut self, symbol: &str, record_store: RecordStore) -> Self {
+        self.symbol = symbol.to_string();
+        self.record_store = record_store;
+        self
+    }
+
+    /// 第bar_idx根K线之前只预热：买卖点记为已回调，但不调用策略也不下单，账户保持空仓
+    ///
+    /// walk-forward的测试段从训练段开头开始跑，用它保证测试段的交易都在测试段内开仓
+    pub fn with_trade_start(mut self, bar_idx: usize) -> Self {
+        self.trade_start = bar_idx;
+        self
+    }
+
+    /// 换成其他券商，账户的初始资金仍取BacktestConfig
+    pub fn with_broker(mut self, broker: Box<dyn Broker + Send>) -> Self {
+        self.broker = broker;
+        self
+    }
+
+    /// 新K线开盘，把上一根K线收盘后发出的指令报给券商并推送这根K线撮合，需在klu交给Analyzer之前调用
+    pub fn begin_bar(&mut self, klu: &KLineUnit) -> Bar {
+        let bar = Bar::from_klu(self.last_bar.map_or(0, |bar| bar.idx + 1), klu);
+        self.last_bar = Some(bar);
+        self.place_pending(&bar);
+        self.broker.on_bar(&self.symbol, &bar);
+        self.settle_order(&bar);
+        bar
+    }
+
+    /// K线收盘，Analyzer已经算到这根K线，把新买卖点交给策略
+    pub fn end_bar(
+        &mut self,
+        bar: &Bar,
+        analyzer: &Analyzer,
+        bs_point_lst: &BSPointList<Bi>,
+        seg_bs_point_lst: &BSPointList<Seg<Bi>>,
+    ) {
+        let mut events = new_bsp_events(bs_point_lst, false, &mut self.seen_bsp);
+        events.extend(new_bsp_events(seg_bs_point_lst, true, &mut self.seen_bsp));
+        if bar.idx < self.trade_start {
+            self.equity_curve.push((bar.time, self.portfolio.equity(bar.close)));
+            return;
+        }
+
+        let ctx = StepContext {
+            bar,
+            position: self.portfolio.position.as_ref(),
+            analyzer,
+        };
+        // 多个指令按Signal::priority取一个
+        let mut chosen: (Signal, Option<&BspEvent>) = (Signal::Hold, None);
+        for event in &events {
+            let signal = self.strategy.on_bsp(&ctx, event);
+            if signal.priority() > chosen.0.priority() {
+                chosen = (signal, Some(event));
+            }
+        }
+        // on_bar每根K线都调用
+        let bar_signal = self.strategy.on_bar(&ctx);
+        if bar_signal.priority() > chosen.0.priority() {
+            chosen = (bar_signal, None);
+        }
+        // T+1未能成交的平仓指令继续保留
+        let keep_pending = self
+            .pending
+            .as_ref()
+            .is_some_and(|(signal, ..)| signal.priority() >= chosen.0.priority());
+        if !keep_pending {
+            match chosen {
+                (Signal::Hold, _) => {}
+                (Signal::Open, Some(event)) => {
+                    let bsp_type = event.type_to_string();
+                    let key = signal_key(&event.time, event.is_buy, event.is_segbsp);
+                    // 已记录过的信号不再重复开仓，后来增加的类型合并进原记录
+                    match self.record_store.add_signal(&self.symbol, &key, &bsp_type, event.is_buy, bar.time) {
+                        Ok(id) => self.pending = Some((Signal::Open, bsp_type, Some(id))),
+                        Err(e) => self.rejected.push((bar.time, e)),
+                    }
+                }
+                (signal, event) => {
+                    self.pending = Some((signal, event.map_or(String::new(), BspEvent::type_to_string), None))
+                }
+            }
+        }
+
+        self.equity_curve.push((bar.time, self.portfolio.equity(bar.close)));
+    }
+
+    fn place_pending(&mut self, bar: &Bar) {
+        // 上一个订单还没有结果时先不报
+        if self.order.is_some() {
+            return;
+        }
+        let Some((signal, bsp_type, record_id)) = self.pending.take() else {
+            return;
+        };
+        let res = match signal {
+            Signal::Open => self.place_open(bar),
+            Signal::Close => self.place_close(),
+            Signal::Hold => return,
+        };
+        match res {
+            Ok(order_id) => self.order = Some((order_id, (signal, bsp_type, record_id))),
+            Err(e) => self.reject(bar, e, record_id),
+        }
+    }
+
+    /// 开仓数量按开盘价计算，开盘价在报单时已知，不算未来数据
+    fn place_open(&mut self, bar: &Bar) -> Result<u64, ChanException> {
+        if self.portfolio.position.is_some() {
+            return Err(ChanException::new("position already opened", ErrCode::RecordAlreadyOpened));
+        }
+        let max_qty = self.broker.get_max_buy_qty(&self.symbol, bar.open)?;
+        let lot_size = self.broker.get_lot_size(&self.symbol)?;
+        let qty = (max_qty as f64 * self.position_rate / lot_size as f64).floor() as u64 * lot_size;
+        if qty == 0 {
+            return Err(ChanException::new(
+                format!("cash {:.2} not enough for one lot at {:.4}", self.portfolio.cash, bar.open),
+                ErrCode::QuotaNotEnough,
+            ));
+        }
+        self.broker
+            .place_order(&self.symbol, OrderSide::Buy, OrderType::Market, qty)
+    }
+
+    fn place_close(&mut self) -> Result<u64, ChanException> {
+        let qty = self
+            .portfolio
+            .position
+            .as_ref()
+            .map(|position| position.qty)
+            .ok_or_else(|| ChanException::new("no position to close", ErrCode::RecordNotOpened))?;
+        self.broker
+            .place_order(&self.symbol, OrderSide::Sell, OrderType::Market, qty)
+    }
+
+    /// 按券商的订单状态记账
+    fn settle_order(&mut self, bar: &Bar) {
+        let Some((order_id, (signal, bsp_type, record_id))) = self.order.take() else {
+            return;
+        };
+        let order = match self.broker.list_orders(Some(&self.symbol)) {
+            Ok(orders) => orders.into_iter().find(|order| order.id == order_id),
+            Err(e) => {
+                self.order = Some((order_id, (signal, bsp_type, record_id)));
+                self.rejected.push((bar.time, e));
+                return;
+            }
+        };
+        let Some(order) = order else {
+            let e = ChanException::new(format!("order {} not found", order_id), ErrCode::ListOrderFail);
+            self.reject(bar, e, record_id);
+            return;
+        };
+        match (order.status, signal) {
+            (OrderStatus::Submitted, _) => self.order = Some((order_id, (signal, bsp_type, record_id))),
+            (OrderStatus::Filled, Signal::Open) => {
+                if let Err(e) = self.on_open_fill(&order, &bsp_type, record_id) {
+                    self.rejected.push((bar.time, e));
+                }
+            }
+            (OrderStatus::Filled, _) => {
+                if let Err(e) = self.portfolio.on_sell_fill(&order).and_then(|_| self.close_record(bar)) {
+                    self.rejected.push((bar.time, e));
+                }
+            }
+            // T+1下当天买入的不能卖，卖单被拒后下一根K线重试
+            (OrderStatus::Rejected, Signal::Close) => self.pending = Some((signal, bsp_type, record_id)),
+            (status, _) => {
+                let e = ChanException::new(format!("order {} is {:?}", order_id, status), ErrCode::QuotaNotEnough);
+                self.reject(bar, e, record_id);
+            }
+        }
+    }
+
+    fn on_open_fill(&mut self, order: &Order, bsp_type: &str, record_id: Option<u64>) -> Result<(), ChanException> {
+        self.portfolio.on_buy_fill(order, bsp_type)?;
+        let (Some(id), Some(position)) = (record_id, &self.portfolio.position) else {
+            return Ok(());
+        };
+        self.record_store
+            .open_record(id, position.entry_price, position.qty, position.entry_time)?;
+        self.open_record_id = Some(id);
+        Ok(())
+    }
+
+    /// 指令失败，开仓信号的记录一并取消
+    fn reject(&mut self, bar: &Bar, e: ChanException, record_id: Option<u64>) {
+        self.rejected.push((bar.time, e));
+        if let Some(Err(e)) = record_id.map(|id| self.record_store.cancel_record(id)) {
+            self.rejected.push((bar.time, e));
+        }
+    }
+
+    fn close_record(&mut self, bar: &Bar) -> Result<(), ChanException> {
+        match (self.open_record_id.take(), self.portfolio.trades.last()) {
+            (Some(id), Some(trade)) => self.record_store.close_record(id, trade.exit_price, bar.time),
+            _ => Ok(()),
+        }
+    }
+
+    /// 剩余仓位按最后收盘价估值平仓并生成报告
+    pub fn finish(mut self) -> BacktestReport {
+        if let (Some(_), Some(bar)) = (&self.portfolio.position, self.last_bar) {
+            self.portfolio.mark_to_market(bar.close, bar.time).expect("position checked above");
+            if let Err(e) = self.close_record(&bar) {
+                self.rejected.push((bar.time, e));
+            }
+        }
+        BacktestReport {
+            initial_cash: self.portfolio.initial_cash,
+            final_equity: self.portfolio.cash,
+            equity_curve: self.equity_curve,
+            trades: self.portfolio.trades,
+            rejected: self.rejected,
+        }
+    }
+}
+
+/// 已经回调过的买卖点，(KLU序号, 是否买点, 是否线段买卖点) -> 已回调过的类型
+type SeenBsp = HashMap<(usize, bool, bool), Vec<BspType>>;
+
+/// 新出现的买卖点，以及已回调过但增加了新类型的买卖点
+fn new_bsp_events<T: LineTrait>(bsp_list: &BSPointList<T>, is_segbsp: bool, seen_bsp: &mut SeenBsp) -> Vec<BspEvent> {
+    bsp_list
+        .iter()
+        .filter_map(|bsp| {
+            let bsp = bsp.borrow();
+            let seen = seen_bsp.entry((bsp.klu.index(), bsp.is_buy, is_segbsp)).or_default();
+            let new_types: Vec<BspType> = bsp.bs_type.iter().filter(|t| !seen.contains(t)).copied().collect();
+            if new_types.is_empty() {
+                return None;
+            }
+            seen.extend(new_types);
+            Some(BspEvent::from_bsp(&bsp, is_segbsp))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::cenum::DataField;
+    use crate::config::chan_config::ChanConfig;
+    use crate::common::test_util::sine_kl_data;
+
+    fn day(d: i64, h: i64) -> Time {
+        Time::new(1_704_067_200 + d * 86_400 + h * 3_600)
+    }
+
+    fn config() -> BacktestConfig {
+        BacktestConfig {
+            broker: SimBrokerConfig {
+                initial_cash: 10_000.0,
+                commission_rate: 0.001,
+                min_commission: 5.0,
+                slippage_rate: 0.01,
+                default_lot_size: Some(100),
+                lot_sizes: HashMap::new(),
+                t_plus_one: true,
+            },
+            position_rate: 1.0,
+        }
+    }
+
+    fn filled(side: OrderSide, qty: u64, price: f64, commission: f64, time: Time) -> Order {
+        Order {
+            id: 0,
+            symbol: String::new(),
+            side,
+            order_type: OrderType::Market,
+            qty,
+            status: OrderStatus::Filled,
+            create_time: time,
+            fill_time: Some(time),
+            fill_price: Some(price),
+            commission,
+        }
+    }
+
+    #[test]
+    fn test_portfolio_fills() {
+        let mut portfolio = Portfolio::new(10_000.0);
+        portfolio
+            .on_buy_fill(&filled(OrderSide::Buy, 900, 10.1, 9.09, day(0, 10)), "BS1")
+            .unwrap();
+        assert!((portfolio.cash - (10_000.0 - 9090.0 - 9.09)).abs() < 1e-9);
+        assert!((portfolio.equity(11.0) - (10_000.0 - 9090.0 - 9.09 + 9900.0)).abs() < 1e-9);
+
+        portfolio
+            .on_sell_fill(&filled(OrderSide::Sell, 900, 11.88, 10.692, day(1, 10)))
+            .unwrap();
+        let trade = &portfolio.trades[0];
+        let sell_amount = 11.88 * 900.0;
+        assert!((trade.pnl - (sell_amount - 10.692 - 9090.0 - 9.09)).abs() < 1e-9);
+        assert!((portfolio.cash - (10_000.0 + trade.pnl)).abs() < 1e-9);
+        assert_eq!(trade.bsp_type, "BS1");
+    }
+
+    #[test]
+    fn test_portfolio_errors() {
+        let mut portfolio = Portfolio::new(10_000.0);
+        let sell = filled(OrderSide::Sell, 100, 10.0, 0.0, day(0, 10));
+        assert_eq!(portfolio.on_sell_fill(&sell).unwrap_err().errcode, ErrCode::RecordNotOpened);
+        let buy = filled(OrderSide::Buy, 100, 10.0, 0.0, day(0, 10));
+        portfolio.on_buy_fill(&buy, "BS1").unwrap();
+        assert_eq!(portfolio.on_buy_fill(&buy, "BS1").unwrap_err().errcode, ErrCode::RecordAlreadyOpened);
+        let rejected = Order { status: OrderStatus::Rejected, ..sell };
+        assert_eq!(portfolio.on_sell_fill(&rejected).unwrap_err().errcode, ErrCode::CommonError);
+
+        portfolio.mark_to_market(11.0, day(1, 10)).unwrap();
+        assert!((portfolio.trades[0].pnl - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_step_costs_from_broker() {
+        let bars = [kl_data(day(0, 10), 10.0, 10.0), kl_data(day(1, 10), 10.0, 11.0), kl_data(day(2, 10), 12.0, 12.0)];
+        let strategy = ScriptStrategy {
+            bar_signals: HashMap::from([(0, Signal::Open), (1, Signal::Close)]),
+            ..Default::default()
+        };
+        let report = backtester(strategy, config()).run(&bars).unwrap();
+        let trade = &report.trades[0];
+        // 成交价、整手和佣金都来自SimBroker
+        assert_eq!(trade.qty, 900);
+        assert!((trade.entry_price - 10.1).abs() < 1e-12);
+        assert!((trade.exit_price - 11.88).abs() < 1e-12);
+        let sell_amount = 11.88 * 900.0;
+        assert!((trade.pnl - (sell_amount - sell_amount * 0.001 - 9090.0 - 9.09)).abs() < 1e-9);
+        assert!((report.final_equity - (10_000.0 + trade.pnl)).abs() < 1e-9);
+
+        // 资金不足一手时开仓被拒
+        let strategy = ScriptStrategy {
+            bar_signals: HashMap::from([(0, Signal::Open)]),
+            ..Default::default()
+        };
+        let bars = [kl_data(day(0, 10), 200.0, 200.0), kl_data(day(1, 10), 200.0, 200.0)];
+        let report = backtester(strategy, config()).run(&bars).unwrap();
+        assert!(report.trades.is_empty());
+        assert_eq!(report.rejected[0].1.errcode, ErrCode::QuotaNotEnough);
+    }
+
+    #[test]
+    fn test_report_stats() {
+        let trade = |bsp_type: &str, pnl: f64| Trade {
+            bsp_type: bsp_type.to_string(),
+            entry_time: day(0, 0),
+            exit_time: day(1, 0),
+            entry_price: 1.0,
+            exit_price: 1.0,
+            qty: 100,
+            pnl,
+        };
+        let report = BacktestReport {
+            initial_cash: 100.0,
+            final_equity: 110.0,
+            equity_curve: [100.0, 120.0, 90.0, 110.0].iter().enumerate().map(|(i, &v)| (day(i as i64, 0), v)).collect(),
+            trades: vec![trade("BS1", 5.0), trade("BS1", -2.0), trade("BS2", 7.0)],
+            rejected: Vec::new(),
+        };
+        assert!((report.total_return() - 0.1).abs() < 1e-12);
+        assert!((report.max_drawdown() - 0.25).abs() < 1e-12);
+        assert!((report.total_return_since(2) - (110.0 / 120.0 - 1.0)).abs() < 1e-12);
+        assert!((report.max_drawdown_since(2) - 0.0).abs() < 1e-12);
+        assert_eq!(report.trades_since(1).len(), 3);
+        assert!(report.trades_since(2).is_empty());
+        assert!((report.win_rate() - 2.0 / 3.0).abs() < 1e-12);
+        let by_type = report.stat_by_bsp_type();
+        assert_eq!(by_type["BS1"], TypeStat { trade_cnt: 2, win_cnt: 1, pnl: 3.0 });
+        assert_eq!(by_type["BS2"].win_rate(), 1.0);
+    }
+
+    fn kl_data(time: Time, open: f64, close: f64) -> KlData {
+        KlData {
+            time,
+            kl_dict: HashMap::from([
+                (DataField::FieldOpen, open),
+                (DataField::FieldHigh, open.max(close) + 0.1),
+                (DataField::FieldLow, open.min(close) - 0.1),
+                (DataField::FieldClose, close),
+            ]),
+        }
+    }
+
+    /// 按K线序号发出指令，并记录每一步看到的状态
+    #[derive(Default)]
+    struct ScriptStrategy {
+        bar_signals: HashMap<usize, Signal>,
+        bsp_signal: Option<Signal>,
+        /// (K线序号, Analyzer中的KLU数, 收盘价, 是否持仓)
+        steps: Vec<(usize, usize, f64, bool)>,
+        /// (K线序号, 买卖点)
+        events: Vec<(usize, BspEvent)>,
+    }
+
+    impl Strategy for ScriptStrategy {
+        fn on_bsp(&mut self, ctx: &StepContext, bsp: &BspEvent) -> Signal {
+            self.events.push((ctx.bar.idx, bsp.clone()));
+            self.bsp_signal.unwrap_or(Signal::Hold)
+        }
+
+        fn on_bar(&mut self, ctx: &StepContext) -> Signal {
+            self.steps
+                .push((ctx.bar.idx, ctx.analyzer.klu_lst.len(), ctx.bar.close, ctx.position.is_some()));
+            self.bar_signals.get(&ctx.bar.idx).copied().unwrap_or(Signal::Hold)
+        }
+    }
+
+    fn backtester(strategy: ScriptStrategy, config: BacktestConfig) -> Backtester<ScriptStrategy> {
+        let analyzer = Analyzer::new("test".to_string(), ChanConfig::new(None).unwrap()).unwrap();
+        Backtester::new(analyzer, strategy, config)
+    }
+
+    fn no_cost_config() -> BacktestConfig {
+        let mut config = config();
+        config.broker.commission_rate = 0.0;
+        config.broker.min_commission = 0.0;
+        config.broker.slippage_rate = 0.0;
+        config
+    }
+
+    #[test]
+    fn test_step_fills_at_next_open() {
+        let bars: Vec<KlData> = (0..5).map(|i| kl_data(day(i, 10), 10.0 + i as f64, 10.5 + i as f64)).collect();
+        let strategy = ScriptStrategy {
+            bar_signals: HashMap::from([(1, Signal::Open), (3, Signal::Close)]),
+            ..Default::default()
+        };
+        let mut bt = backtester(strategy, no_cost_config());
+        bt.step(&bars[0]).unwrap();
+        bt.step(&bars[1]).unwrap();
+        // 第1根收盘发出的开仓指令还没有成交
+        assert!(bt.session.portfolio.position.is_none());
+        bt.step(&bars[2]).unwrap();
+        let position = bt.session.portfolio.position.as_ref().unwrap();
+        assert_eq!(position.entry_price, 12.0);
+        assert_eq!(position.entry_time, day(2, 10));
+        bt.step(&bars[3]).unwrap();
+        bt.step(&bars[4]).unwrap();
+        assert!(bt.session.portfolio.position.is_none());
+
+        let steps: Vec<bool> = bt.session.strategy.steps.iter().map(|step| step.3).collect();
+        assert_eq!(steps, vec![false, false, true, true, false]);
+        let report = bt.finish();
+        assert_eq!(report.trades.len(), 1);
+        assert_eq!(report.trades[0].exit_price, 14.0);
+        assert_eq!(report.trades[0].exit_time, day(4, 10));
+        // 第2根收盘时持仓按收盘价估值
+        assert!((report.equity_curve[2].1 - (10_000.0 - 12.0 * 800.0 + 12.5 * 800.0)).abs() < 1e-9);
+        assert!(report.rejected.is_empty());
+    }
+
+    #[test]
+    fn test_step_no_look_ahead() {
+        let bars = sine_kl_data(300);
+        let mut bt = backtester(ScriptStrategy::default(), no_cost_config());
+        for kl_data in &bars {
+            bt.step(kl_data).unwrap();
+        }
+        let strategy = &bt.session.strategy;
+        assert_eq!(strategy.steps.len(), bars.len());
+        for &(idx, klu_cnt, close, _) in &strategy.steps {
+            // Analyzer只算到当前K线
+            assert_eq!(klu_cnt, idx + 1);
+            assert_eq!(close, bars[idx].kl_dict[&DataField::FieldClose]);
+        }
+        assert!(!strategy.events.is_empty());
+        let mut seen: HashMap<(usize, bool, bool), Vec<BspType>> = HashMap::new();
+        for (idx, event) in &strategy.events {
+            assert!(event.klu_idx <= *idx);
+            assert!(event.time.timestamp <= bars[*idx].time.timestamp);
+            // 同一个买卖点只有增加了类型才会再次回调
+            let types = seen.entry((event.klu_idx, event.is_buy, event.is_segbsp)).or_default();
+            assert!(event.bs_type.iter().any(|t| !types.contains(t)));
+            types.extend(event.bs_type.iter().copied());
+        }
+    }
+
+    #[test]
+    fn test_step_retries_close_after_t_plus_one() {
+        let times = [day(0, 10), day(0, 11), day(0, 14), day(1, 10), day(1, 11)];
+        let bars: Vec<KlData> = times.iter().enumerate().map(|(i, &t)| kl_data(t, 10.0 + i as f64, 10.0)).collect();
+        let strategy = ScriptStrategy {
+            // 第2根的开仓指令与保留的平仓指令冲突，平仓优先
+            bar_signals: HashMap::from([(0, Signal::Open), (1, Signal::Close), (2, Signal::Open)]),
+            ..Default::default()
+        };
+        let mut bt = backtester(strategy, no_cost_config());
+        for kl_data in &bars[..3] {
+            bt.step(kl_data).unwrap();
+        }
+        // 第1根开仓，第2根当天不能平仓
+        assert!(bt.session.portfolio.position.is_some());
+        bt.step(&bars[3]).unwrap();
+        assert!(bt.session.portfolio.position.is_none());
+        bt.step(&bars[4]).unwrap();
+
+        let report = bt.finish();
+        assert_eq!(report.trades.len(), 1);
+        assert_eq!(report.trades[0].entry_time, day(0, 11));
+        assert_eq!(report.trades[0].exit_time, day(1, 10));
+        assert_eq!(report.trades[0].exit_price, 13.0);
+        assert!(report.rejected.is_empty());
+    }
+
+    #[test]
+    fn test_signal_precedence() {
+        assert!(Signal::Close.priority() > Signal::Open.priority());
+        assert!(Signal::Open.priority() > Signal::Hold.priority());
+
+        // 每个买卖点都发开仓，每根K线on_bar都发平仓，平仓优先所以从不开仓
+        let strategy = ScriptStrategy {
+            bar_signals: (0..300).map(|idx| (idx, Signal::Close)).collect(),
+            bsp_signal: Some(Signal::Open),
+            ..Default::default()
+        };
+        let report = backtester(strategy, no_cost_config()).run(&sine_kl_data(300)).unwrap();
+        assert!(report.trades.is_empty());
+        assert!(report
+            .rejected
+            .iter()
+            .all(|(_, e)| e.errcode == ErrCode::RecordNotOpened));
+    }
+}
@@ -0,0 +1,437 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::chan_exception::{ChanException, ErrCode};
+use crate::common::time::Time;
+
+use super::backtest::Bar;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OrderType {
+    Market,
+    Limit(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    Submitted,
+    Filled,
+    Canceled,
+    /// 成交时资金或持仓不足
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Order {
+    pub id: u64,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub qty: u64,
+    pub status: OrderStatus,
+    pub create_time: Time,
+    pub fill_time: Option<Time>,
+    pub fill_price: Option<f64>,
+    pub commission: f64,
+}
+
+/// 券商接口，策略只依赖这个trait，实盘和模拟盘可以互换
+pub trait Broker {
+    /// 下单，返回订单id，失败返回PlaceOrderFail
+    fn place_order(&mut self, symbol: &str, side: OrderSide, order_type: OrderType, qty: u64)
+        -> Result<u64, ChanException>;
+
+    /// 列出订单，symbol为None时返回全部，失败返回ListOrderFail
+    fn list_orders(&self, symbol: Option<&str>) -> Result<Vec<Order>, ChanException>;
+
+    /// 撤单，订单不存在或已经结束时返回CandelOrderFail
+    fn cancel_order(&mut self, order_id: u64) -> Result<(), ChanException>;
+
+    /// 最新价，失败返回GetFutuPriceFail
+    fn get_price(&self, symbol: &str) -> Result<f64, ChanException>;
+
+    /// 每手股数，失败返回GetFutuLotSizeFail
+    fn get_lot_size(&self, symbol: &str) -> Result<u64, ChanException>;
+
+    /// 可卖的持仓数量，失败返回GetHoldingQtyFail
+    fn get_holding_qty(&self, symbol: &str) -> Result<u64, ChanException>;
+
+    /// 现有资金以price买入市价单最多能买的数量，已按整手取整并扣除佣金和滑点，失败返回PlaceOrderFail
+    fn get_max_buy_qty(&self, symbol: &str, price: f64) -> Result<u64, ChanException>;
+
+    /// 推送symbol的新K线，返回本根K线成交或被拒的订单；
+    /// 模拟券商在这里撮合挂单，实盘由交易所撮合，默认什么都不做，订单状态用`list_orders`查询
+    fn on_bar(&mut self, _symbol: &str, _bar: &Bar) -> Vec<Order> {
+        Vec::new()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimBrokerConfig {
+    pub initial_cash: f64,
+    pub commission_rate: f64,
+    pub min_commission: f64,
+    /// 市价单的滑点比例
+    pub slippage_rate: f64,
+    /// 未单独配置的标的使用的每手股数，None时这些标的取不到lot size
+    pub default_lot_size: Option<u64>,
+    pub lot_sizes: HashMap<String, u64>,
+    /// 当天买入的数量当天不可卖
+    pub t_plus_one: bool,
+}
+
+impl Default for SimBrokerConfig {
+    fn default() -> Self {
+        Self {
+            initial_cash: 1_000_000.0,
+            commission_rate: 0.0003,
+            min_commission: 5.0,
+            slippage_rate: 0.001,
+            default_lot_size: Some(100),
+            lot_sizes: HashMap::new(),
+            t_plus_one: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Holding {
+    qty: u64,
+    /// 今天买入、T+1下暂不可卖的数量
+    frozen_qty: u64,
+}
+
+/// 本地模拟券商：订单挂起，在`on_bar`收到该标的的下一根K线时撮合
+///
+/// 市价单按开盘价加滑点成交；限价买单在最低价不高于限价时按min(开盘价, 限价)成交，
+/// 限价卖单同理。成交时资金或可卖持仓不足则订单被拒
+#[derive(Debug, Clone)]
+pub struct SimBroker {
+    pub config: SimBrokerConfig,
+    pub cash: f64,
+    holdings: HashMap<String, Holding>,
+    orders: BTreeMap<u64, Order>,
+    last_bars: HashMap<String, Bar>,
+    next_id: u64,
+}
+
+impl SimBroker {
+    pub fn new(config: SimBrokerConfig) -> Self {
+        Self {
+            cash: config.initial_cash,
+            config,
+            holdings: HashMap::new(),
+            orders: BTreeMap::new(),
+            last_bars: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    fn commission(&self, amount: f64) -> f64 {
+        (amount * self.config.commission_rate).max(self.config.min_commission)
+    }
+
+    fn match_order(&mut self, id: u64, bar: &Bar) -> Option<Order> {
+        let order = self.orders[&id].clone();
+        let price = match (order.side, order.order_type) {
+            (OrderSide::Buy, OrderType::Market) => bar.open * (1.0 + self.config.slippage_rate),
+            (OrderSide::Sell, OrderType::Market) => bar.open * (1.0 - self.config.slippage_rate),
+            (OrderSide::Buy, OrderType::Limit(limit)) if bar.low <= limit => bar.open.min(limit),
+            (OrderSide::Sell, OrderType::Limit(limit)) if bar.high >= limit => bar.open.max(limit),
+            _ => return None,
+        };
+        let amount = price * order.qty as f64;
+        let commission = self.commission(amount);
+        let holding = self.holdings.entry(order.symbol.clone()).or_default();
+        let status = match order.side {
+            OrderSide::Buy if amount + commission <= self.cash => {
+                self.cash -= amount + commission;
+                holding.qty += order.qty;
+                if self.config.t_plus_one {
+                    holding.frozen_qty += order.qty;
+                }
+                OrderStatus::Filled
+            }
+            OrderSide::Sell if order.qty <= holding.qty - holding.frozen_qty => {
+                self.cash += amount - commission;
+                holding.qty -= order.qty;
+                OrderStatus::Filled
+            }
+            _ => OrderStatus::Rejected,
+        };
+        let order = self.orders.get_mut(&id).unwrap();
+        order.status = status;
+        if status == OrderStatus::Filled {
+            order.fill_time = Some(bar.time);
+            order.fill_price = Some(price);
+            order.commission = commission;
+        }
+        Some(order.clone())
+    }
+
+    /// 按各标的最新价估算的总资产
+    pub fn equity(&self) -> f64 {
+        self.cash
+            + self
+                .holdings
+                .iter()
+                .map(|(symbol, holding)| {
+                    holding.qty as f64 * self.last_bars.get(symbol).map_or(0.0, |bar| bar.close)
+                })
+                .sum::<f64>()
+    }
+}
+
+impl Broker for SimBroker {
+    fn place_order(
+        &mut self,
+        symbol: &str,
+        side: OrderSide,
+        order_type: OrderType,
+        qty: u64,
+    ) -> Result<u64, ChanException> {
+        let create_time = self
+            .last_bars
+            .get(symbol)
+            .map(|bar| bar.time)
+            .ok_or_else(|| ChanException::new(format!("no quote for {}", symbol), ErrCode::PlaceOrderFail))?;
+        let lot_size = self
+            .get_lot_size(symbol)
+            .map_err(|e| ChanException::new(e.msg, ErrCode::PlaceOrderFail))?;
+        if qty == 0 || !qty.is_multiple_of(lot_size) {
+            return Err(ChanException::new(
+                format!("qty {} is not a multiple of lot size {}", qty, lot_size),
+                ErrCode::PlaceOrderFail,
+            ));
+        }
+        if let OrderType::Limit(limit) = order_type {
+            if limit <= 0.0 {
+                return Err(ChanException::new(format!("invalid limit price {}", limit), ErrCode::PlaceOrderFail));
+            }
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.orders.insert(
+            id,
+            Order {
+                id,
+                symbol: symbol.to_string(),
+                side,
+                order_type,
+                qty,
+                status: OrderStatus::Submitted,
+                create_time,
+                fill_time: None,
+                fill_price: None,
+                commission: 0.0,
+            },
+        );
+        Ok(id)
+    }
+
+    fn list_orders(&self, symbol: Option<&str>) -> Result<Vec<Order>, ChanException> {
+        Ok(self
+            .orders
+            .values()
+            .filter(|order| symbol.is_none_or(|symbol| order.symbol == symbol))
+            .cloned()
+            .collect())
+    }
+
+    fn cancel_order(&mut self, order_id: u64) -> Result<(), ChanException> {
+        let order = self
+            .orders
+            .get_mut(&order_id)
+            .ok_or_else(|| ChanException::new(format!("order {} not exist", order_id), ErrCode::CandelOrderFail))?;
+        if order.status != OrderStatus::Submitted {
+            return Err(ChanException::new(
+                format!("order {} is {:?}, can not cancel", order_id, order.status),
+                ErrCode::CandelOrderFail,
+            ));
+        }
+        order.status = OrderStatus::Canceled;
+        Ok(())
+    }
+
+    fn get_price(&self, symbol: &str) -> Result<f64, ChanException> {
+        self.last_bars
+            .get(symbol)
+            .map(|bar| bar.close)
+            .ok_or_else(|| ChanException::new(format!("no quote for {}", symbol), ErrCode::GetFutuPriceFail))
+    }
+
+    fn get_lot_size(&self, symbol: &str) -> Result<u64, ChanException> {
+        self.config
+            .lot_sizes
+            .get(symbol)
+            .copied()
+            .or(self.config.default_lot_size)
+            .ok_or_else(|| ChanException::new(format!("lot size of {} unknown", symbol), ErrCode::GetFutuLotSizeFail))
+    }
+
+    fn get_holding_qty(&self, symbol: &str) -> Result<u64, ChanException> {
+        if !self.last_bars.contains_key(symbol) {
+            return Err(ChanException::new(format!("unknown symbol {}", symbol), ErrCode::GetHoldingQtyFail));
+        }
+        Ok(self
+            .holdings
+            .get(symbol)
+            .map_or(0, |holding| holding.qty - holding.frozen_qty))
+    }
+
+    fn get_max_buy_qty(&self, symbol: &str, price: f64) -> Result<u64, ChanException> {
+        let lot_size = self
+            .get_lot_size(symbol)
+            .map_err(|e| ChanException::new(e.msg, ErrCode::PlaceOrderFail))?;
+        let price = price * (1.0 + self.config.slippage_rate);
+        let lot_cost = price * lot_size as f64 * (1.0 + self.config.commission_rate);
+        let mut qty = (self.cash / lot_cost).floor() as u64 * lot_size;
+        // 最低佣金可能让刚好够的一手变得不够
+        while qty > 0 && price * qty as f64 + self.commission(price * qty as f64) > self.cash {
+            qty -= lot_size;
+        }
+        Ok(qty)
+    }
+
+    fn on_bar(&mut self, symbol: &str, bar: &Bar) -> Vec<Order> {
+        let new_day = self
+            .last_bars
+            .get(symbol)
+            .is_none_or(|last| last.time.date() != bar.time.date());
+        if new_day {
+            if let Some(holding) = self.holdings.get_mut(symbol) {
+                holding.frozen_qty = 0;
+            }
+        }
+        self.last_bars.insert(symbol.to_string(), *bar);
+
+        let ids: Vec<u64> = self
+            .orders
+            .values()
+            .filter(|order| order.symbol == symbol && order.status == OrderStatus::Submitted)
+            .map(|order| order.id)
+            .collect();
+        ids.into_iter().filter_map(|id| self.match_order(id, bar)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYMBOL: &str = "sz.000001";
+
+    fn bar(idx: usize, day: i64, open: f64, high: f64, low: f64, close: f64) -> Bar {
+        Bar {
+            idx,
+            time: Time::new(1_704_067_200 + day * 86_400 + idx as i64 * 60),
+            open,
+            high,
+            low,
+            close,
+        }
+    }
+
+    fn broker() -> SimBroker {
+        SimBroker::new(SimBrokerConfig {
+            initial_cash: 10_000.0,
+            commission_rate: 0.0,
+            min_commission: 0.0,
+            slippage_rate: 0.01,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_market_order_and_t_plus_one() {
+        let mut broker = broker();
+        broker.on_bar(SYMBOL, &bar(0, 0, 10.0, 10.2, 9.8, 10.0));
+        let id = broker.place_order(SYMBOL, OrderSide::Buy, OrderType::Market, 500).unwrap();
+
+        let fills = broker.on_bar(SYMBOL, &bar(1, 0, 10.0, 10.5, 9.9, 10.4));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].id, id);
+        assert!((fills[0].fill_price.unwrap() - 10.1).abs() < 1e-12);
+        assert!((broker.cash - (10_000.0 - 5050.0)).abs() < 1e-9);
+        // 当天买入不可卖
+        assert_eq!(broker.get_holding_qty(SYMBOL).unwrap(), 0);
+        let sell = broker.place_order(SYMBOL, OrderSide::Sell, OrderType::Market, 500).unwrap();
+        broker.on_bar(SYMBOL, &bar(2, 0, 10.4, 10.5, 10.3, 10.4));
+        assert_eq!(broker.list_orders(None).unwrap()[sell as usize].status, OrderStatus::Rejected);
+
+        broker.on_bar(SYMBOL, &bar(3, 1, 10.6, 10.8, 10.5, 10.7));
+        assert_eq!(broker.get_holding_qty(SYMBOL).unwrap(), 500);
+        assert!((broker.equity() - (4950.0 + 5350.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_max_buy_qty() {
+        let mut broker = SimBroker::new(SimBrokerConfig {
+            initial_cash: 10_000.0,
+            commission_rate: 0.001,
+            min_commission: 5.0,
+            slippage_rate: 0.01,
+            ..Default::default()
+        });
+        // 10.1的买入价，10000资金最多900股
+        assert_eq!(broker.get_max_buy_qty(SYMBOL, 10.0).unwrap(), 900);
+        assert_eq!(broker.get_max_buy_qty(SYMBOL, 200.0).unwrap(), 0);
+        // 900股刚好够，加上最低佣金就不够了
+        broker.cash = 9090.0 + 4.0;
+        broker.config.commission_rate = 0.0;
+        assert_eq!(broker.get_max_buy_qty(SYMBOL, 10.0).unwrap(), 800);
+
+        broker.on_bar(SYMBOL, &bar(0, 0, 10.0, 10.2, 9.8, 10.0));
+        broker.cash = 10_000.0;
+        broker.config.commission_rate = 0.001;
+        let qty = broker.get_max_buy_qty(SYMBOL, 10.0).unwrap();
+        broker.place_order(SYMBOL, OrderSide::Buy, OrderType::Market, qty).unwrap();
+        let fills = broker.on_bar(SYMBOL, &bar(1, 0, 10.0, 10.2, 9.8, 10.0));
+        assert_eq!(fills[0].status, OrderStatus::Filled);
+        assert!((broker.cash - (10_000.0 - 9090.0 - 9.09)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_limit_order_and_cancel() {
+        let mut broker = broker();
+        broker.on_bar(SYMBOL, &bar(0, 0, 10.0, 10.2, 9.8, 10.0));
+        let id = broker.place_order(SYMBOL, OrderSide::Buy, OrderType::Limit(9.5), 100).unwrap();
+        assert!(broker.on_bar(SYMBOL, &bar(1, 0, 10.0, 10.1, 9.6, 9.7)).is_empty());
+        let fills = broker.on_bar(SYMBOL, &bar(2, 0, 9.7, 9.8, 9.4, 9.5));
+        assert_eq!(fills[0].fill_price, Some(9.5));
+        assert_eq!(broker.cancel_order(id).unwrap_err().errcode, ErrCode::CandelOrderFail);
+
+        let id = broker.place_order(SYMBOL, OrderSide::Buy, OrderType::Limit(9.0), 100).unwrap();
+        broker.cancel_order(id).unwrap();
+        assert!(broker.on_bar(SYMBOL, &bar(3, 0, 8.8, 9.0, 8.7, 8.9)).is_empty());
+        assert_eq!(broker.list_orders(Some(SYMBOL)).unwrap().len(), 2);
+        assert!(broker.list_orders(Some("sz.000002")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut broker = broker();
+        let err = broker.place_order(SYMBOL, OrderSide::Buy, OrderType::Market, 100).unwrap_err();
+        assert_eq!(err.errcode, ErrCode::PlaceOrderFail);
+        assert_eq!(broker.get_price(SYMBOL).unwrap_err().errcode, ErrCode::GetFutuPriceFail);
+        assert_eq!(broker.get_holding_qty(SYMBOL).unwrap_err().errcode, ErrCode::GetHoldingQtyFail);
+
+        broker.on_bar(SYMBOL, &bar(0, 0, 10.0, 10.2, 9.8, 10.0));
+        let err = broker.place_order(SYMBOL, OrderSide::Buy, OrderType::Market, 150).unwrap_err();
+        assert_eq!(err.errcode, ErrCode::PlaceOrderFail);
+        assert_eq!(broker.cancel_order(42).unwrap_err().errcode, ErrCode::CandelOrderFail);
+
+        broker.config.default_lot_size = None;
+        assert_eq!(broker.get_lot_size(SYMBOL).unwrap_err().errcode, ErrCode::GetFutuLotSizeFail);
+        broker.config.lot_sizes.insert(SYMBOL.to_string(), 200);
+        assert_eq!(broker.get_lot_size(SYMBOL).unwrap(), 200);
+    }
+}
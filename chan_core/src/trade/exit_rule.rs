@@ -0,0 +1,492 @@
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::analyzer::Analyzer;
+use crate::common::enums::BiDir;
+use crate::traits::line_trait::LineTrait;
+
+use super::backtest::{Bar, Position};
+use super::strategy::{BspEvent, Signal, StepContext, Strategy};
+
+/// 出场规则每根K线能看到的状态
+pub struct ExitContext<'a> {
+    pub bar: &'a Bar,
+    pub position: &'a Position,
+    /// 开仓时的买卖点
+    pub entry: &'a BspEvent,
+    /// 本根K线新出现的买卖点
+    pub new_bsps: &'a [BspEvent],
+    pub analyzer: &'a Analyzer,
+}
+
+/// 基于缠论结构的出场规则，返回Some(原因)表示出场
+///
+/// 规则在K线收盘后按这根K线的价格判断，触发后的平仓指令在下一根K线开盘价成交，
+/// 所以止损、止盈价只是触发条件，跳空时实际成交价可能更差（或更好）
+pub trait ExitRule: Send {
+    fn name(&self) -> &str;
+
+    /// 发出开仓指令时调用，用于初始化止损位等状态，analyzer为当时的状态
+    fn on_open(&mut self, _entry: &BspEvent, _analyzer: &Analyzer) {}
+
+    fn check(&mut self, ctx: &ExitContext) -> Option<String>;
+}
+
+/// 出场规则配置，放在ChanConfig同一个文件的`exit_rules`中，如
+/// `[{"type": "bsp_bi_stop", "buffer_rate": 0.01}, {"type": "opposite_bsp"}]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExitRuleConfig {
+    BspBiStop {
+        #[serde(default)]
+        buffer_rate: f64,
+    },
+    OppositeBsp {
+        #[serde(default = "default_true")]
+        same_level: bool,
+    },
+    TrailingBiStop,
+    ZsBreak,
+    SegReverse,
+    TakeProfit {
+        rate: f64,
+    },
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ExitRuleConfig {
+    pub fn build(&self) -> Box<dyn ExitRule> {
+        match *self {
+            ExitRuleConfig::BspBiStop { buffer_rate } => Box::new(BspBiStop::new(buffer_rate)),
+            ExitRuleConfig::OppositeBsp { same_level } => Box::new(OppositeBspExit { same_level }),
+            ExitRuleConfig::TrailingBiStop => Box::new(TrailingBiStop::default()),
+            ExitRuleConfig::ZsBreak => Box::new(ZsBreakExit::default()),
+            ExitRuleConfig::SegReverse => Box::new(SegReverseExit),
+            ExitRuleConfig::TakeProfit { rate } => Box::new(TakeProfit { rate }),
+        }
+    }
+}
+
+/// 止损在开仓买卖点所在笔的终点之下，如一买那一笔的最低点
+#[derive(Debug, Clone)]
+pub struct BspBiStop {
+    pub buffer_rate: f64,
+    stop: f64,
+}
+
+impl BspBiStop {
+    pub fn new(buffer_rate: f64) -> Self {
+        Self {
+            buffer_rate,
+            stop: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl ExitRule for BspBiStop {
+    fn name(&self) -> &str {
+        "bsp_bi_stop"
+    }
+
+    fn on_open(&mut self, entry: &BspEvent, _analyzer: &Analyzer) {
+        self.stop = entry.bi_end_val * (1.0 - self.buffer_rate);
+    }
+
+    fn check(&mut self, ctx: &ExitContext) -> Option<String> {
+        (ctx.bar.low <= self.stop).then(|| format!("low {} below bsp bi stop {}", ctx.bar.low, self.stop))
+    }
+}
+
+/// 出现卖点时离场，same_level时只看与开仓买卖点同级别（笔/线段）的卖点
+#[derive(Debug, Clone)]
+pub struct OppositeBspExit {
+    pub same_level: bool,
+}
+
+impl ExitRule for OppositeBspExit {
+    fn name(&self) -> &str {
+        "opposite_bsp"
+    }
+
+    fn check(&mut self, ctx: &ExitContext) -> Option<String> {
+        ctx.new_bsps
+            .iter()
+            .find(|bsp| !bsp.is_buy && (!self.same_level || bsp.is_segbsp == ctx.entry.is_segbsp))
+            .map(|bsp| format!("sell bsp {} appeared", bsp.type_to_string()))
+    }
+}
+
+/// 跟踪止损，止损位为开仓买卖点之后最后一个已确定向下笔的终点，只上移不下移
+#[derive(Debug, Clone)]
+pub struct TrailingBiStop {
+    stop: f64,
+}
+
+impl Default for TrailingBiStop {
+    fn default() -> Self {
+        Self { stop: f64::NEG_INFINITY }
+    }
+}
+
+impl ExitRule for TrailingBiStop {
+    fn name(&self) -> &str {
+        "trailing_bi_stop"
+    }
+
+    fn on_open(&mut self, entry: &BspEvent, _analyzer: &Analyzer) {
+        self.stop = entry.bi_end_val;
+    }
+
+    fn check(&mut self, ctx: &ExitContext) -> Option<String> {
+        let last_sure_down = ctx
+            .analyzer
+            .bi_list
+            .bi_list
+            .iter()
+            .rev()
+            .find(|bi| bi.is_sure() && bi.is_down() && bi.idx() > ctx.entry.bi_idx);
+        if let Some(bi) = last_sure_down {
+            self.stop = self.stop.max(bi.get_end_val());
+        }
+        (ctx.bar.close < self.stop).then(|| format!("close {} below trailing bi stop {}", ctx.bar.close, self.stop))
+    }
+}
+
+/// 收盘跌破开仓时所在笔中枢的下沿，即开仓买卖点之前最后一个笔中枢，之后新出现的中枢不影响
+#[derive(Debug, Clone, Default)]
+pub struct ZsBreakExit {
+    zs_low: Option<f64>,
+}
+
+impl ExitRule for ZsBreakExit {
+    fn name(&self) -> &str {
+        "zs_break"
+    }
+
+    fn on_open(&mut self, entry: &BspEvent, analyzer: &Analyzer) {
+        self.zs_low = analyzer
+            .zs_list
+            .iter()
+            .rev()
+            .find(|zs| zs.begin.as_ref().is_some_and(|klu| klu.index() <= entry.klu_idx))
+            .and_then(|zs| zs.low);
+    }
+
+    fn check(&mut self, ctx: &ExitContext) -> Option<String> {
+        let low = self.zs_low?;
+        (ctx.bar.close < low).then(|| format!("close {} below zs low {}", ctx.bar.close, low))
+    }
+}
+
+/// 开仓买卖点所在笔之后出现已确定的向下线段
+#[derive(Debug, Clone)]
+pub struct SegReverseExit;
+
+impl ExitRule for SegReverseExit {
+    fn name(&self) -> &str {
+        "seg_reverse"
+    }
+
+    fn check(&mut self, ctx: &ExitContext) -> Option<String> {
+        let seg = ctx.analyzer.seg_list.iter().rev().find(|seg| seg.borrow().is_sure)?;
+        let seg = seg.borrow();
+        (seg.dir == BiDir::Down && seg.start_bi.borrow().idx() >= ctx.entry.bi_idx)
+            .then(|| format!("down seg {} confirmed", seg.index()))
+    }
+}
+
+/// 按开仓价的固定比例止盈
+#[derive(Debug, Clone)]
+pub struct TakeProfit {
+    pub rate: f64,
+}
+
+impl ExitRule for TakeProfit {
+    fn name(&self) -> &str {
+        "take_profit"
+    }
+
+    fn check(&mut self, ctx: &ExitContext) -> Option<String> {
+        let target = ctx.position.entry_price * (1.0 + self.rate);
+        (ctx.bar.high >= target).then(|| format!("high {} reached target {}", ctx.bar.high, target))
+    }
+}
+
+/// 一组出场规则，任意一条触发即离场
+#[derive(Default)]
+pub struct ExitRuleSet {
+    pub rules: Vec<Box<dyn ExitRule>>,
+}
+
+impl ExitRuleSet {
+    pub fn from_config(configs: &[ExitRuleConfig]) -> Self {
+        Self {
+            rules: configs.iter().map(ExitRuleConfig::build).collect(),
+        }
+    }
+
+    pub fn add(mut self, rule: Box<dyn ExitRule>) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn on_open(&mut self, entry: &BspEvent, analyzer: &Analyzer) {
+        self.rules.iter_mut().for_each(|rule| rule.on_open(entry, analyzer));
+    }
+
+    /// 返回第一条触发的规则名和原因
+    pub fn check(&mut self, ctx: &ExitContext) -> Option<(String, String)> {
+        self.rules
+            .iter_mut()
+            .find_map(|rule| rule.check(ctx).map(|reason| (rule.name().to_string(), reason)))
+    }
+}
+
+/// 给任意策略加上出场规则：开仓仍由内部策略决定，持仓期间每根K线检查规则
+pub struct WithExitRules<S: Strategy> {
+    pub inner: S,
+    pub rules: ExitRuleSet,
+    entry: Option<BspEvent>,
+    new_bsps: Vec<BspEvent>,
+}
+
+impl<S: Strategy> WithExitRules<S> {
+    pub fn new(inner: S, rules: ExitRuleSet) -> Self {
+        Self {
+            inner,
+            rules,
+            entry: None,
+            new_bsps: Vec::new(),
+        }
+    }
+}
+
+impl<S: Strategy> Strategy for WithExitRules<S> {
+    fn on_bsp(&mut self, ctx: &StepContext, bsp: &BspEvent) -> Signal {
+        self.new_bsps.push(bsp.clone());
+        let signal = self.inner.on_bsp(ctx, bsp);
+        if signal == Signal::Open {
+            self.rules.on_open(bsp, ctx.analyzer);
+            self.entry = Some(bsp.clone());
+        }
+        signal
+    }
+
+    fn on_bar(&mut self, ctx: &StepContext) -> Signal {
+        let new_bsps = std::mem::take(&mut self.new_bsps);
+        let (Some(position), Some(entry)) = (ctx.position, &self.entry) else {
+            return self.inner.on_bar(ctx);
+        };
+        let exit_ctx = ExitContext {
+            bar: ctx.bar,
+            position,
+            entry,
+            new_bsps: &new_bsps,
+            analyzer: ctx.analyzer,
+        };
+        if self.rules.check(&exit_ctx).is_some() {
+            return Signal::Close;
+        }
+        self.inner.on_bar(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::cenum::BspType;
+    use crate::common::test_util::sine_kl_data;
+    use crate::common::time::Time;
+    use crate::config::chan_config::ChanConfig;
+
+    fn analyzer() -> Analyzer {
+        let mut analyzer = Analyzer::new("sine".to_string(), ChanConfig::new(None).unwrap()).unwrap();
+        for kl_data in sine_kl_data(300) {
+            analyzer.add_kl_data(&kl_data.kl_dict, kl_data.time, true).unwrap();
+        }
+        analyzer
+    }
+
+    fn bar(low: f64, high: f64, close: f64) -> Bar {
+        Bar {
+            idx: 0,
+            time: Time::new(1_704_067_200),
+            open: close,
+            high,
+            low,
+            close,
+        }
+    }
+
+    fn event(is_buy: bool, is_segbsp: bool, klu_idx: usize, bi_idx: usize, bi_end_val: f64) -> BspEvent {
+        BspEvent {
+            bs_type: vec![BspType::T1],
+            is_buy,
+            is_segbsp,
+            score: None,
+            klu_idx,
+            time: Time::new(1_704_067_200),
+            bi_idx,
+            line_idx: bi_idx,
+            bi_begin_val: bi_end_val + 1.0,
+            bi_end_val,
+        }
+    }
+
+    fn position(entry_price: f64) -> Position {
+        Position {
+            qty: 100,
+            entry_price,
+            entry_time: Time::new(1_704_067_200),
+            entry_commission: 0.0,
+            bsp_type: "1".to_string(),
+        }
+    }
+
+    /// 开仓后检查一根K线
+    fn fire(
+        rule: &mut dyn ExitRule,
+        analyzer: &Analyzer,
+        entry: &BspEvent,
+        bar: Bar,
+        new_bsps: &[BspEvent],
+    ) -> Option<String> {
+        rule.on_open(entry, analyzer);
+        rule.check(&ExitContext {
+            bar: &bar,
+            position: &position(10.0),
+            entry,
+            new_bsps,
+            analyzer,
+        })
+    }
+
+    #[test]
+    fn test_bsp_bi_stop() {
+        let analyzer = analyzer();
+        let entry = event(true, false, 10, 2, 10.0);
+        let mut rule = BspBiStop::new(0.01);
+        assert!(fire(&mut rule, &analyzer, &entry, bar(9.95, 10.5, 10.2), &[]).is_none());
+        assert!(fire(&mut rule, &analyzer, &entry, bar(9.9, 10.5, 10.2), &[]).is_some());
+    }
+
+    #[test]
+    fn test_opposite_bsp() {
+        let analyzer = analyzer();
+        let entry = event(true, false, 10, 2, 10.0);
+        let seg_sell = [event(false, true, 20, 4, 12.0)];
+        let bi_sell = [event(false, false, 20, 4, 12.0)];
+        let buy = [event(true, false, 20, 4, 12.0)];
+        let mut same_level = OppositeBspExit { same_level: true };
+        assert!(fire(&mut same_level, &analyzer, &entry, bar(10.0, 10.5, 10.2), &buy).is_none());
+        assert!(fire(&mut same_level, &analyzer, &entry, bar(10.0, 10.5, 10.2), &seg_sell).is_none());
+        assert!(fire(&mut same_level, &analyzer, &entry, bar(10.0, 10.5, 10.2), &bi_sell).is_some());
+        let mut any_level = OppositeBspExit { same_level: false };
+        assert!(fire(&mut any_level, &analyzer, &entry, bar(10.0, 10.5, 10.2), &seg_sell).is_some());
+    }
+
+    #[test]
+    fn test_trailing_bi_stop() {
+        let analyzer = analyzer();
+        let bis = &analyzer.bi_list.bi_list;
+        let first_down = bis.iter().find(|bi| bi.is_sure() && bi.is_down()).unwrap();
+        let entry = event(true, false, first_down.get_end_klu().index(), first_down.idx(), first_down.get_end_val());
+        // 止损上移到之后最后一个已确定向下笔的终点
+        let stop = bis
+            .iter()
+            .rev()
+            .find(|bi| bi.is_sure() && bi.is_down() && bi.idx() > first_down.idx())
+            .unwrap()
+            .get_end_val()
+            .max(first_down.get_end_val());
+        let mut rule = TrailingBiStop::default();
+        assert!(fire(&mut rule, &analyzer, &entry, bar(stop, stop + 1.0, stop + 0.01), &[]).is_none());
+        assert!(fire(&mut rule, &analyzer, &entry, bar(stop - 1.0, stop, stop - 0.01), &[]).is_some());
+
+        // 线段买卖点的bi_idx是线段最后一笔，之后没有向下笔时止损不动
+        let last = bis.last().unwrap();
+        let entry = event(true, true, last.get_end_klu().index(), last.idx(), 5.0);
+        let mut rule = TrailingBiStop::default();
+        assert!(fire(&mut rule, &analyzer, &entry, bar(5.0, 6.0, 5.5), &[]).is_none());
+        assert!(fire(&mut rule, &analyzer, &entry, bar(4.0, 5.0, 4.9), &[]).is_some());
+    }
+
+    #[test]
+    fn test_zs_break_anchors_entry_zs() {
+        let analyzer = analyzer();
+        let zs_lst: Vec<_> = analyzer.zs_list.iter().collect();
+        assert!(zs_lst.len() >= 2);
+        // 开仓在第一个中枢之后、第二个中枢之前
+        let first = zs_lst[0];
+        let entry_klu = zs_lst[1].begin.as_ref().unwrap().index() - 1;
+        let entry = event(true, false, entry_klu, 0, 10.0);
+        let low = first.low.unwrap();
+        let mut rule = ZsBreakExit::default();
+        assert!(fire(&mut rule, &analyzer, &entry, bar(low, low + 1.0, low + 0.01), &[]).is_none());
+        assert!(fire(&mut rule, &analyzer, &entry, bar(low - 1.0, low, low - 0.01), &[]).is_some());
+
+        // 开仓之前没有中枢时不触发
+        let entry = event(true, false, 0, 0, 10.0);
+        assert!(fire(&mut rule, &analyzer, &entry, bar(0.0, 1.0, 0.5), &[]).is_none());
+    }
+
+    #[test]
+    fn test_seg_reverse() {
+        // 逐K线计算，停在最后一个已确定线段向下的时候
+        let mut analyzer = Analyzer::new("sine".to_string(), ChanConfig::new(None).unwrap()).unwrap();
+        analyzer.step_calculation = true;
+        let last_sure_down = |analyzer: &Analyzer| {
+            let seg = analyzer.seg_list.iter().rev().find(|seg| seg.borrow().is_sure)?;
+            let seg = seg.borrow();
+            (seg.dir == BiDir::Down).then(|| seg.start_bi.borrow().idx())
+        };
+        let mut start_bi_idx = None;
+        for kl_data in sine_kl_data(300) {
+            analyzer.add_kl_data(&kl_data.kl_dict, kl_data.time, true).unwrap();
+            start_bi_idx = last_sure_down(&analyzer);
+            if start_bi_idx.is_some() {
+                break;
+            }
+        }
+        let start_bi_idx = start_bi_idx.unwrap();
+
+        let mut rule = SegReverseExit;
+        let entry = event(true, false, 0, start_bi_idx, 10.0);
+        assert!(fire(&mut rule, &analyzer, &entry, bar(9.0, 11.0, 10.0), &[]).is_some());
+        // 向下线段在开仓买卖点之前就开始了
+        let entry = event(true, true, 0, start_bi_idx + 1, 10.0);
+        assert!(fire(&mut rule, &analyzer, &entry, bar(9.0, 11.0, 10.0), &[]).is_none());
+    }
+
+    #[test]
+    fn test_take_profit() {
+        let analyzer = analyzer();
+        let entry = event(true, false, 10, 2, 9.0);
+        let mut rule = TakeProfit { rate: 0.2 };
+        assert!(fire(&mut rule, &analyzer, &entry, bar(10.0, 11.99, 11.5), &[]).is_none());
+        assert!(fire(&mut rule, &analyzer, &entry, bar(10.0, 12.0, 11.5), &[]).is_some());
+    }
+
+    #[test]
+    fn test_rule_config() {
+        let configs: Vec<ExitRuleConfig> = serde_json::from_str(
+            r#"[
+                {"type": "bsp_bi_stop", "buffer_rate": 0.01},
+                {"type": "opposite_bsp"},
+                {"type": "trailing_bi_stop"},
+                {"type": "take_profit", "rate": 0.2}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(configs[0], ExitRuleConfig::BspBiStop { buffer_rate: 0.01 });
+        assert_eq!(configs[1], ExitRuleConfig::OppositeBsp { same_level: true });
+
+        let rule_set = ExitRuleSet::from_config(&configs);
+        let names: Vec<&str> = rule_set.rules.iter().map(|rule| rule.name()).collect();
+        assert_eq!(names, ["bsp_bi_stop", "opposite_bsp", "trailing_bi_stop", "take_profit"]);
+
+        assert!(serde_json::from_str::<ExitRuleConfig>(r#"{"type": "take_profit"}"#).is_err());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod backtest;
+pub mod broker;
+pub mod exit_rule;
+pub mod optimize;
+pub mod record_store;
+pub mod strategy;
@@ -0,0 +1,641 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::analyzer::analyzer::Analyzer;
+use crate::bi::bi::Bi;
+use crate::buy_sell_point::bs_point_list::BSPointList;
+use crate::common::cenum::DataField;
+use crate::common::chan_exception::{ChanException, ErrCode};
+use crate::common::time::Time;
+use crate::config::chan_config::ChanConfig;
+use crate::seg::seg::Seg;
+
+use super::backtest::{BacktestConfig, BacktestReport, TradeSession};
+use super::strategy::Strategy;
+
+/// 一组被搜索的参数取值，只含参数空间里的key
+pub type ParamSet = BTreeMap<String, Value>;
+
+/// 参数空间，json格式`{"base": {"bi_strict": true}, "params": {"divergence_rate": [0.8, 0.9, 1.0]}}`
+///
+/// base是所有组合共用的ChanConfig参数，params的每个key给出候选值
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParamSpace {
+    #[serde(default)]
+    pub base: HashMap<String, Value>,
+    pub params: BTreeMap<String, Vec<Value>>,
+}
+
+impl ParamSpace {
+    /// 网格中的组合数
+    pub fn size(&self) -> usize {
+        self.params.values().map(Vec::len).product()
+    }
+
+    /// 全部组合，最后一个参数变化最快
+    pub fn grid(&self) -> Vec<ParamSet> {
+        let mut res = vec![ParamSet::new()];
+        for (key, values) in &self.params {
+            res = res
+                .into_iter()
+                .flat_map(|set| {
+                    values.iter().map(move |value| {
+                        let mut set = set.clone();
+                        set.insert(key.clone(), value.clone());
+                        set
+                    })
+                })
+                .collect();
+        }
+        res
+    }
+
+    /// 不重复地随机抽取cnt个组合，cnt不小于网格大小时等同于grid
+    pub fn random(&self, cnt: usize, seed: u64) -> Vec<ParamSet> {
+        let size = self.size();
+        if cnt >= size {
+            return self.grid();
+        }
+        let mut rng = SplitMix64(seed);
+        let mut picked = HashSet::new();
+        let mut res = Vec::with_capacity(cnt);
+        while res.len() < cnt {
+            let set: ParamSet = self
+                .params
+                .iter()
+                .map(|(key, values)| (key.clone(), values[rng.next_below(values.len())].clone()))
+                .collect();
+            if picked.insert(param_set_to_string(&set)) {
+                res.push(set);
+            }
+        }
+        res
+    }
+
+    /// base和param_set合并后生成ChanConfig
+    pub fn build_config(&self, param_set: &ParamSet) -> Result<ChanConfig, ChanException> {
+        let mut conf = self.base.clone();
+        conf.extend(param_set.iter().map(|(k, v)| (k.clone(), v.clone())));
+        ChanConfig::new(Some(conf))
+    }
+}
+
+pub fn param_set_to_string(param_set: &ParamSet) -> String {
+    serde_json::to_string(param_set).unwrap_or_default()
+}
+
+/// 只有买卖点参数和出场规则不同的组合结构key相同，可以共用K线合并、笔、线段和中枢
+fn structure_key(param_set: &ParamSet) -> String {
+    let structure: ParamSet = param_set
+        .iter()
+        .filter(|(k, _)| !ChanConfig::is_bsp_para(k) && k.as_str() != "exit_rules")
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    param_set_to_string(&structure)
+}
+
+/// 随机抽样用的SplitMix64，不需要密码学强度，只要给定seed可复现
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn next_below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// 滚动的训练/测试划分，单位为K线根数
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WalkForward {
+    pub train_bars: usize,
+    pub test_bars: usize,
+    /// 每次向后滚动的K线数，默认等于test_bars，测试段首尾相接
+    pub step_bars: usize,
+    /// 训练段固定从第0根开始，逐步变长
+    pub anchored: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fold {
+    pub train: Range<usize>,
+    pub test: Range<usize>,
+}
+
+impl WalkForward {
+    pub fn new(train_bars: usize, test_bars: usize) -> Self {
+        Self {
+            train_bars,
+            test_bars,
+            step_bars: test_bars,
+            anchored: false,
+        }
+    }
+
+    /// 最后一个测试段可能不足test_bars
+    pub fn splits(&self, total_bars: usize) -> Result<Vec<Fold>, ChanException> {
+        if self.train_bars == 0 || self.test_bars == 0 || self.step_bars == 0 {
+            return Err(ChanException::new(
+                format!("walk forward bars must be positive: {:?}", self),
+                ErrCode::ParaError,
+            ));
+        }
+        let mut folds = Vec::new();
+        let mut start = 0;
+        while start + self.train_bars < total_bars {
+            let test_begin = start + self.train_bars;
+            folds.push(Fold {
+                train: if self.anchored { 0..test_begin } else { start..test_begin },
+                test: test_begin..(test_begin + self.test_bars).min(total_bars),
+            });
+            start += self.step_bars;
+        }
+        if folds.is_empty() {
+            return Err(ChanException::new(
+                format!("{} bars not enough for {} train bars", total_bars, self.train_bars),
+                ErrCode::ParaError,
+            ));
+        }
+        Ok(folds)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RankBy {
+    TotalReturn,
+    WinRate,
+    /// 收益/最大回撤
+    ReturnDrawdown,
+}
+
+impl std::str::FromStr for RankBy {
+    type Err = ChanException;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "return" | "total_return" => Ok(Self::TotalReturn),
+            "win_rate" => Ok(Self::WinRate),
+            "return_drawdown" | "calmar" => Ok(Self::ReturnDrawdown),
+            _ => Err(ChanException::new(format!("unknown rank metric: {}", s), ErrCode::ParaError)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SweepMetrics {
+    pub total_return: f64,
+    pub max_drawdown: f64,
+    pub win_rate: f64,
+    pub trade_cnt: usize,
+}
+
+impl SweepMetrics {
+    /// 只统计第start根K线之后的部分，之前的K线用于预热
+    pub fn from_report(report: &BacktestReport, start: usize) -> Self {
+        let trades = report.trades_since(start);
+        let win_cnt = trades.iter().filter(|trade| trade.pnl > 0.0).count();
+        Self {
+            total_return: report.total_return_since(start),
+            max_drawdown: report.max_drawdown_since(start),
+            win_rate: if trades.is_empty() { 0.0 } else { win_cnt as f64 / trades.len() as f64 },
+            trade_cnt: trades.len(),
+        }
+    }
+
+    pub fn score(&self, rank_by: RankBy) -> f64 {
+        match rank_by {
+            RankBy::TotalReturn => self.total_return,
+            RankBy::WinRate => self.win_rate,
+            RankBy::ReturnDrawdown => self.total_return / self.max_drawdown.max(f64::EPSILON),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SweepResult {
+    pub params: ParamSet,
+    /// 参数非法或计算出错时为Err，排在最后
+    pub metrics: Result<SweepMetrics, ChanException>,
+}
+
+/// 按rank_by从高到低排序，出错的组合排在最后
+pub fn rank_results(results: &mut [SweepResult], rank_by: RankBy) {
+    let score = |res: &SweepResult| res.metrics.as_ref().map_or(f64::NEG_INFINITY, |m| m.score(rank_by));
+    results.sort_by(|a, b| score(b).total_cmp(&score(a)));
+}
+
+/// 排名表，每行为排名、各项指标和参数
+pub fn format_results(results: &[SweepResult], top: usize) -> String {
+    let mut lines = vec!["rank\ttotal_return\tmax_drawdown\twin_rate\ttrade_cnt\tparams".to_string()];
+    for (rank, res) in results.iter().take(top).enumerate() {
+        let params = param_set_to_string(&res.params);
+        lines.push(match &res.metrics {
+            Ok(m) => format!(
+                "{}\t{:.4}\t{:.4}\t{:.4}\t{}\t{}",
+                rank + 1,
+                m.total_return,
+                m.max_drawdown,
+                m.win_rate,
+                m.trade_cnt,
+                params
+            ),
+            Err(e) => format!("{}\tERR\tERR\tERR\t0\t{}\t{}", rank + 1, params, e.msg),
+        });
+    }
+    lines.join("\n")
+}
+
+#[derive(Debug)]
+pub struct FoldResult {
+    pub fold: Fold,
+    /// 训练段上排名第一的组合，全部出错时为None
+    pub best: Option<SweepResult>,
+    /// best在测试段上的表现
+    pub test: Option<SweepResult>,
+}
+
+/// 预先读入的一根K线，所有组合共用，回测时再各自生成KLineUnit
+#[derive(Debug, Clone)]
+pub struct KlData {
+    pub time: Time,
+    pub kl_dict: HashMap<DataField, f64>,
+}
+
+/// 参数搜索：每个组合在同一份K线上跑一遍逐K线回测，多线程并行
+///
+/// 结构参数相同的组合放在同一个任务里，共用一个Analyzer做K线合并、笔、线段和中枢，
+/// 每个组合只各自计算买卖点和回测账户
+pub struct Optimizer<'a, S: Strategy, F: Fn(&ChanConfig) -> S + Sync> {
+    pub symbol: String,
+    pub bars: &'a [KlData],
+    pub space: ParamSpace,
+    pub backtest_config: BacktestConfig,
+    /// 根据每个组合的ChanConfig生成策略，如按exit_rules加上出场规则
+    pub strategy_factory: F,
+    pub rank_by: RankBy,
+    pub threads: usize,
+}
+
+impl<'a, S: Strategy, F: Fn(&ChanConfig) -> S + Sync> Optimizer<'a, S, F> {
+    pub fn new(
+        symbol: &str,
+        bars: &'a [KlData],
+        space: ParamSpace,
+        backtest_config: BacktestConfig,
+        strategy_factory: F,
+    ) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            bars,
+            space,
+            backtest_config,
+            strategy_factory,
+            rank_by: RankBy::TotalReturn,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    /// 在全部K线上评估每个组合，返回排好序的结果
+    pub fn sweep(&self, param_sets: &[ParamSet]) -> Vec<SweepResult> {
+        self.sweep_range(param_sets, 0..self.bars.len(), 0)
+    }
+
+    /// 每个划分先在训练段上搜索，再用第一名在测试段上评估
+    ///
+    /// 测试段的回测从训练段开头开始跑，Analyzer有足够的K线预热，
+    /// 但只从测试段第一根K线开始交易，训练段内不会开仓，指标只统计测试段
+    pub fn walk_forward(
+        &self,
+        param_sets: &[ParamSet],
+        walk_forward: &WalkForward,
+    ) -> Result<Vec<FoldResult>, ChanException> {
+        let mut res = Vec::new();
+        for fold in walk_forward.splits(self.bars.len())? {
+            let best = self
+                .sweep_range(param_sets, fold.train.clone(), fold.train.start)
+                .into_iter()
+                .next()
+                .filter(|best| best.metrics.is_ok());
+            let test = best.as_ref().and_then(|best| {
+                self.sweep_range(std::slice::from_ref(&best.params), fold.train.start..fold.test.end, fold.test.start)
+                    .into_iter()
+                    .next()
+            });
+            res.push(FoldResult { fold, best, test });
+        }
+        Ok(res)
+    }
+
+    /// 在bars[range]上回测，从第metric_start根K线开始交易并统计指标，之前的K线只用于预热
+    fn sweep_range(&self, param_sets: &[ParamSet], range: Range<usize>, metric_start: usize) -> Vec<SweepResult> {
+        // 同一结构的组合太多时拆成几个任务，保证线程都能用上
+        let chunk_size = param_sets.len().div_ceil(self.threads.max(1)).max(1);
+        let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (idx, param_set) in param_sets.iter().enumerate() {
+            groups.entry(structure_key(param_set)).or_default().push(idx);
+        }
+        let jobs: Vec<&[usize]> = groups.values().flat_map(|idxs| idxs.chunks(chunk_size)).collect();
+
+        let metrics = par_map(&jobs, self.threads, |job| {
+            let sets: Vec<&ParamSet> = job.iter().map(|&idx| &param_sets[idx]).collect();
+            job.iter()
+                .copied()
+                .zip(self.run_group(&sets, range.clone(), metric_start - range.start))
+                .collect::<Vec<_>>()
+        });
+        let mut by_idx: BTreeMap<usize, Result<SweepMetrics, ChanException>> = metrics.into_iter().flatten().collect();
+        let mut results: Vec<SweepResult> = param_sets
+            .iter()
+            .enumerate()
+            .map(|(idx, params)| SweepResult {
+                params: params.clone(),
+                metrics: by_idx.remove(&idx).expect("every param set belongs to one job"),
+            })
+            .collect();
+        rank_results(&mut results, self.rank_by);
+        results
+    }
+
+    /// 一组结构参数相同的组合共用一个Analyzer，返回顺序与param_sets一致
+    fn run_group(
+        &self,
+        param_sets: &[&ParamSet],
+        range: Range<usize>,
+        metric_start: usize,
+    ) -> Vec<Result<SweepMetrics, ChanException>> {
+        let configs: Vec<Result<ChanConfig, ChanException>> =
+            param_sets.iter().map(|set| self.space.build_config(set)).collect();
+        let Some(shared_conf) = configs.iter().find_map(|conf| conf.as_ref().ok()) else {
+            return configs.into_iter().map(|conf| conf.map(|_| SweepMetrics::default())).collect();
+        };
+        let mut variants: Vec<Option<BspVariant<S>>> = configs
+            .iter()
+            .map(|conf| {
+                conf.as_ref()
+                    .ok()
+                    .map(|conf| BspVariant::new(conf, &self.strategy_factory, &self.backtest_config, metric_start))
+            })
+            .collect();
+
+        match self.run_variants(shared_conf, &mut variants, range) {
+            Ok(()) => configs
+                .into_iter()
+                .zip(variants)
+                .map(|(conf, variant)| {
+                    conf?;
+                    let report = variant.expect("variant exists for valid config").session.finish();
+                    Ok(SweepMetrics::from_report(&report, metric_start))
+                })
+                .collect(),
+            Err(e) => configs
+                .into_iter()
+                .map(|conf| {
+                    conf?;
+                    Err(ChanException::new(e.msg.clone(), e.errcode))
+                })
+                .collect(),
+        }
+    }
+
+    fn run_variants(
+        &self,
+        shared_conf: &ChanConfig,
+        variants: &mut [Option<BspVariant<S>>],
+        range: Range<usize>,
+    ) -> Result<(), ChanException> {
+        let mut analyzer = Analyzer::new(self.symbol.clone(), shared_conf.clone())?;
+        analyzer.step_calculation = true;
+        // 买卖点由每个组合各自计算
+        analyzer.cal_bsp = false;
+        let mut structure_version = analyzer.structure_version;
+        for kl_data in &self.bars[range] {
+            let klu = analyzer.new_klu(&kl_data.kl_dict, kl_data.time, true)?;
+            let bars: Vec<_> = variants
+                .iter_mut()
+                .map(|variant| variant.as_mut().map(|variant| variant.session.begin_bar(&klu)))
+                .collect();
+            analyzer.add_single_klu(klu)?;
+            let recal = analyzer.structure_version != structure_version;
+            structure_version = analyzer.structure_version;
+            for (variant, bar) in variants.iter_mut().zip(bars) {
+                let (Some(variant), Some(bar)) = (variant, bar) else {
+                    continue;
+                };
+                if recal {
+                    analyzer.cal_bsp_variant(&mut variant.bs_point_lst, &mut variant.seg_bs_point_lst)?;
+                }
+                variant
+                    .session
+                    .end_bar(&bar, &analyzer, &variant.bs_point_lst, &variant.seg_bs_point_lst);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 共用结构时每个组合各自的买卖点和回测账户
+struct BspVariant<S: Strategy> {
+    bs_point_lst: BSPointList<Bi>,
+    seg_bs_point_lst: BSPointList<Seg<Bi>>,
+    session: TradeSession<S>,
+}
+
+impl<S: Strategy> BspVariant<S> {
+    fn new<F: Fn(&ChanConfig) -> S>(
+        conf: &ChanConfig,
+        strategy_factory: &F,
+        backtest_config: &BacktestConfig,
+        trade_start: usize,
+    ) -> Self {
+        Self {
+            bs_point_lst: BSPointList::new(conf.bs_point_conf.clone()),
+            seg_bs_point_lst: BSPointList::new_seg(conf.seg_bs_point_conf.clone()),
+            session: TradeSession::new(strategy_factory(conf), backtest_config.clone()).with_trade_start(trade_start),
+        }
+    }
+}
+
+/// 用threads个线程依次领取任务，结果顺序与jobs一致
+pub fn par_map<J: Sync, T: Send>(jobs: &[J], threads: usize, f: impl Fn(&J) -> T + Sync) -> Vec<T> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<T>>> = Mutex::new((0..jobs.len()).map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, jobs.len().max(1)) {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(idx) else {
+                    break;
+                };
+                let res = f(job);
+                results.lock().unwrap()[idx] = Some(res);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|res| res.expect("every job finished"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::test_util::sine_kl_data;
+    use crate::trade::backtest::Backtester;
+    use crate::trade::strategy::BspStrategy;
+    use serde_json::json;
+
+    fn space() -> ParamSpace {
+        serde_json::from_value(json!({
+            "base": {"bi_strict": true},
+            "params": {
+                "bi_strict": [true, false],
+                "divergence_rate": [0.8, 0.9, 1.0],
+                "min_zs_cnt-buy": [0, 1],
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_grid_and_random() {
+        let space = space();
+        let grid = space.grid();
+        assert_eq!(space.size(), 12);
+        assert_eq!(grid.len(), 12);
+        assert_eq!(param_set_to_string(&grid[1]), r#"{"bi_strict":true,"divergence_rate":0.8,"min_zs_cnt-buy":1}"#);
+
+        let sampled = space.random(5, 7);
+        assert_eq!(sampled.len(), 5);
+        let distinct: HashSet<String> = sampled.iter().map(param_set_to_string).collect();
+        assert_eq!(distinct.len(), 5);
+        assert_eq!(space.random(5, 7), sampled);
+        assert_eq!(space.random(100, 7).len(), 12);
+
+        // 只有bi_strict影响K线合并之后的结构
+        let keys: HashSet<String> = grid.iter().map(structure_key).collect();
+        assert_eq!(keys.len(), 2);
+    }
+
+    #[test]
+    fn test_walk_forward_splits() {
+        let folds = WalkForward::new(100, 30).splits(200).unwrap();
+        assert_eq!(
+            folds,
+            vec![
+                Fold { train: 0..100, test: 100..130 },
+                Fold { train: 30..130, test: 130..160 },
+                Fold { train: 60..160, test: 160..190 },
+                Fold { train: 90..190, test: 190..200 },
+            ]
+        );
+        let anchored = WalkForward { anchored: true, ..WalkForward::new(100, 50) };
+        assert_eq!(anchored.splits(200).unwrap()[1], Fold { train: 0..150, test: 150..200 });
+        assert_eq!(WalkForward::new(100, 30).splits(100).unwrap_err().errcode, ErrCode::ParaError);
+    }
+
+    #[test]
+    fn test_par_map_keeps_order() {
+        let jobs: Vec<usize> = (0..50).collect();
+        assert_eq!(par_map(&jobs, 4, |x| x * 2), jobs.iter().map(|x| x * 2).collect::<Vec<_>>());
+        assert!(par_map(&Vec::<usize>::new(), 4, |x| *x).is_empty());
+    }
+
+    fn bsp_space() -> ParamSpace {
+        serde_json::from_value(json!({
+            "params": {
+                "divergence_rate": [0.8, 1.0],
+                "min_zs_cnt-buy": [0, 1],
+            }
+        }))
+        .unwrap()
+    }
+
+    fn default_strategy(_conf: &ChanConfig) -> BspStrategy {
+        BspStrategy::default()
+    }
+
+    fn backtest(space: &ParamSpace, params: &ParamSet, bars: &[KlData], trade_start: usize) -> BacktestReport {
+        let analyzer = Analyzer::new("sine".to_string(), space.build_config(params).unwrap()).unwrap();
+        Backtester::new(analyzer, BspStrategy::default(), BacktestConfig::default())
+            .with_trade_start(trade_start)
+            .run(bars)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_shared_structure_matches_backtester() {
+        let bars = sine_kl_data(300);
+        let space = bsp_space();
+        let param_sets = space.grid();
+        // 4个组合只有买卖点参数不同，共用一个Analyzer
+        assert_eq!(param_sets.iter().map(structure_key).collect::<HashSet<_>>().len(), 1);
+        let mut optimizer = Optimizer::new("sine", &bars, space.clone(), BacktestConfig::default(), default_strategy);
+        optimizer.threads = 1;
+
+        let results = optimizer.sweep(&param_sets);
+        assert_eq!(results.len(), 4);
+        let mut trade_cnt = 0;
+        for res in &results {
+            let expected = SweepMetrics::from_report(&backtest(&space, &res.params, &bars, 0), 0);
+            trade_cnt += expected.trade_cnt;
+            assert_eq!(res.metrics.as_ref().unwrap(), &expected);
+        }
+        assert!(trade_cnt > 0);
+    }
+
+    #[test]
+    fn test_bad_bsp_value_is_err_row() {
+        let bars = sine_kl_data(200);
+        let space: ParamSpace = serde_json::from_value(json!({
+            "params": {"macd_algo": ["amp", "no_such_algo"]}
+        }))
+        .unwrap();
+        let param_sets = space.grid();
+        assert_eq!(space.build_config(&param_sets[1]).unwrap_err().errcode, ErrCode::ParaError);
+
+        let mut optimizer = Optimizer::new("sine", &bars, space, BacktestConfig::default(), default_strategy);
+        optimizer.threads = 2;
+        let results = optimizer.sweep(&param_sets);
+        assert_eq!(results.len(), 2);
+        // 出错的组合排在最后，输出ERR行而不是让工作线程panic
+        assert!(results[0].metrics.is_ok());
+        assert_eq!(results[1].metrics.as_ref().unwrap_err().errcode, ErrCode::ParaError);
+        let table = format_results(&results, 2);
+        assert!(table.lines().last().unwrap().starts_with("2\tERR\t"));
+    }
+
+    #[test]
+    fn test_test_segment_trades_start_at_boundary() {
+        let bars = sine_kl_data(300);
+        let space = bsp_space();
+        let params = space.grid().remove(0);
+        let report = backtest(&space, &params, &bars, 150);
+        // 预热段不开仓，账户保持初始资金
+        assert!(report
+            .trades
+            .iter()
+            .all(|trade| trade.entry_time.timestamp >= bars[150].time.timestamp));
+        assert!(report.equity_curve[..150]
+            .iter()
+            .all(|&(_, equity)| equity == report.initial_cash));
+        assert_eq!(report.trades_since(150).len(), report.trades.len());
+
+        let optimizer = Optimizer::new("sine", &bars, space, BacktestConfig::default(), default_strategy);
+        let res = optimizer.sweep_range(std::slice::from_ref(&params), 0..300, 150);
+        assert_eq!(res[0].metrics.as_ref().unwrap(), &SweepMetrics::from_report(&report, 150));
+    }
+}
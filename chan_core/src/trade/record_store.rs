@@ -0,0 +1,407 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::chan_exception::{ChanException, ErrCode};
+use crate::common::time::Time;
+
+/// 交易记录的状态：信号 -> 观察中 -> 已开仓 -> 已平仓，观察中可以取消
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordStatus {
+    Watching,
+    Opened,
+    Closed,
+    Canceled,
+}
+
+/// 一个买卖点信号及其对应的仓位
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeRecord {
+    pub id: u64,
+    pub symbol: String,
+    /// 信号去重的key，同一标的同一买卖点只记录一次，见`signal_key`
+    pub signal_key: String,
+    /// 逗号分隔的买卖点类型，同一买卖点后来增加的类型会合并进来
+    pub bsp_type: String,
+    pub is_buy: bool,
+    pub status: RecordStatus,
+    pub signal_time: Time,
+    pub open_time: Option<Time>,
+    pub open_price: Option<f64>,
+    pub qty: u64,
+    pub close_time: Option<Time>,
+    pub close_price: Option<f64>,
+}
+
+impl TradeRecord {
+    pub fn pnl(&self) -> Option<f64> {
+        let (open, close) = (self.open_price?, self.close_price?);
+        let sign = if self.is_buy { 1.0 } else { -1.0 };
+        Some(sign * (close - open) * self.qty as f64)
+    }
+}
+
+/// 买卖点的去重key：买卖点所在KLU的时间、方向以及是笔还是线段买卖点，不含类型
+pub fn signal_key(time: &Time, is_buy: bool, is_segbsp: bool) -> String {
+    format!(
+        "{}|{}|{}",
+        time,
+        if is_buy { "buy" } else { "sell" },
+        if is_segbsp { "seg" } else { "bi" }
+    )
+}
+
+/// 把new中old没有的类型追加到old后面，没有新类型时返回None
+fn merge_bsp_type(old: &str, new: &str) -> Option<String> {
+    let mut types: Vec<&str> = old.split(',').filter(|t| !t.is_empty()).collect();
+    let old_cnt = types.len();
+    for t in new.split(',').filter(|t| !t.is_empty()) {
+        if !types.contains(&t) {
+            types.push(t);
+        }
+    }
+    (types.len() > old_cnt).then(|| types.join(","))
+}
+
+/// 交易记录库，保存为jsonl文件，回测时也可以只放在内存里
+///
+/// 每次状态变化只在文件末尾追加一行变化后的记录，`open`时按顺序回放，同一id以最后一行为准；
+/// 历史行太多时用`compact`整体重写一次（先写临时文件再rename）。
+/// 非法的状态转换返回对应的交易类ErrCode
+#[derive(Debug)]
+pub struct RecordStore {
+    path: Option<PathBuf>,
+    file: Option<File>,
+    /// 同时处于Opened状态的记录数上限
+    pub max_open_cnt: usize,
+    next_id: u64,
+    records: BTreeMap<u64, TradeRecord>,
+    /// (symbol, signal_key) -> id
+    signal_index: HashMap<(String, String), u64>,
+}
+
+impl RecordStore {
+    pub fn in_memory(max_open_cnt: usize) -> Self {
+        Self {
+            path: None,
+            file: None,
+            max_open_cnt,
+            next_id: 0,
+            records: BTreeMap::new(),
+            signal_index: HashMap::new(),
+        }
+    }
+
+    /// 打开path上的记录库，文件不存在时新建
+    ///
+    /// 进程在写最后一行时退出会留下不完整的一行，打开时截掉
+    pub fn open(path: impl AsRef<Path>, max_open_cnt: usize) -> Result<Self, ChanException> {
+        let path = path.as_ref().to_path_buf();
+        let mut store = Self::in_memory(max_open_cnt);
+        if path.exists() {
+            let content = std::fs::read_to_string(&path).map_err(|e| io_err(&path, e))?;
+            let complete_len = content.rfind('\n').map_or(0, |pos| pos + 1);
+            for (line_no, line) in content[..complete_len].lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record: TradeRecord = serde_json::from_str(line).map_err(|e| {
+                    ChanException::new(
+                        format!("parse record store {} line {} fail: {}", path.display(), line_no + 1, e),
+                        ErrCode::CommonError,
+                    )
+                })?;
+                store.insert(record);
+            }
+            if complete_len < content.len() {
+                let file = OpenOptions::new().write(true).open(&path).map_err(|e| io_err(&path, e))?;
+                file.set_len(complete_len as u64).map_err(|e| io_err(&path, e))?;
+            }
+        }
+        store.file = Some(open_append(&path)?);
+        store.path = Some(path);
+        Ok(store)
+    }
+
+    fn insert(&mut self, record: TradeRecord) {
+        self.next_id = self.next_id.max(record.id + 1);
+        self.signal_index
+            .insert((record.symbol.clone(), record.signal_key.clone()), record.id);
+        self.records.insert(record.id, record);
+    }
+
+    /// 在文件末尾追加id的当前状态
+    fn append(&mut self, id: u64) -> Result<(), ChanException> {
+        let (Some(file), Some(path)) = (&mut self.file, &self.path) else {
+            return Ok(());
+        };
+        let mut line = serde_json::to_string(&self.records[&id])
+            .map_err(|e| ChanException::new(format!("serialize record {} fail: {}", id, e), ErrCode::CommonError))?;
+        line.push('\n');
+        file.write_all(line.as_bytes()).map_err(|e| io_err(path, e))
+    }
+
+    /// 每条记录只保留当前状态，整体重写文件
+    pub fn compact(&mut self) -> Result<(), ChanException> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let mut content = String::new();
+        for record in self.records.values() {
+            let line = serde_json::to_string(record).map_err(|e| {
+                ChanException::new(format!("serialize record {} fail: {}", record.id, e), ErrCode::CommonError)
+            })?;
+            content.push_str(&line);
+            content.push('\n');
+        }
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, content).map_err(|e| io_err(&tmp_path, e))?;
+        std::fs::rename(&tmp_path, &path).map_err(|e| io_err(&path, e))?;
+        self.file = Some(open_append(&path)?);
+        Ok(())
+    }
+
+    pub fn get(&self, id: u64) -> Result<&TradeRecord, ChanException> {
+        self.records
+            .get(&id)
+            .ok_or_else(|| ChanException::new(format!("record {} not exist", id), ErrCode::RecordNotExist))
+    }
+
+    fn get_mut(&mut self, id: u64) -> Result<&mut TradeRecord, ChanException> {
+        self.records
+            .get_mut(&id)
+            .ok_or_else(|| ChanException::new(format!("record {} not exist", id), ErrCode::RecordNotExist))
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &TradeRecord> {
+        self.records.values()
+    }
+
+    pub fn records_with_status(&self, status: RecordStatus) -> impl Iterator<Item = &TradeRecord> {
+        self.records().filter(move |record| record.status == status)
+    }
+
+    pub fn find_signal(&self, symbol: &str, signal_key: &str) -> Option<&TradeRecord> {
+        let id = self.signal_index.get(&(symbol.to_string(), signal_key.to_string()))?;
+        self.records.get(id)
+    }
+
+    /// 记录新信号，返回记录id，状态为Watching
+    ///
+    /// 同一信号已存在时把新增的类型合并进记录的bsp_type，已经开过仓返回SignalTraded，否则返回SignalExisted
+    pub fn add_signal(
+        &mut self,
+        symbol: &str,
+        signal_key: &str,
+        bsp_type: &str,
+        is_buy: bool,
+        time: Time,
+    ) -> Result<u64, ChanException> {
+        if let Some(&id) = self.signal_index.get(&(symbol.to_string(), signal_key.to_string())) {
+            let record = self.get_mut(id)?;
+            let errcode = match record.status {
+                RecordStatus::Opened | RecordStatus::Closed => ErrCode::SignalTraded,
+                RecordStatus::Watching | RecordStatus::Canceled => ErrCode::SignalExisted,
+            };
+            if let Some(merged) = merge_bsp_type(&record.bsp_type, bsp_type) {
+                record.bsp_type = merged;
+                self.append(id)?;
+            }
+            return Err(ChanException::new(
+                format!("signal {} of {} already in record {}", signal_key, symbol, id),
+                errcode,
+            ));
+        }
+        let id = self.next_id;
+        self.insert(TradeRecord {
+            id,
+            symbol: symbol.to_string(),
+            signal_key: signal_key.to_string(),
+            bsp_type: bsp_type.to_string(),
+            is_buy,
+            status: RecordStatus::Watching,
+            signal_time: time,
+            open_time: None,
+            open_price: None,
+            qty: 0,
+            close_time: None,
+            close_price: None,
+        });
+        self.append(id)?;
+        Ok(id)
+    }
+
+    /// Watching -> Opened，超过max_open_cnt时返回QuotaNotEnough
+    pub fn open_record(&mut self, id: u64, price: f64, qty: u64, time: Time) -> Result<(), ChanException> {
+        let opened_cnt = self.records_with_status(RecordStatus::Opened).count();
+        let max_open_cnt = self.max_open_cnt;
+        let record = self.get_mut(id)?;
+        match record.status {
+            RecordStatus::Watching => {}
+            RecordStatus::Opened => {
+                return Err(ChanException::new(format!("record {} already opened", id), ErrCode::RecordAlreadyOpened))
+            }
+            RecordStatus::Closed => {
+                return Err(ChanException::new(format!("record {} already closed", id), ErrCode::RecordClosed))
+            }
+            RecordStatus::Canceled => {
+                return Err(ChanException::new(
+                    format!("record {} is not watching", id),
+                    ErrCode::OpenRecordNotWatching,
+                ))
+            }
+        }
+        if opened_cnt >= max_open_cnt {
+            return Err(ChanException::new(
+                format!("{} records opened, quota is {}", opened_cnt, max_open_cnt),
+                ErrCode::QuotaNotEnough,
+            ));
+        }
+        record.status = RecordStatus::Opened;
+        record.open_time = Some(time);
+        record.open_price = Some(price);
+        record.qty = qty;
+        self.append(id)
+    }
+
+    /// Opened -> Closed
+    pub fn close_record(&mut self, id: u64, price: f64, time: Time) -> Result<(), ChanException> {
+        let record = self.get_mut(id)?;
+        match record.status {
+            RecordStatus::Opened => {}
+            RecordStatus::Closed => {
+                return Err(ChanException::new(format!("record {} already closed", id), ErrCode::RecordClosed))
+            }
+            RecordStatus::Watching | RecordStatus::Canceled => {
+                return Err(ChanException::new(format!("record {} not opened", id), ErrCode::RecordNotOpened))
+            }
+        }
+        record.status = RecordStatus::Closed;
+        record.close_time = Some(time);
+        record.close_price = Some(price);
+        self.append(id)
+    }
+
+    /// Watching -> Canceled，信号失效时调用
+    pub fn cancel_record(&mut self, id: u64) -> Result<(), ChanException> {
+        let record = self.get_mut(id)?;
+        if record.status != RecordStatus::Watching {
+            return Err(ChanException::new(
+                format!("record {} is not watching", id),
+                ErrCode::OpenRecordNotWatching,
+            ));
+        }
+        record.status = RecordStatus::Canceled;
+        self.append(id)
+    }
+}
+
+fn open_append(path: &Path) -> Result<File, ChanException> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| io_err(path, e))
+}
+
+fn io_err(path: &Path, e: std::io::Error) -> ChanException {
+    ChanException::new(format!("access record store {} fail: {}", path.display(), e), ErrCode::CommonError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(i: i64) -> Time {
+        Time::new(1_704_067_200 + i * 86_400)
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut store = RecordStore::in_memory(1);
+        let key = signal_key(&time(0), true, false);
+        let id = store.add_signal("sz.000001", &key, "1", true, time(0)).unwrap();
+        let err = store.add_signal("sz.000001", &key, "1", true, time(0)).unwrap_err();
+        assert_eq!(err.errcode, ErrCode::SignalExisted);
+        // 其他标的的同一时间信号不冲突
+        let other = store.add_signal("sz.000002", &key, "1", true, time(0)).unwrap();
+
+        assert_eq!(store.close_record(id, 11.0, time(1)).unwrap_err().errcode, ErrCode::RecordNotOpened);
+        store.open_record(id, 10.0, 100, time(1)).unwrap();
+        assert_eq!(store.open_record(id, 10.0, 100, time(1)).unwrap_err().errcode, ErrCode::RecordAlreadyOpened);
+        assert_eq!(store.open_record(other, 10.0, 100, time(1)).unwrap_err().errcode, ErrCode::QuotaNotEnough);
+        assert_eq!(store.add_signal("sz.000001", &key, "1", true, time(1)).unwrap_err().errcode, ErrCode::SignalTraded);
+
+        store.close_record(id, 11.0, time(2)).unwrap();
+        assert_eq!(store.close_record(id, 11.0, time(2)).unwrap_err().errcode, ErrCode::RecordClosed);
+        assert_eq!(store.open_record(id, 10.0, 100, time(2)).unwrap_err().errcode, ErrCode::RecordClosed);
+        assert_eq!(store.get(id).unwrap().pnl(), Some(100.0));
+
+        store.cancel_record(other).unwrap();
+        assert_eq!(
+            store.open_record(other, 10.0, 100, time(3)).unwrap_err().errcode,
+            ErrCode::OpenRecordNotWatching
+        );
+        assert_eq!(store.get(99).unwrap_err().errcode, ErrCode::RecordNotExist);
+    }
+
+    #[test]
+    fn test_merge_bsp_type() {
+        assert_ne!(signal_key(&time(0), true, false), signal_key(&time(0), true, true));
+        assert_eq!(merge_bsp_type("1", "1,2"), Some("1,2".to_string()));
+        assert_eq!(merge_bsp_type("1,2", "2"), None);
+
+        let mut store = RecordStore::in_memory(1);
+        let key = signal_key(&time(0), true, false);
+        let id = store.add_signal("sz.000001", &key, "1", true, time(0)).unwrap();
+        // 同一买卖点后来又成为二类买点，合并类型而不是新建记录
+        let err = store.add_signal("sz.000001", &key, "2", true, time(1)).unwrap_err();
+        assert_eq!(err.errcode, ErrCode::SignalExisted);
+        assert_eq!(store.records().count(), 1);
+        assert_eq!(store.get(id).unwrap().bsp_type, "1,2");
+        assert_eq!(store.find_signal("sz.000001", &key).unwrap().id, id);
+        assert!(store.find_signal("sz.000002", &key).is_none());
+    }
+
+    #[test]
+    fn test_persist() {
+        let path = std::env::temp_dir().join(format!("chan_record_store_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let id = {
+            let mut store = RecordStore::open(&path, 5).unwrap();
+            let id = store.add_signal("sz.000001", "k", "2", true, time(0)).unwrap();
+            store.open_record(id, 10.0, 200, time(1)).unwrap();
+            id
+        };
+        // 每次状态变化追加一行
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        let mut store = RecordStore::open(&path, 5).unwrap();
+        assert_eq!(store.get(id).unwrap().status, RecordStatus::Opened);
+        assert_eq!(store.get(id).unwrap().qty, 200);
+        assert_eq!(store.add_signal("sz.000001", "k", "3", true, time(2)).unwrap_err().errcode, ErrCode::SignalTraded);
+        // id不会复用
+        assert_eq!(store.add_signal("sz.000001", "k2", "2", true, time(2)).unwrap(), id + 1);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 4);
+
+        store.compact().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        store.close_record(id, 11.0, time(3)).unwrap();
+        drop(store);
+
+        // 写到一半的最后一行被截掉，之后追加的记录不受影响
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"id\": 1, \"sym").unwrap();
+        drop(file);
+        let mut store = RecordStore::open(&path, 5).unwrap();
+        assert_eq!(store.get(id).unwrap().status, RecordStatus::Closed);
+        assert_eq!(store.get(id).unwrap().bsp_type, "2,3");
+        store.cancel_record(id + 1).unwrap();
+        drop(store);
+        let store = RecordStore::open(&path, 5).unwrap();
+        assert_eq!(store.get(id + 1).unwrap().status, RecordStatus::Canceled);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use crate::analyzer::analyzer::Analyzer;
+use crate::buy_sell_point::bs_point::BSPoint;
+use crate::common::cenum::BspType;
+use crate::common::time::Time;
+use crate::traits::line_trait::LineTrait;
+
+use super::backtest::{Bar, Position};
+
+/// 策略在某根K线收盘后发出的指令，在下一根K线开盘时成交
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Open,
+    Close,
+    Hold,
+}
+
+impl Signal {
+    /// 同一根K线收盘时有多个指令，只保留优先级最高的一个：Close > Open > Hold，
+    /// 同级取先发出的（笔买卖点、线段买卖点、on_bar依次回调）
+    pub fn priority(self) -> u8 {
+        match self {
+            Signal::Close => 2,
+            Signal::Open => 1,
+            Signal::Hold => 0,
+        }
+    }
+}
+
+/// 新出现的买卖点，笔和线段买卖点统一成同一个结构交给策略
+///
+/// 已回调过的买卖点后来增加了类型（如一类买点又被确认为二类）时会再次回调，bs_type为全部类型
+#[derive(Debug, Clone)]
+pub struct BspEvent {
+    pub bs_type: Vec<BspType>,
+    pub is_buy: bool,
+    pub is_segbsp: bool,
+    pub score: Option<f64>,
+    /// 买卖点所在KLU
+    pub klu_idx: usize,
+    pub time: Time,
+    /// 买卖点所在笔的序号，线段买卖点取线段最后一笔
+    pub bi_idx: usize,
+    /// 买卖点所在笔（线段）的序号和起止价格
+    pub line_idx: usize,
+    pub bi_begin_val: f64,
+    pub bi_end_val: f64,
+}
+
+impl BspEvent {
+    pub fn from_bsp<T: LineTrait>(bsp: &BSPoint<T>, is_segbsp: bool) -> Self {
+        let bi = bsp.bi.borrow();
+        Self {
+            bs_type: bsp.bs_type.clone(),
+            is_buy: bsp.is_buy,
+            is_segbsp,
+            score: bsp.score,
+            klu_idx: bsp.klu.index(),
+            time: bsp.klu.time,
+            bi_idx: bi.end_bi_idx(),
+            line_idx: bi.idx(),
+            bi_begin_val: bi.get_begin_val(),
+            bi_end_val: bi.get_end_val(),
+        }
+    }
+
+    pub fn type_to_string(&self) -> String {
+        self.bs_type.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(",")
+    }
+}
+
+/// 策略每一步能看到的状态：当前K线、持仓和逐K线计算到当前为止的Analyzer
+pub struct StepContext<'a> {
+    pub bar: &'a Bar,
+    pub position: Option<&'a Position>,
+    pub analyzer: &'a Analyzer,
+}
+
+/// 回测策略，只能看到当前K线及之前的数据
+pub trait Strategy {
+    /// 本根K线新出现的买卖点，笔买卖点和线段买卖点都会回调
+    fn on_bsp(&mut self, ctx: &StepContext, bsp: &BspEvent) -> Signal;
+
+    /// 每根K线收盘后调用，用于止盈止损等与买卖点无关的退出
+    fn on_bar(&mut self, _ctx: &StepContext) -> Signal {
+        Signal::Hold
+    }
+}
+
+/// 最简单的买卖点策略：指定类型的买点开仓，任意卖点平仓
+#[derive(Debug, Clone)]
+pub struct BspStrategy {
+    /// 开仓的买点类型，按main_type比较，如T1同时包括T1P
+    pub buy_types: Vec<BspType>,
+    /// 是否使用线段买卖点
+    pub use_segbsp: bool,
+    /// 设置后只在买点打分不低于该值时开仓
+    pub min_score: Option<f64>,
+}
+
+impl Default for BspStrategy {
+    fn default() -> Self {
+        Self {
+            buy_types: vec![BspType::T1, BspType::T2, BspType::T3A],
+            use_segbsp: false,
+            min_score: None,
+        }
+    }
+}
+
+impl Strategy for BspStrategy {
+    fn on_bsp(&mut self, ctx: &StepContext, bsp: &BspEvent) -> Signal {
+        if bsp.is_segbsp && !self.use_segbsp {
+            return Signal::Hold;
+        }
+        if !bsp.is_buy {
+            return if ctx.position.is_some() { Signal::Close } else { Signal::Hold };
+        }
+        let type_match = bsp
+            .bs_type
+            .iter()
+            .any(|bs_type| self.buy_types.iter().any(|t| t.main_type() == bs_type.main_type()));
+        let score_match = self
+            .min_score
+            .is_none_or(|min_score| bsp.score.is_some_and(|score| score >= min_score));
+        if ctx.position.is_none() && type_match && score_match {
+            Signal::Open
+        } else {
+            Signal::Hold
+        }
+    }
+}
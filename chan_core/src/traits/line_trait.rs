@@ -1,35 +1,247 @@
+use crate::common::cenum::{DataField, MacdAlgo};
 use crate::common::enums::BiDir;
+use crate::common::chan_exception::{ChanException, ErrCode};
 use crate::common::handle::Handle;
 use crate::kline::kline_unit::KLineUnit;
 
 pub trait LineTrait {
     /// Get the beginning KLineUnit
     fn get_begin_klu(&self) -> Handle<KLineUnit>;
-    
+
     /// Get the ending KLineUnit
     fn get_end_klu(&self) -> Handle<KLineUnit>;
-    
+
     /// Get the low value
     fn _low(&self) -> f64;
-    
+
     /// Get the high value
     fn _high(&self) -> f64;
-    
+
     /// Get the index
     fn idx(&self) -> usize;
-    
+
+    /// Index of the last bi: the bi itself for a bi, the end bi for a seg
+    fn end_bi_idx(&self) -> usize {
+        self.idx()
+    }
+
     /// Get the segment index
-    fn seg_idx(&self) -> usize;
-    
+    fn seg_idx(&self) -> Option<usize>;
+
+    /// Set the index of the seg this line belongs to
+    fn set_seg_idx(&mut self, idx: usize);
+
+    /// Get the begin value
+    fn get_begin_val(&self) -> f64;
+
     /// Get the end value
     fn get_end_val(&self) -> f64;
-    
+
+    /// Get the amplitude between begin and end value
+    fn amp(&self) -> f64 {
+        (self.get_end_val() - self.get_begin_val()).abs()
+    }
+
     /// Check if it's a downward direction
     fn is_down(&self) -> bool;
-    
+
     /// Check if it's an upward direction
     fn is_up(&self) -> bool;
-    
+
+    /// Check if the line is confirmed
+    fn is_sure(&self) -> bool;
+
+    /// Direction of the seg this line belongs to, None if not inside any seg
+    fn parent_seg_dir(&self) -> Option<BiDir>;
+
+    /// Iterate the KLineUnits covered by this line, in time order
+    ///
+    /// 默认从起点KLU到终点KLU；笔会覆盖为首尾合并K线内的全部KLU，与chan.py的klc_lst一致
+    fn klu_iter(&self) -> Box<dyn Iterator<Item = Handle<KLineUnit>> + '_> {
+        let end_idx = self.get_end_klu().index();
+        Box::new(
+            std::iter::successors(Some(self.get_begin_klu()), |klu| klu.next())
+                .take_while(move |klu| klu.index() <= end_idx),
+        )
+    }
+
     /// Calculate MACD metric
-    fn cal_macd_metric(&self, algo: &str, is_reverse: bool) -> f64;
-} 
\ No newline at end of file
+    fn cal_macd_metric(&self, macd_algo: MacdAlgo, is_reverse: bool) -> Result<f64, ChanException> {
+        Ok(match macd_algo {
+            MacdAlgo::Area => self.cal_macd_half(is_reverse)?,
+            MacdAlgo::Peak => self.cal_macd_peak()?,
+            MacdAlgo::FullArea => self.cal_macd_area()?,
+            MacdAlgo::Diff => self.cal_macd_diff()?,
+            MacdAlgo::Slope => self.cal_macd_slope(),
+            MacdAlgo::Amp => self.cal_macd_amp(),
+            MacdAlgo::Amount => self.cal_macd_trade_metric(DataField::FieldTurnover, false),
+            MacdAlgo::Volumn => self.cal_macd_trade_metric(DataField::FieldVolume, false),
+            MacdAlgo::VolumnAvg => self.cal_macd_trade_metric(DataField::FieldVolume, true),
+            MacdAlgo::AmountAvg => self.cal_macd_trade_metric(DataField::FieldTurnover, true),
+            MacdAlgo::TurnrateAvg => self.cal_macd_trade_metric(DataField::FieldTurnrate, true),
+            MacdAlgo::Rsi => self.cal_rsi(),
+            MacdAlgo::AtrAmp => self.cal_atr_amp()?,
+            MacdAlgo::Obv => self.cal_obv_diff()?,
+        })
+    }
+
+    /// Amplitude normalised by the ATR at the begin KLU
+    fn cal_atr_amp(&self) -> Result<f64, ChanException> {
+        let atr = self.get_begin_klu().atr.ok_or_else(|| {
+            ChanException::new("macd_algo=atr_amp requires cal_atr", ErrCode::ParaError)
+        })?;
+        Ok(self.amp() / (atr + 1e-7))
+    }
+
+    /// Absolute OBV change from the begin KLU to the end KLU
+    fn cal_obv_diff(&self) -> Result<f64, ChanException> {
+        match (self.get_begin_klu().obv, self.get_end_klu().obv) {
+            (Some(begin), Some(end)) => Ok((end - begin).abs() + 1e-7),
+            _ => Err(ChanException::new("macd_algo=obv requires cal_obv", ErrCode::ParaError)),
+        }
+    }
+
+    /// Calculate RSI
+    fn cal_rsi(&self) -> f64 {
+        let rsi_lst = self.klu_iter().filter_map(|klu| klu.rsi);
+        if self.is_down() {
+            10000.0 / (rsi_lst.fold(f64::INFINITY, f64::min) + 1e-7)
+        } else {
+            rsi_lst.fold(f64::NEG_INFINITY, f64::max)
+        }
+    }
+
+    /// Values of a registered metric on the covered KLineUnits, skipping bars without it
+    fn custom_metric_values(&self, name: &str) -> Vec<f64> {
+        self.klu_iter()
+            .filter_map(|klu| klu.custom_metric.get(name).copied())
+            .collect()
+    }
+
+    /// Peak of a registered metric along the line: max for up lines, min for down lines
+    fn cal_custom_metric_peak(&self, name: &str) -> Option<f64> {
+        let values = self.custom_metric_values(name).into_iter();
+        if self.is_up() {
+            values.reduce(f64::max)
+        } else {
+            values.reduce(f64::min)
+        }
+    }
+
+    /// Calculate MACD area
+    fn cal_macd_area(&self) -> Result<f64, ChanException> {
+        let mut s = 1e-7;
+        for klu in self.klu_iter() {
+            s += klu_macd(&klu)?.abs();
+        }
+        Ok(s)
+    }
+
+    /// Calculate MACD peak, only counting bars in the same direction as the line
+    fn cal_macd_peak(&self) -> Result<f64, ChanException> {
+        let mut peak = 1e-7;
+        for klu in self.klu_iter() {
+            let macd = klu_macd(&klu)?;
+            if macd.abs() > peak && ((self.is_down() && macd < 0.0) || (self.is_up() && macd > 0.0)) {
+                peak = macd.abs();
+            }
+        }
+        Ok(peak)
+    }
+
+    /// Calculate half MACD area, reversed from the end when `is_reverse`
+    fn cal_macd_half(&self, is_reverse: bool) -> Result<f64, ChanException> {
+        if is_reverse {
+            self.cal_macd_half_reverse()
+        } else {
+            self.cal_macd_half_obverse()
+        }
+    }
+
+    /// Sum MACD bars from the begin KLU until the sign flips
+    fn cal_macd_half_obverse(&self) -> Result<f64, ChanException> {
+        let peak_macd = klu_macd(&self.get_begin_klu())?;
+        let mut s = 1e-7;
+        for klu in self.klu_iter() {
+            let macd = klu_macd(&klu)?;
+            if macd * peak_macd <= 0.0 {
+                break;
+            }
+            s += macd.abs();
+        }
+        Ok(s)
+    }
+
+    /// Sum MACD bars backwards from the end KLU until the sign flips
+    fn cal_macd_half_reverse(&self) -> Result<f64, ChanException> {
+        let peak_macd = klu_macd(&self.get_end_klu())?;
+        let klu_lst: Vec<Handle<KLineUnit>> = self.klu_iter().collect();
+        let mut s = 1e-7;
+        for klu in klu_lst.iter().rev() {
+            let macd = klu_macd(klu)?;
+            if macd * peak_macd <= 0.0 {
+                break;
+            }
+            s += macd.abs();
+        }
+        Ok(s)
+    }
+
+    /// macd红绿柱最大值最小值之差
+    fn cal_macd_diff(&self) -> Result<f64, ChanException> {
+        let mut max = f64::NEG_INFINITY;
+        let mut min = f64::INFINITY;
+        for klu in self.klu_iter() {
+            let macd = klu_macd(&klu)?;
+            max = max.max(macd);
+            min = min.min(macd);
+        }
+        Ok(max - min)
+    }
+
+    /// Calculate MACD slope
+    fn cal_macd_slope(&self) -> f64 {
+        let begin_klu = self.get_begin_klu();
+        let end_klu = self.get_end_klu();
+        let klu_cnt = end_klu.index() as f64 - begin_klu.index() as f64 + 1.0;
+        if self.is_up() {
+            (end_klu.high - begin_klu.low) / end_klu.high / klu_cnt
+        } else {
+            (begin_klu.high - end_klu.low) / begin_klu.high / klu_cnt
+        }
+    }
+
+    /// Calculate MACD amplitude
+    fn cal_macd_amp(&self) -> f64 {
+        let begin_klu = self.get_begin_klu();
+        let end_klu = self.get_end_klu();
+        if self.is_down() {
+            (begin_klu.high - end_klu.low) / begin_klu.high
+        } else {
+            (end_klu.high - begin_klu.low) / begin_klu.low
+        }
+    }
+
+    /// Sum (or average) a trade info field, 0.0 if any bar lacks it
+    fn cal_macd_trade_metric(&self, metric: DataField, cal_avg: bool) -> f64 {
+        let mut s = 0.0;
+        for klu in self.klu_iter() {
+            match klu.trade_info.metric.get(metric.as_str()).copied().flatten() {
+                Some(metric_res) => s += metric_res,
+                None => return 0.0,
+            }
+        }
+        if cal_avg {
+            s / (self.get_end_klu().index() - self.get_begin_klu().index() + 1) as f64
+        } else {
+            s
+        }
+    }
+}
+
+/// MACD bar of a KLineUnit, error if MACD has not been calculated on it
+fn klu_macd(klu: &KLineUnit) -> Result<f64, ChanException> {
+    klu.macd.as_ref().map(|item| item.macd).ok_or_else(|| {
+        ChanException::new(format!("macd not calculated on klu {}", klu.index()), ErrCode::CommonError)
+    })
+}
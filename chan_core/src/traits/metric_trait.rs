@@ -0,0 +1,115 @@
+use std::fmt::Debug;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::common::chan_exception::{ChanException, ErrCode};
+use crate::kline::kline_unit::KLineUnit;
+
+/// 逐根KLU增量计算的指标
+///
+/// 实现本trait的类型自动获得`MetricModel`，可以放进`ChanConfig::get_metric_model`
+/// 或通过`Analyzer::register_metric_model`注册自定义指标
+pub trait Indicator: Clone + Debug + Serialize + DeserializeOwned + 'static {
+    /// 每根KLU的计算结果
+    type Output;
+
+    /// 指标名称，自定义指标以此为key存放在`KLineUnit.custom_metric`中
+    fn name(&self) -> String;
+
+    /// 用新的一根KLU更新指标状态，返回这根KLU上的指标值
+    fn update(&mut self, klu: &KLineUnit) -> Self::Output;
+
+    /// 把计算结果保存到KLU上
+    fn store(&self, klu: &mut KLineUnit, output: Self::Output);
+
+    /// 清空状态，保留参数
+    fn reset(&mut self);
+}
+
+/// 对象安全的指标接口，`ChanConfig`和`Analyzer`以`Box<dyn MetricModel>`持有
+pub trait MetricModel: Debug {
+    fn name(&self) -> String;
+
+    /// 增量计算并把结果写入KLU
+    fn update_kline_unit(&mut self, klu: &mut KLineUnit);
+
+    fn reset(&mut self);
+
+    /// 序列化内部状态，用于断点续算
+    fn save_state(&self) -> Result<serde_json::Value, ChanException>;
+
+    /// 从`save_state`的结果恢复内部状态
+    fn load_state(&mut self, state: &serde_json::Value) -> Result<(), ChanException>;
+
+    fn box_clone(&self) -> Box<dyn MetricModel>;
+}
+
+impl<T: Indicator> MetricModel for T {
+    fn name(&self) -> String {
+        Indicator::name(self)
+    }
+
+    fn update_kline_unit(&mut self, klu: &mut KLineUnit) {
+        let output = self.update(klu);
+        self.store(klu, output);
+    }
+
+    fn reset(&mut self) {
+        Indicator::reset(self)
+    }
+
+    fn save_state(&self) -> Result<serde_json::Value, ChanException> {
+        serde_json::to_value(self).map_err(|e| {
+            ChanException::new(format!("save {} state fail: {}", Indicator::name(self), e), ErrCode::ModelError)
+        })
+    }
+
+    fn load_state(&mut self, state: &serde_json::Value) -> Result<(), ChanException> {
+        *self = T::deserialize(state).map_err(|e| {
+            ChanException::new(format!("load {} state fail: {}", Indicator::name(self), e), ErrCode::ModelError)
+        })?;
+        Ok(())
+    }
+
+    fn box_clone(&self) -> Box<dyn MetricModel> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn MetricModel> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::rsi::RSI;
+
+    #[test]
+    fn test_state_round_trip() {
+        let closes = [10.0, 10.5, 10.2, 10.8, 11.3, 11.0, 10.6, 10.9];
+        let mut model = RSI::new(6);
+        for &close in &closes[..4] {
+            model.add(close);
+        }
+        let state = model.save_state().unwrap();
+
+        let mut restored = RSI::new(6);
+        restored.load_state(&state).unwrap();
+        for &close in &closes[4..] {
+            assert_eq!(model.add(close), restored.add(close));
+        }
+
+        MetricModel::reset(&mut restored);
+        assert_eq!(restored.add(closes[0]), 50.0);
+    }
+
+    #[test]
+    fn test_load_bad_state() {
+        let mut model = RSI::new(6);
+        let err = model.load_state(&serde_json::json!({"period": "six"})).unwrap_err();
+        assert_eq!(err.errcode, ErrCode::ModelError);
+    }
+}
@@ -1,5 +1,108 @@
+use crate::buy_sell_point::bs_point_config::PointConfig;
+use crate::common::chan_exception::ChanException;
+use crate::common::handle::Handle;
+use crate::common::utils::has_overlap;
+use crate::kline::kline_unit::KLineUnit;
+use crate::seg::seg::Seg;
+use crate::traits::line_trait::LineTrait;
+use super::zs_config::ZSCombineMode;
+
+/// 中枢
+///
+/// begin/end：永远指向klu；low/high：中枢的范围；
+/// peak_low/peak_high：中枢所涉及到的笔的最大值，最小值
+#[derive(Debug, Clone)]
+pub struct ZS<T: LineTrait> {
+    pub is_sure: bool,
+    /// 合并前的原始中枢，未发生合并时为空
+    pub sub_zs_lst: Vec<ZS<T>>,
+    pub begin: Option<Handle<KLineUnit>>,
+    pub begin_bi: Option<Handle<T>>,
+    pub low: Option<f64>,
+    pub high: Option<f64>,
+    pub mid: Option<f64>,
+    pub end: Option<Handle<KLineUnit>>,
+    pub end_bi: Option<Handle<T>>,
+    pub peak_high: f64,
+    pub peak_low: f64,
+    /// 进中枢那一笔
+    pub bi_in: Option<Handle<T>>,
+    /// 出中枢那一笔
+    pub bi_out: Option<Handle<T>>,
+    /// begin_bi~end_bi之间的笔，在update_zs_in_seg函数中更新
+    pub bi_lst: Vec<Handle<T>>,
+}
+
 impl<T: LineTrait> ZS<T> {
-    pub fn combine(&mut self, zs2: &ZS<T>, combine_mode: &str) -> bool {
+    pub fn new(lst: Option<&[Handle<T>]>, is_sure: bool) -> Self {
+        let mut zs = Self {
+            is_sure,
+            sub_zs_lst: Vec::new(),
+            begin: None,
+            begin_bi: None,
+            low: None,
+            high: None,
+            mid: None,
+            end: None,
+            end_bi: None,
+            peak_high: f64::NEG_INFINITY,
+            peak_low: f64::INFINITY,
+            bi_in: None,
+            bi_out: None,
+            bi_lst: Vec::new(),
+        };
+        if let Some(lst) = lst {
+            zs.begin = Some(lst[0].borrow().get_begin_klu());
+            zs.begin_bi = Some(lst[0].clone());
+            zs.update_zs_range(lst);
+            for item in lst {
+                zs.update_zs_end(item);
+            }
+        }
+        zs
+    }
+
+    pub fn update_zs_range(&mut self, lst: &[Handle<T>]) {
+        let low = lst.iter().map(|bi| bi.borrow()._low()).fold(f64::NEG_INFINITY, f64::max);
+        let high = lst.iter().map(|bi| bi.borrow()._high()).fold(f64::INFINITY, f64::min);
+        self.low = Some(low);
+        self.high = Some(high);
+        // 中枢的中点
+        self.mid = Some((low + high) / 2.0);
+    }
+
+    pub fn is_one_bi_zs(&self) -> bool {
+        let end_bi = self.end_bi.as_ref().expect("end_bi should not be None");
+        self.begin_bi.as_ref().map(|bi| bi.borrow().idx()) == Some(end_bi.borrow().idx())
+    }
+
+    pub fn update_zs_end(&mut self, item: &Handle<T>) {
+        let item_ref = item.borrow();
+        self.end = Some(item_ref.get_end_klu());
+        self.end_bi = Some(item.clone());
+        if item_ref._low() < self.peak_low {
+            self.peak_low = item_ref._low();
+        }
+        if item_ref._high() > self.peak_high {
+            self.peak_high = item_ref._high();
+        }
+    }
+
+    /// 是否由多个中枢合并而来
+    pub fn is_combined(&self) -> bool {
+        !self.sub_zs_lst.is_empty()
+    }
+
+    /// 撤销合并，返回参与合并的原始中枢，用于展示；未合并的中枢返回自身
+    pub fn uncombine(&self) -> Vec<ZS<T>> {
+        if self.is_combined() {
+            self.sub_zs_lst.clone()
+        } else {
+            vec![self.make_copy()]
+        }
+    }
+
+    pub fn combine(&mut self, zs2: &ZS<T>, combine_mode: ZSCombineMode) -> bool {
         if zs2.is_one_bi_zs() {
             return false;
         }
@@ -11,7 +114,7 @@ impl<T: LineTrait> ZS<T> {
         }
 
         match combine_mode {
-            "zs" => {
+            ZSCombineMode::Zs => {
                 if !has_overlap(
                     self.low.unwrap(),
                     self.high.unwrap(),
@@ -24,24 +127,20 @@ impl<T: LineTrait> ZS<T> {
                 self.do_combine(zs2);
                 true
             }
-            "peak" => {
-                if has_overlap(
+            ZSCombineMode::Peak => {
+                // 笔的极值区间严格重叠才合并，仅端点相接不算
+                if !has_overlap(
                     self.peak_low,
                     self.peak_high,
                     zs2.peak_low,
                     zs2.peak_high,
-                    true,
+                    false,
                 ) {
-                    self.do_combine(zs2);
-                    true
-                } else {
-                    false
+                    return false;
                 }
+                self.do_combine(zs2);
+                true
             }
-            _ => Err(ChanError::new(
-                format!("{} is unsupport zs combine mode", combine_mode),
-                ErrCode::ParaError,
-            ))?,
         }
     }
 
@@ -49,7 +148,8 @@ impl<T: LineTrait> ZS<T> {
         if self.sub_zs_lst.is_empty() {
             self.sub_zs_lst.push(self.make_copy());
         }
-        self.sub_zs_lst.push(zs2.make_copy());
+        // zs2本身也可能是合并过的中枢，记录其原始中枢以便完整撤销
+        self.sub_zs_lst.extend(zs2.uncombine());
 
         self.low = Some(self.low.unwrap().min(zs2.low.unwrap()));
         self.high = Some(self.high.unwrap().max(zs2.high.unwrap()));
@@ -94,35 +194,32 @@ impl<T: LineTrait> ZS<T> {
         }
     }
 
+    /// 出中枢笔相对进中枢笔是否背驰，返回(是否背驰, 背驰比例)
     pub fn is_divergence(
         &self,
         config: &PointConfig,
         out_bi: Option<&Handle<T>>,
-    ) -> (bool, Option<f64>) {
+    ) -> Result<(bool, Option<f64>), ChanException> {
         if !self.end_bi_break(out_bi) {
-            return (false, None);
+            // 最后一笔必须突破中枢
+            return Ok((false, None));
         }
 
         let in_metric = self
             .get_bi_in()
             .borrow()
-            .cal_macd_metric(&config.macd_algo, false);
-        let out_metric = if let Some(out) = out_bi {
-            out.borrow().cal_macd_metric(&config.macd_algo, true)
-        } else {
-            self.get_bi_out()
-                .borrow()
-                .cal_macd_metric(&config.macd_algo, true)
-        };
+            .cal_macd_metric(config.macd_algo, false)?;
+        let out_metric = out_bi
+            .unwrap_or_else(|| self.get_bi_out())
+            .borrow()
+            .cal_macd_metric(config.macd_algo, true)?;
 
         let ratio = out_metric / in_metric;
         if config.divergence_rate > 100.0 {
-            (true, Some(ratio))
+            // 保送
+            Ok((true, Some(ratio)))
         } else {
-            (
-                out_metric <= config.divergence_rate * in_metric,
-                Some(ratio),
-            )
+            Ok((out_metric <= config.divergence_rate * in_metric, Some(ratio)))
         }
     }
 
@@ -157,22 +254,20 @@ impl<T: LineTrait> ZS<T> {
             || (end_bi.is_up() && end_bi._high() > self.high.unwrap())
     }
 
+    /// 返回 (出中枢笔是否最低/高点，bi_out与中枢里面尾部最接近它的差距比例)
     pub fn out_bi_is_peak(&self, end_bi_idx: usize) -> (bool, Option<f64>) {
-        if self.bi_lst.is_empty() || self.bi_out.is_none() {
-            return (false, None);
-        }
+        assert!(!self.bi_lst.is_empty());
+        let bi_out = match &self.bi_out {
+            Some(bi_out) => bi_out.borrow(),
+            None => return (false, None),
+        };
 
-        let bi_out = self.bi_out.as_ref().unwrap();
         let mut peak_rate = f64::INFINITY;
-
         for bi in &self.bi_lst {
-            if bi.borrow().idx() > end_bi_idx {
+            let bi = bi.borrow();
+            if bi.idx() > end_bi_idx {
                 break;
             }
-
-            let bi = bi.borrow();
-            let bi_out = bi_out.borrow();
-
             if (bi_out.is_down() && bi._low() < bi_out._low())
                 || (bi_out.is_up() && bi._high() > bi_out._high())
             {
@@ -237,3 +332,74 @@ impl<T: LineTrait> std::fmt::Display for ZS<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::test_util::{bi_handles, build_bi_lst, MockBi};
+    use crate::zs::zs_config::ZSConfig;
+
+    const VALS: [f64; 11] = [10.0, 20.0, 12.0, 18.0, 14.0, 22.0, 16.0, 30.0, 25.0, 20.0, 28.0];
+
+    fn zs_of(bi_lst: &[Handle<MockBi>], begin: usize, end: usize) -> ZS<MockBi> {
+        ZS::new(Some(&bi_lst[begin..=end]), true)
+    }
+
+    #[test]
+    fn test_combine_keeps_sub_zs() {
+        let (_klus, bis) = build_bi_lst(&VALS);
+        let bi_lst = bi_handles(&bis);
+        let mut zs = zs_of(&bi_lst, 0, 1);
+        assert_eq!(zs.uncombine().len(), 1);
+        assert!(zs.combine(&zs_of(&bi_lst, 2, 3), ZSCombineMode::Zs));
+        assert_eq!(zs.to_string(), "0->3(0->1,2->3)");
+        assert_eq!((zs.low, zs.high), (Some(12.0), Some(20.0)));
+        let ranges: Vec<_> = zs.uncombine().iter().map(|sub_zs| (sub_zs.low, sub_zs.high)).collect();
+        assert_eq!(ranges, vec![(Some(12.0), Some(20.0)), (Some(14.0), Some(18.0))]);
+    }
+
+    #[test]
+    fn test_combine_flattens_combined_zs2() {
+        let (_klus, bis) = build_bi_lst(&VALS);
+        let bi_lst = bi_handles(&bis);
+        let mut zs2 = zs_of(&bi_lst, 2, 3);
+        assert!(zs2.combine(&zs_of(&bi_lst, 4, 5), ZSCombineMode::Zs));
+        let mut zs = zs_of(&bi_lst, 0, 1);
+        assert!(zs.combine(&zs2, ZSCombineMode::Zs));
+        assert_eq!(zs.to_string(), "0->5(0->1,2->3,4->5)");
+        assert_eq!(zs.uncombine().len(), 3);
+    }
+
+    #[test]
+    fn test_combine_mode_zs_vs_peak() {
+        let (_klus, bis) = build_bi_lst(&VALS);
+        let bi_lst = bi_handles(&bis);
+        let zs = zs_of(&bi_lst, 0, 1);
+        // 中枢区间[12, 20]与[20, 25]端点相接，笔的极值区间[10, 20]与[20, 28]也只是端点相接
+        let touch_zs = zs_of(&bi_lst, 8, 9);
+        assert!(zs.clone().combine(&touch_zs, ZSCombineMode::Zs));
+        assert!(!zs.clone().combine(&touch_zs, ZSCombineMode::Peak));
+        // 中枢区间[12, 20]与[25, 30]不重叠，但极值区间[10, 20]与[16, 30]重叠
+        let peak_zs = zs_of(&bi_lst, 6, 7);
+        assert!(!zs.clone().combine(&peak_zs, ZSCombineMode::Zs));
+        let mut combined = zs.clone();
+        assert!(combined.combine(&peak_zs, ZSCombineMode::Peak));
+        assert_eq!((combined.peak_low, combined.peak_high), (10.0, 30.0));
+    }
+
+    #[test]
+    fn test_one_bi_zs_never_combined() {
+        let (_klus, bis) = build_bi_lst(&VALS);
+        let bi_lst = bi_handles(&bis);
+        let mut zs = zs_of(&bi_lst, 0, 1);
+        assert!(!zs.combine(&zs_of(&bi_lst, 2, 2), ZSCombineMode::Zs));
+        assert!(!zs.is_combined());
+    }
+
+    #[test]
+    fn test_combine_mode_from_config() {
+        let config = ZSConfig::new(None, Some("peak"), None, None).unwrap();
+        assert_eq!(config.zs_combine_mode, ZSCombineMode::Peak);
+        assert!(ZSConfig::new(None, Some("unknown"), None, None).is_err());
+    }
+}
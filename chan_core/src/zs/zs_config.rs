@@ -1,9 +1,29 @@
 use strum_macros::{Display, EnumString};
+use crate::common::chan_exception::{ChanException, ErrCode};
 
-#[derive(Debug, Clone, Display, EnumString)]
+/// 中枢算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
 pub enum ZSAlgo {
+    /// 段内中枢，中枢不跨越线段
     #[strum(serialize = "normal")]
     Normal,
+    /// 跨段中枢，中枢可以跨越线段
+    #[strum(serialize = "over_seg")]
+    OverSeg,
+    /// 已确定的线段用段内中枢，未确定的线段用跨段中枢
+    #[strum(serialize = "auto")]
+    Auto,
+}
+
+/// 中枢合并方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+pub enum ZSCombineMode {
+    /// 中枢区间[low, high]有重叠即合并
+    #[strum(serialize = "zs")]
+    Zs,
+    /// 中枢内笔的极值区间[peak_low, peak_high]有重叠即合并
+    #[strum(serialize = "peak")]
+    Peak,
 }
 
 #[derive(Debug, Clone)]
@@ -11,8 +31,8 @@ pub struct ZSConfig {
     /// Whether combination is needed
     pub need_combine: bool,
     
-    /// Mode for ZS combination ("zs" or "peak")
-    pub zs_combine_mode: String,
+    /// Mode for ZS combination
+    pub zs_combine_mode: ZSCombineMode,
     
     /// Whether to allow single bi ZS
     pub one_bi_zs: bool,
@@ -25,7 +45,7 @@ impl Default for ZSConfig {
     fn default() -> Self {
         Self {
             need_combine: true,
-            zs_combine_mode: "zs".to_string(),
+            zs_combine_mode: ZSCombineMode::Zs,
             one_bi_zs: false,
             zs_algo: ZSAlgo::Normal,
         }
@@ -38,14 +58,35 @@ impl ZSConfig {
         need_combine: Option<bool>,
         zs_combine_mode: Option<&str>,
         one_bi_zs: Option<bool>,
-        zs_algo: Option<ZSAlgo>,
-    ) -> Self {
+        zs_algo: Option<&str>,
+    ) -> Result<Self, ChanException> {
         let default = Self::default();
-        Self {
+        let zs_combine_mode = match zs_combine_mode {
+            Some(mode) => mode.parse::<ZSCombineMode>().map_err(|_| ChanException::new(
+                format!("{} is unsupport zs combine mode", mode),
+                ErrCode::ParaError,
+            ))?,
+            None => default.zs_combine_mode,
+        };
+        let zs_algo = match zs_algo {
+            Some(algo) => algo.parse::<ZSAlgo>().map_err(|_| ChanException::new(
+                format!("unknown zs_algo={}", algo),
+                ErrCode::ParaError,
+            ))?,
+            None => default.zs_algo,
+        };
+        if zs_algo == ZSAlgo::OverSeg && one_bi_zs.unwrap_or(default.one_bi_zs) {
+            return Err(ChanException::new(
+                "one_bi_zs is not supported when zs_algo=over_seg",
+                ErrCode::ParaError,
+            ));
+        }
+
+        Ok(Self {
             need_combine: need_combine.unwrap_or(default.need_combine),
-            zs_combine_mode: zs_combine_mode.unwrap_or(&default.zs_combine_mode).to_string(),
+            zs_combine_mode,
             one_bi_zs: one_bi_zs.unwrap_or(default.one_bi_zs),
-            zs_algo: zs_algo.unwrap_or(default.zs_algo),
-        }
+            zs_algo,
+        })
     }
 } 
\ No newline at end of file
@@ -1,28 +1,92 @@
+use std::ops::{Index, IndexMut};
+use crate::common::enums::BiDir;
+use crate::common::handle::Handle;
+use crate::common::utils::revert_bi_dir;
+use crate::seg::seg::Seg;
+use crate::seg::seg_list_comm::SegListComm;
+use crate::traits::line_trait::LineTrait;
+use super::zs::ZS;
+use super::zs_config::{ZSAlgo, ZSConfig};
+
+/// 中枢列表，笔中枢和线段中枢共用
+#[derive(Debug, Clone)]
+pub struct ZSList<T: LineTrait> {
+    pub zs_lst: Vec<ZS<T>>,
+    pub config: ZSConfig,
+    pub free_item_lst: Vec<Handle<T>>,
+    pub last_sure_pos: isize,
+}
+
 impl<T: LineTrait> ZSList<T> {
+    pub fn new(zs_config: ZSConfig) -> Self {
+        Self {
+            zs_lst: Vec::new(),
+            config: zs_config,
+            free_item_lst: Vec::new(),
+            last_sure_pos: -1,
+        }
+    }
+
+    pub fn update_last_pos(&mut self, seg_list: &SegListComm<T>) {
+        self.last_sure_pos = seg_list
+            .iter()
+            .rev()
+            .find(|seg| seg.borrow().is_sure)
+            .map_or(-1, |seg| seg.borrow().start_bi.borrow().idx() as isize);
+    }
+
+    pub fn seg_need_cal(&self, seg: &Handle<Seg<T>>) -> bool {
+        seg.borrow().start_bi.borrow().idx() as isize >= self.last_sure_pos
+    }
+
+    pub fn add_to_free_lst(&mut self, item: Handle<T>, is_sure: bool, over_seg: bool) {
+        if let Some(last) = self.free_item_lst.last() {
+            if item.borrow().idx() == last.borrow().idx() {
+                // 防止笔新高或新低的更新带来bug
+                self.free_item_lst.pop();
+            }
+        }
+        self.free_item_lst.push(item);
+        // 可能是一笔中枢
+        if let Some(zs) = self.try_construct_zs(&self.free_item_lst, is_sure, over_seg) {
+            // 禁止第一笔就是中枢的起点
+            if zs.begin_bi.as_ref().map_or(false, |bi| bi.borrow().idx() > 0) {
+                self.zs_lst.push(zs);
+                self.clear_free_lst();
+                self.try_combine();
+            }
+        }
+    }
+
+    pub fn clear_free_lst(&mut self) {
+        self.free_item_lst.clear();
+    }
+
     pub fn update(&mut self, bi: Handle<T>, is_sure: bool) {
         if self.free_item_lst.is_empty() && self.try_add_to_end(&bi) {
+            // zs_combine_mode=peak合并模式下会触发生效，=zs合并一定无效返回
             self.try_combine();
             return;
         }
-        self.add_to_free_lst(bi, is_sure, "normal");
+        self.add_to_free_lst(bi, is_sure, false);
     }
 
     pub fn try_add_to_end(&mut self, bi: &Handle<T>) -> bool {
-        if self.zs_lst.is_empty() {
-            false
-        } else {
-            self.zs_lst.last_mut().unwrap().try_add_to_end(bi)
+        match self.zs_lst.last_mut() {
+            Some(zs) => zs.try_add_to_end(bi),
+            None => false,
         }
     }
 
     pub fn add_zs_from_bi_range(&mut self, seg_bi_lst: &[Handle<T>], seg_dir: BiDir, seg_is_sure: bool) {
         let mut deal_bi_cnt = 0;
         for bi in seg_bi_lst {
-            if bi.borrow().dir() == seg_dir {
+            if bi.borrow().is_up() == (seg_dir == BiDir::Up) {
                 continue;
             }
+            // 防止try_add_to_end执行到上一个线段的中枢里面去
             if deal_bi_cnt < 1 {
-                self.add_to_free_lst(bi.clone(), seg_is_sure, "normal");
+                self.add_to_free_lst(bi.clone(), seg_is_sure, false);
                 deal_bi_cnt += 1;
             } else {
                 self.update(bi.clone(), seg_is_sure);
@@ -30,33 +94,31 @@ impl<T: LineTrait> ZSList<T> {
         }
     }
 
-    pub fn try_construct_zs(&self, lst: &[Handle<T>], is_sure: bool, zs_algo: &str) -> Option<ZS<T>> {
-        let lst = match zs_algo {
-            "normal" => {
-                if !self.config.one_bi_zs {
-                    if lst.len() == 1 {
-                        return None;
-                    }
-                    &lst[lst.len()-2..]
-                } else {
-                    lst
-                }
-            },
-            "over_seg" => {
-                if lst.len() < 3 {
-                    return None;
-                }
-                let lst = &lst[lst.len()-3..];
-                if lst[0].borrow().dir() == lst[0].borrow().parent_seg().unwrap().borrow().dir {
-                    return None;
-                }
-                &lst[1..]
-            },
-            _ => return None,
+    /// 用free_item_lst尾部的笔尝试构造中枢
+    ///
+    /// over_seg为false时按段内中枢取最后两笔（one_bi_zs时全部），为true时按跨段中枢取最后三笔；
+    /// auto在cal_bi_zs中按线段选择其中一种，所以这里只区分这两种
+    pub fn try_construct_zs(&self, lst: &[Handle<T>], is_sure: bool, over_seg: bool) -> Option<ZS<T>> {
+        let lst = if over_seg {
+            if lst.len() < 3 {
+                return None;
+            }
+            let lst = &lst[lst.len() - 3..];
+            let first = lst[0].borrow();
+            if first.parent_seg_dir().map_or(false, |dir| first.is_up() == (dir == BiDir::Up)) {
+                return None;
+            }
+            lst
+        } else if self.config.one_bi_zs {
+            lst
+        } else if lst.len() == 1 {
+            return None;
+        } else {
+            &lst[lst.len() - 2..]
         };
 
-        let min_high = lst.iter().map(|item| item.borrow()._high()).min_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
-        let max_low = lst.iter().map(|item| item.borrow()._low()).max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
+        let min_high = lst.iter().map(|item| item.borrow()._high()).fold(f64::INFINITY, f64::min);
+        let max_low = lst.iter().map(|item| item.borrow()._low()).fold(f64::NEG_INFINITY, f64::max);
 
         if min_high > max_low {
             Some(ZS::new(Some(lst), is_sure))
@@ -65,6 +127,104 @@ impl<T: LineTrait> ZSList<T> {
         }
     }
 
+    /// 根据zs_algo重新计算未确定部分的中枢
+    ///
+    /// normal只在线段内部找中枢；over_seg允许中枢跨越线段；
+    /// auto对已确定的线段用normal，从第一个未确定的线段开始用over_seg
+    pub fn cal_bi_zs(&mut self, bi_lst: &[Handle<T>], seg_lst: &SegListComm<T>) {
+        while self
+            .zs_lst
+            .last()
+            .and_then(|zs| zs.begin_bi.as_ref())
+            .map_or(false, |bi| bi.borrow().idx() as isize >= self.last_sure_pos)
+        {
+            self.zs_lst.pop();
+        }
+
+        match self.config.zs_algo {
+            ZSAlgo::Normal => {
+                for seg in seg_lst.iter() {
+                    if !self.seg_need_cal(seg) {
+                        continue;
+                    }
+                    self.clear_free_lst();
+                    let seg = seg.borrow();
+                    let seg_bi_lst = &bi_lst[seg.start_bi.borrow().idx()..=seg.end_bi.borrow().idx()];
+                    self.add_zs_from_bi_range(seg_bi_lst, seg.dir, seg.is_sure);
+                }
+
+                // 处理未生成新线段的部分
+                if let Some(last_seg) = seg_lst.last() {
+                    self.clear_free_lst();
+                    let last_seg = last_seg.borrow();
+                    let left_bi_lst = &bi_lst[last_seg.end_bi.borrow().idx() + 1..];
+                    self.add_zs_from_bi_range(left_bi_lst, revert_bi_dir(last_seg.dir), false);
+                }
+            }
+            ZSAlgo::OverSeg => {
+                assert!(!self.config.one_bi_zs);
+                self.clear_free_lst();
+                let begin_bi_idx = self
+                    .zs_lst
+                    .last()
+                    .and_then(|zs| zs.end_bi.as_ref())
+                    .map_or(0, |bi| bi.borrow().idx() + 1);
+                for bi in bi_lst.iter().skip(begin_bi_idx) {
+                    self.update_overseg_zs(bi);
+                }
+            }
+            ZSAlgo::Auto => {
+                let mut sure_seg_appear = false;
+                let exist_sure_seg = seg_lst.exist_sure_seg();
+                for seg in seg_lst.iter() {
+                    let seg_ref = seg.borrow();
+                    if seg_ref.is_sure {
+                        sure_seg_appear = true;
+                    }
+                    if !self.seg_need_cal(seg) {
+                        continue;
+                    }
+                    self.clear_free_lst();
+                    let start_idx = seg_ref.start_bi.borrow().idx();
+                    if seg_ref.is_sure || (!sure_seg_appear && exist_sure_seg) {
+                        let seg_bi_lst = &bi_lst[start_idx..=seg_ref.end_bi.borrow().idx()];
+                        self.add_zs_from_bi_range(seg_bi_lst, seg_ref.dir, seg_ref.is_sure);
+                    } else {
+                        for bi in &bi_lst[start_idx..] {
+                            self.update_overseg_zs(bi);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        self.update_last_pos(seg_lst);
+    }
+
+    pub fn update_overseg_zs(&mut self, bi: &Handle<T>) {
+        let bi_idx = bi.borrow().idx();
+        if self.free_item_lst.is_empty() {
+            if let Some(last_zs) = self.zs_lst.last_mut() {
+                let next_bi = match bi.next() {
+                    Some(next_bi) => next_bi,
+                    None => return,
+                };
+                let last_end_idx = last_zs.end_bi.as_ref().map_or(0, |end_bi| end_bi.borrow().idx());
+                if bi_idx <= last_end_idx + 1 && last_zs.in_range(&next_bi) && last_zs.try_add_to_end(bi) {
+                    return;
+                }
+            }
+            if let Some(last_zs) = self.zs_lst.last() {
+                let last_end_idx = last_zs.end_bi.as_ref().map_or(0, |end_bi| end_bi.borrow().idx());
+                if last_zs.in_range(bi) && bi_idx <= last_end_idx + 1 {
+                    return;
+                }
+            }
+        }
+        let is_sure = bi.borrow().is_sure();
+        self.add_to_free_lst(bi.clone(), is_sure, true);
+    }
+
     pub fn try_combine(&mut self) {
         if !self.config.need_combine {
             return;
@@ -75,7 +235,7 @@ impl<T: LineTrait> ZSList<T> {
                 let (first, second) = self.zs_lst.split_at_mut(last_idx);
                 first.last_mut().unwrap().combine(
                     &second[0],
-                    &self.config.zs_combine_mode
+                    self.config.zs_combine_mode
                 )
             };
             
@@ -86,6 +246,19 @@ impl<T: LineTrait> ZSList<T> {
             }
         }
     }
+
+    /// 撤销所有合并后的中枢列表，用于展示合并前的原始中枢
+    pub fn uncombined_zs_lst(&self) -> Vec<ZS<T>> {
+        self.zs_lst.iter().flat_map(|zs| zs.uncombine()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.zs_lst.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zs_lst.is_empty()
+    }
 }
 
 impl<T: LineTrait> Index<usize> for ZSList<T> {
@@ -119,4 +292,80 @@ impl<T: LineTrait> ZSList<T> {
     pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, ZS<T>> {
         self.zs_lst.iter_mut()
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::test_util::{bi_handles, build_bi_lst, build_seg_list, MockBi};
+
+    /// 笔1、笔3、笔5两两重叠，笔2、笔4也与它们重叠
+    const VALS: [f64; 8] = [100.0, 60.0, 80.0, 65.0, 78.0, 66.0, 79.0, 55.0];
+
+    /// 每个中枢的(起始笔, 结束笔, low, high)
+    fn zs_ranges(zs_list: &ZSList<MockBi>) -> Vec<(usize, usize, f64, f64)> {
+        zs_list
+            .iter()
+            .map(|zs| {
+                (
+                    zs.begin_bi.as_ref().unwrap().borrow().idx(),
+                    zs.end_bi.as_ref().unwrap().borrow().idx(),
+                    zs.low.unwrap(),
+                    zs.high.unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn cal_zs(zs_algo: &str, segs: &[(usize, usize, bool)]) -> Vec<(usize, usize, f64, f64)> {
+        let (_klus, bis) = build_bi_lst(&VALS);
+        let bi_lst = bi_handles(&bis);
+        let (_segs, seg_list) = build_seg_list(&bi_lst, segs);
+        let mut zs_list = ZSList::new(ZSConfig::new(None, None, None, Some(zs_algo)).unwrap());
+        zs_list.cal_bi_zs(&bi_lst, &seg_list);
+        zs_ranges(&zs_list)
+    }
+
+    #[test]
+    fn test_normal_zs_inside_one_seg() {
+        assert_eq!(cal_zs("normal", &[(0, 6, true)]), vec![(1, 5, 65.0, 78.0)]);
+    }
+
+    #[test]
+    fn test_normal_zs_does_not_cross_segs() {
+        assert!(cal_zs("normal", &[(0, 2, true), (3, 5, true)]).is_empty());
+    }
+
+    #[test]
+    fn test_over_seg_zs_crosses_segs() {
+        assert_eq!(cal_zs("over_seg", &[(0, 2, true), (3, 5, true)]), vec![(1, 5, 65.0, 78.0)]);
+    }
+
+    #[test]
+    fn test_over_seg_rejects_first_bi_in_seg_dir() {
+        // 笔1与线段同为向下，不能作为跨段中枢的第一笔，中枢从笔2开始
+        let vals = [50.0, 100.0, 60.0, 80.0, 65.0, 78.0, 66.0, 79.0, 55.0];
+        let (_klus, bis) = build_bi_lst(&vals);
+        let bi_lst = bi_handles(&bis);
+        let (_segs, seg_list) = build_seg_list(&bi_lst, &[(1, 7, true)]);
+        let mut zs_list = ZSList::new(ZSConfig::new(None, None, None, Some("over_seg")).unwrap());
+        assert!(zs_list.try_construct_zs(&bi_lst[1..4], true, true).is_none());
+        // 笔1、2、3本身是重叠的
+        assert!(zs_list.try_construct_zs(&bi_lst[1..4], true, false).is_some());
+
+        zs_list.cal_bi_zs(&bi_lst, &seg_list);
+        assert_eq!(zs_ranges(&zs_list), vec![(2, 6, 65.0, 78.0)]);
+    }
+
+    #[test]
+    fn test_auto_uses_over_seg_from_first_unsure_seg() {
+        // 笔3与线段1同向，跨段中枢从笔4开始
+        assert_eq!(cal_zs("auto", &[(0, 2, true), (3, 5, false)]), vec![(4, 6, 66.0, 78.0)]);
+        assert!(cal_zs("auto", &[(0, 2, true), (3, 5, true)]).is_empty());
+    }
+
+    #[test]
+    fn test_zs_config_rejects_invalid_algo() {
+        assert!(ZSConfig::new(None, None, None, Some("unknown")).is_err());
+        assert!(ZSConfig::new(None, None, Some(true), Some("over_seg")).is_err());
+    }
+}